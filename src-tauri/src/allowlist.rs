@@ -0,0 +1,185 @@
+use anyhow::Result;
+use ipnet::Ipv4Net;
+use std::net::Ipv4Addr;
+
+// Ranges that never leave the local network segment (loopback, RFC 1918,
+// link-local, CGNAT and multicast); anything else is treated as public
+const LOCAL_RANGES: &[&str] = &[
+    "127.0.0.0/8",
+    "10.0.0.0/8",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "169.254.0.0/16",
+    "100.64.0.0/10",
+    "224.0.0.0/4",
+];
+
+// iptables' multiport match takes at most 15 ports (a range counts as two)
+const MAX_MULTIPORT: usize = 15;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    Any,
+    Tcp,
+    Udp,
+}
+
+/// Destination that bypasses Tor while the shield is up
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AllowRule {
+    /// IPv4 network or single address
+    pub cidr: String,
+    pub protocol: Protocol,
+    /// Comma separated ports or "first:last" ranges, empty for all
+    pub ports: String,
+    pub description: String,
+}
+
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Allowlist {
+    pub rules: Vec<AllowRule>,
+}
+
+impl AllowRule {
+    fn new(cidr: &str, description: &str) -> Self {
+        Self {
+            cidr: cidr.to_string(),
+            description: description.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn network(&self) -> Result<Ipv4Net> {
+        let cidr = self.cidr.trim();
+        let net = if cidr.contains('/') {
+            cidr.parse::<Ipv4Net>().ok()
+        } else {
+            cidr.parse::<Ipv4Addr>().ok().map(Ipv4Net::from)
+        };
+
+        net.map(|n| n.trunc())
+            .ok_or_else(|| anyhow::anyhow!("Invalid IPv4 network \"{}\"", self.cidr))
+    }
+
+    pub fn is_public(&self) -> Result<bool> {
+        let net = self.network()?;
+        Ok(!local_ranges().iter().any(|local| local.contains(&net)))
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.network()?;
+        if self.ports.trim().is_empty() {
+            return Ok(());
+        }
+        if self.protocol == Protocol::Any {
+            return Err(anyhow::anyhow!(
+                "{}: ports need a protocol (tcp or udp)",
+                self.cidr
+            ));
+        }
+
+        let mut count = 0;
+        for part in self.ports.split(',') {
+            let bounds: Vec<&str> = part.trim().split(':').collect();
+            let parsed: Vec<u16> = bounds
+                .iter()
+                .filter_map(|p| p.parse().ok())
+                .filter(|p| *p != 0)
+                .collect();
+            let valid = match parsed.as_slice() {
+                [_] => bounds.len() == 1,
+                [first, last] => bounds.len() == 2 && first <= last,
+                _ => false,
+            };
+            if !valid {
+                return Err(anyhow::anyhow!("{}: invalid port \"{}\"", self.cidr, part));
+            }
+            count += parsed.len();
+        }
+
+        if count > MAX_MULTIPORT {
+            return Err(anyhow::anyhow!(
+                "{}: at most {} ports per entry",
+                self.cidr,
+                MAX_MULTIPORT
+            ));
+        }
+        Ok(())
+    }
+
+    /// iptables match arguments for this entry
+    fn matcher(&self) -> String {
+        let net = self
+            .network()
+            .map(|n| n.to_string())
+            .unwrap_or_else(|_| self.cidr.clone());
+        let mut args = format!("-d {}", net);
+
+        match self.protocol {
+            Protocol::Any => {}
+            Protocol::Tcp => args.push_str(" -p tcp"),
+            Protocol::Udp => args.push_str(" -p udp"),
+        }
+        let ports: String = self.ports.chars().filter(|c| !c.is_whitespace()).collect();
+        if !ports.is_empty() {
+            args.push_str(&format!(" -m multiport --dports {}", ports));
+        }
+        args
+    }
+}
+
+impl Allowlist {
+    /// The usual home/office ranges: printers, NAS, SSH to local hosts
+    pub fn local_networks() -> Vec<AllowRule> {
+        vec![
+            AllowRule::new("10.0.0.0/8", "Private network (10/8)"),
+            AllowRule::new("172.16.0.0/12", "Private network (172.16/12)"),
+            AllowRule::new("192.168.0.0/16", "Private network (192.168/16)"),
+            AllowRule::new("169.254.0.0/16", "Link-local"),
+            AllowRule::new("224.0.0.0/4", "Multicast (mDNS, SSDP)"),
+        ]
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.rules.iter().try_for_each(AllowRule::validate)
+    }
+
+    /// Warnings for entries that would send traffic to the internet
+    /// without going through Tor
+    pub fn warnings(&self) -> Result<Vec<String>> {
+        let mut warnings = Vec::new();
+        for rule in &self.rules {
+            if rule.is_public()? {
+                warnings.push(format!(
+                    "{} is a public range: traffic to it will bypass Tor and reveal your IP",
+                    rule.network()?
+                ));
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// nat rules keeping allowlisted traffic away from the TransPort
+    pub fn nat_rules(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .map(|r| format!("iptables -t nat -A OUTPUT {} -j RETURN", r.matcher()))
+            .collect()
+    }
+
+    /// filter rules letting allowlisted traffic through the killswitch
+    pub fn filter_rules(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .map(|r| format!("iptables -A OUTPUT {} -j ACCEPT", r.matcher()))
+            .collect()
+    }
+}
+
+fn local_ranges() -> Vec<Ipv4Net> {
+    LOCAL_RANGES.iter().filter_map(|r| r.parse().ok()).collect()
+}
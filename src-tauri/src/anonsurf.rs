@@ -1,21 +1,94 @@
-use crate::iptables::IptablesManager;
+use crate::apps;
+use crate::bootguard;
+use crate::config::{AegisConfig, ConfigState};
+use crate::dns;
+use crate::iptables::{IptablesManager, Ruleset, ShieldMode};
+use crate::netns;
+use crate::onion;
+use crate::portal::PortalState;
+use crate::rotation;
+use crate::session::{OrphanedSession, ShieldSession};
+use crate::timesync;
+use crate::torctl::TorControl;
+use crate::torrc;
 use anyhow::{Result, Context};
+use std::net::{IpAddr, SocketAddr};
 use std::process::Command;
 use std::sync::Mutex;
 
 // Constants
-const TOR_UID: &str = "debian-tor"; // Default on Debian/Ubuntu
+pub const TOR_UID: &str = "debian-tor"; // Default on Debian/Ubuntu
 const TRANS_PORT: &str = "9040";
 const DNS_PORT: &str = "5353";
+// Names are resolved by Tor too (socks5h), so nothing leaves in the clear
+// whatever the shield mode, allowlist or exemptions
+pub const TOR_SOCKS_PROXY: &str = "socks5h://127.0.0.1:9050";
 
 pub struct AnonsurfState {
     pub is_active: Mutex<bool>,
+    /// Public IP before the shield went up, for the leak test. Only
+    /// recorded on request and never written to disk.
+    pub real_ip: Mutex<Option<String>>,
+    /// Held while the shield starts or stops, so the start button and a
+    /// network profile can't run `start` side by side
+    pub transition: Mutex<()>,
 }
 
 impl AnonsurfState {
     pub fn new() -> Self {
         Self {
             is_active: Mutex::new(false),
+            real_ip: Mutex::new(None),
+            transition: Mutex::new(()),
+        }
+    }
+}
+
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ShieldSettings {
+    pub mode: ShieldMode,
+    /// Extra SocksPorts let through in killswitch mode, e.g. Tor on a
+    /// LAN gateway. SocksPorts on loopback are always reachable.
+    pub socks_ports: Vec<SocketAddr>,
+}
+
+impl ShieldSettings {
+    pub fn validate(&self) -> Result<()> {
+        for socks in &self.socks_ports {
+            let local = match socks.ip() {
+                IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+                IpAddr::V6(_) => false,
+            };
+            if !local || socks.port() == 0 {
+                return Err(anyhow::anyhow!("{} is not a local IPv4 SocksPort", socks));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Options for tearing down the shield
+#[derive(Default, serde::Deserialize)]
+pub struct StopOptions {
+    /// Stop Tor even if it was running before the shield started
+    #[serde(default)]
+    pub stop_tor: bool,
+}
+
+/// What `Anonsurf::stop` managed to undo, step by step
+#[derive(Default, serde::Serialize)]
+pub struct TeardownReport {
+    pub undone: Vec<String>,
+    pub failed: Vec<String>,
+    pub killswitch_active: bool,
+}
+
+impl TeardownReport {
+    fn record(&mut self, step: &str, result: Result<()>) {
+        match result {
+            Ok(_) => self.undone.push(step.to_string()),
+            Err(e) => self.failed.push(format!("{}: {}", step, e)),
         }
     }
 }
@@ -26,34 +99,205 @@ impl Anonsurf {
     pub fn start(app: tauri::AppHandle) -> Result<()> {
         Self::emit_log(&app, "Starting Aegis Shield...");
 
-        // 1. Configure Tor if needed
+        // Remember Tor's state so stop() can put it back
+        let tor_was_running = Self::is_tor_running();
+
+        // 1. Make sure an upstream proxy works before we depend on it
+        let config = Self::config(&app);
+        if config.proxy.enabled {
+            Self::emit_log(&app, "Checking upstream proxy...");
+            config
+                .proxy
+                .check_connectivity(&config.bridges)
+                .context("Upstream proxy check failed, shield not started")?;
+        }
+        apps::ensure_cgroups();
+        let ruleset = Self::ruleset(&app, &config)?;
+
+        // 2. Configure Tor if needed
         Self::check_and_configure_tor(&app)?;
 
-        // 2. Start Tor Service
+        // 3. Start Tor Service
         Self::start_tor_service(&app)?;
 
-        // 3. Backup and Apply Iptables
+        // 4. Backup and Apply Iptables
         Self::emit_log(&app, "Applying Firewall Rules...");
-        IptablesManager::backup_rules()?;
-        IptablesManager::apply_rules(TOR_UID, DNS_PORT, TRANS_PORT)?;
+        let mut session = ShieldSession::new(TOR_UID, TRANS_PORT, DNS_PORT);
+        session.tor_was_running = tor_was_running;
+        if IptablesManager::is_killswitch_installed() {
+            // Don't snapshot our own ruleset or resolver if a previous
+            // shield is still up
+            if let Some(previous) = ShieldSession::load() {
+                // Tor is running now because that shield started it
+                session.tor_was_running = previous.tor_was_running;
+                session.firewall_snapshot = previous.firewall_snapshot;
+                session.dns = previous.dns;
+                session.ntp_disabled = previous.ntp_disabled;
+            }
+        } else {
+            session.firewall_snapshot = Some(IptablesManager::backup_rules()?);
+            // Still unprotected, so the echo service sees the real IP. The
+            // request goes out in the clear, hence opt-in.
+            if config.leak_test.record_real_ip {
+                use tauri::Manager;
+                *app.state::<AnonsurfState>().real_ip.lock().unwrap() =
+                    rotation::fetch_exit_ip(&config.echo_url, None).ok();
+            }
+        }
+        // Without redirection there is no DNSPort to point the resolver at
+        let transparent = config.shield.mode == ShieldMode::Transparent;
+        if transparent && session.dns.is_none() {
+            session.dns = Some(dns::backup()?);
+        }
+        // NTP would leak in the clear, or be blocked and let the clock drift
+        if config.time_sync.disable_ntp && !session.ntp_disabled {
+            match timesync::disable_ntp() {
+                Ok(was_on) => session.ntp_disabled = was_on,
+                Err(e) => Self::emit_log(&app, &format!("Failed to disable NTP: {}", e)),
+            }
+        }
+
+        // Record the session before the killswitch goes up, so a crash
+        // from here on is detected on the next launch
+        session.save()?;
+        IptablesManager::apply_rules(&ruleset)?;
+        if let Err(e) = bootguard::refresh(&ruleset) {
+            Self::emit_log(&app, &format!("Failed to update boot killswitch: {}", e));
+        }
+
+        // 5. Point the system resolver at the DNSPort. The firewall already
+        // redirects port 53, so a failure here breaks lookups, not privacy.
+        if let Some(backup) = session.dns.as_ref().filter(|_| transparent) {
+            Self::emit_log(&app, "Redirecting system DNS to Tor...");
+            if let Err(e) = dns::redirect(backup, DNS_PORT) {
+                Self::emit_log(&app, &format!("Failed to redirect system DNS: {}", e));
+            }
+        } else if !transparent {
+            Self::emit_log(
+                &app,
+                "Killswitch-only mode: use Tor's SocksPort to get out.",
+            );
+        }
+
+        // 6. Restarting Tor dropped our onion services
+        Self::restore_onions(&app);
 
         Self::emit_log(&app, "Aegis Shield Activated Successfully.");
         Ok(())
     }
 
-    pub fn stop() -> Result<()> {
-        println!("Stopping Anonsurf...");
+    /// Takes over a shield left behind by a crashed Aegis process,
+    /// repairing whichever half (Tor or firewall) went missing
+    pub fn resume(app: &tauri::AppHandle, orphan: &OrphanedSession) -> Result<()> {
+        Self::emit_log(app, "Resuming previous shield session...");
+        let reconfigured = Self::check_and_configure_tor(app)?;
+
+        // A Tor started at boot runs without the drop-in, i.e. without
+        // TransPort and DNSPort, until it is restarted
+        if !orphan.tor_running || reconfigured {
+            Self::start_tor_service(app)?;
+            Self::restore_onions(app);
+        }
+
+        // Only the DROP policy is checked, so parts of the old ruleset may
+        // still be loaded: flush them rather than appending duplicates
+        if !orphan.firewall_active {
+            Self::emit_log(app, "Re-applying Firewall Rules...");
+            apps::ensure_cgroups();
+            IptablesManager::reapply_rules(&Self::ruleset(app, &Self::config(app))?)?;
+        }
 
-        // 1. Flush Rules
-        IptablesManager::flush_rules()?;
+        match orphan.session.as_ref().and_then(|s| s.dns.as_ref()) {
+            Some(backup) => dns::redirect(backup, DNS_PORT)?,
+            None if orphan.session.is_none() => {
+                ShieldSession::new(TOR_UID, TRANS_PORT, DNS_PORT).save()?
+            }
+            None => {}
+        }
 
-        // 2. (Optional) Stop Tor Service? Usually we keep it running.
-        // Self::stop_tor_service()?;
+        // The old session may have died, or the boot killswitch come up,
+        // before NTP was turned off
+        if Self::config(app).time_sync.disable_ntp {
+            let mut session = ShieldSession::load()
+                .unwrap_or_else(|| ShieldSession::new(TOR_UID, TRANS_PORT, DNS_PORT));
+            if !session.ntp_disabled {
+                match timesync::disable_ntp() {
+                    Ok(true) => {
+                        session.ntp_disabled = true;
+                        session.save()?;
+                    }
+                    Ok(false) => {}
+                    Err(e) => Self::emit_log(app, &format!("Failed to disable NTP: {}", e)),
+                }
+            }
+        }
 
-        println!("Anonsurf stopped.");
+        Self::emit_log(app, "Previous shield session resumed.");
         Ok(())
     }
 
+    /// Tears the shield down using the session record: restores the
+    /// firewall snapshot, reverts the torrc drop-in and puts Tor back
+    /// into the state it was in before `start`.
+    pub fn stop(opts: &StopOptions) -> TeardownReport {
+        println!("Stopping Anonsurf...");
+        let session = ShieldSession::load();
+        let mut report = TeardownReport::default();
+
+        // 1. Firewall: restore the pre-shield ruleset, or flush as a fallback
+        match session.as_ref().and_then(|s| s.firewall_snapshot.as_ref()) {
+            Some(snapshot) => report.record(
+                "Restore firewall snapshot",
+                IptablesManager::restore_rules(snapshot),
+            ),
+            None => report.record("Flush firewall rules", IptablesManager::flush_rules()),
+        }
+        if netns::is_up() {
+            report.record("Restore Tor namespace rules", netns::install_host_rules());
+        }
+
+        // 2. Resolver configuration
+        if let Some(backup) = session.as_ref().and_then(|s| s.dns.as_ref()) {
+            report.record("Restore resolver configuration", dns::restore(backup));
+        }
+
+        // 3. System clock synchronization
+        if session.as_ref().is_some_and(|s| s.ntp_disabled) {
+            report.record("Re-enable NTP", timesync::set_ntp(true));
+        }
+
+        // 4. Tor configuration (kept while the Tor namespace still needs it)
+        if netns::is_up() {
+            report.record(
+                "Refresh torrc drop-in",
+                Self::refresh_tor_config(&AegisConfig::load()),
+            );
+        } else if session.is_some() || torrc::is_installed() {
+            report.record("Revert torrc drop-in", torrc::revert());
+        }
+
+        // 5. Tor service: stop it, or reload it without our configuration
+        let tor_was_running = session.as_ref().map(|s| s.tor_was_running).unwrap_or(true);
+        if opts.stop_tor || !tor_was_running {
+            report.record("Stop Tor service", Self::systemctl_tor("stop"));
+        } else if Self::is_tor_running() {
+            report.record("Reload Tor configuration", Self::systemctl_tor("reload"));
+        }
+
+        // 6. Keep the record around if something failed so teardown can be retried
+        if report.failed.is_empty() {
+            report.record("Remove session record", ShieldSession::clear());
+        }
+
+        report.killswitch_active = IptablesManager::is_killswitch_installed();
+        println!(
+            "Anonsurf stopped: {} step(s) undone, {} failed.",
+            report.undone.len(),
+            report.failed.len()
+        );
+        report
+    }
+
     pub fn new_identity() -> Result<()> {
         println!("Requesting new identity...");
         // Use netcat or specialized crate to send signal to 9051
@@ -72,84 +316,123 @@ impl Anonsurf {
         Ok(())
     }
 
-    fn start_tor_service(app: &tauri::AppHandle) -> Result<()> {
+    pub fn start_tor_service(app: &tauri::AppHandle) -> Result<()> {
         Self::emit_log(app, "Restarting Tor Service...");
+        Self::systemctl_tor("restart") // Restart to apply new config
+    }
+
+    fn systemctl_tor(action: &str) -> Result<()> {
         let status = Command::new("systemctl")
-            .args(&["restart", "tor"]) // Restart to apply new config
+            .args([action, "tor"])
             .status()
-            .context("Failed to start Tor service")?;
-        
+            .context(format!("Failed to {} Tor service", action))?;
+
         if !status.success() {
-             return Err(anyhow::anyhow!("Failed to start Tor service"));
+            return Err(anyhow::anyhow!("Failed to {} Tor service", action));
         }
         Ok(())
     }
 
+    pub fn is_tor_running() -> bool {
+        Command::new("pidof")
+            .arg("tor")
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false)
+    }
+
     fn emit_log(app: &tauri::AppHandle, msg: &str) {
         use tauri::Emitter;
         let _ = app.emit("app_log", msg);
     }
 
     fn send_tor_signal(signal: &str) -> Result<()> {
-        use std::io::Write;
-        use std::net::TcpStream;
-
-        // Try to connect to Control Port 9051
-        let mut stream = TcpStream::connect("127.0.0.1:9051")
-            .context("Could not connect to Tor Control Port. Is Tor running and ControlPort 9051 enabled?")?;
-        
-        // Authenticate (assume cookie auth or no auth for localhost if configured)
-        // Try passwordless first
-        stream.write_all(b"AUTHENTICATE \"\"\r\n")?;
-        
-        // Send Signal
-        let cmd = format!("SIGNAL {}\r\n", signal);
-        stream.write_all(cmd.as_bytes())?;
-        
-        // Quit
-        stream.write_all(b"QUIT\r\n")?;
+        TorControl::connect()?.signal(signal)
+    }
 
+    /// Rewrites the drop-in after a settings change so it survives Tor
+    /// restarts. Does nothing while the shield is not configured.
+    pub fn refresh_tor_config(config: &AegisConfig) -> Result<()> {
+        if torrc::is_installed() {
+            torrc::install(&torrc::render(TRANS_PORT, DNS_PORT, config)?)?;
+        }
         Ok(())
     }
 
-    fn check_and_configure_tor(app: &tauri::AppHandle) -> Result<()> {
-        use std::fs;
-        use std::io::Write;
+    /// Re-publishes persistent onion services after a Tor restart
+    pub fn restore_onions(app: &tauri::AppHandle) {
+        match onion::restore() {
+            Ok((restored, failed)) => {
+                if restored > 0 {
+                    Self::emit_log(app, &format!("Restored {} onion service(s).", restored));
+                }
+                for failure in failed {
+                    Self::emit_log(app, &format!("Failed to restore onion service {}", failure));
+                }
+            }
+            Err(e) => Self::emit_log(app, &format!("Failed to restore onion services: {}", e)),
+        }
+    }
+
+    /// Installs our drop-in and makes sure Tor runs with it, without
+    /// touching the firewall
+    pub fn ensure_tor(app: &tauri::AppHandle) -> Result<()> {
+        Self::check_and_configure_tor(app)?;
+        if Self::is_tor_running() {
+            Self::reload_tor_config()
+        } else {
+            Self::start_tor_service(app)
+        }
+    }
+
+    /// Makes a running Tor re-read torrc (and our drop-in)
+    pub fn reload_tor_config() -> Result<()> {
+        Self::send_tor_signal("RELOAD")
+    }
+
+    fn config(app: &tauri::AppHandle) -> AegisConfig {
+        use tauri::Manager;
+        app.state::<ConfigState>().config.lock().unwrap().clone()
+    }
+
+    /// Builds the firewall ruleset for the current settings
+    pub fn ruleset(app: &tauri::AppHandle, config: &AegisConfig) -> Result<Ruleset> {
+        use tauri::Manager;
+        let tor_egress = if config.proxy.enabled {
+            Some(config.proxy.endpoint()?)
+        } else {
+            None
+        };
 
+        Ok(Ruleset {
+            mode: config.shield.mode,
+            socks_ports: config.shield.socks_ports.clone(),
+            tor_uid: TOR_UID.to_string(),
+            dns_port: DNS_PORT.to_string(),
+            trans_port: TRANS_PORT.to_string(),
+            tor_egress,
+            allowlist: config.allowlist.clone(),
+            app_groups: apps::cgroups_ready(),
+            owners: config.users.resolve()?,
+            netns: netns::is_up(),
+            app_firewall: config.app_firewall.enabled,
+            portal_uid: app.state::<PortalState>().current().map(|p| p.uid),
+        })
+    }
+
+    /// Returns whether the configuration changed, i.e. a running Tor
+    /// needs a restart or reload to pick it up
+    fn check_and_configure_tor(app: &tauri::AppHandle) -> Result<bool> {
         Self::emit_log(app, "Checking Tor Configuration...");
-        let torrc_path = "/etc/tor/torrc";
-        let content = fs::read_to_string(torrc_path).context("Failed to read torrc")?;
+        let config = Self::config(app);
 
-        // Check if our configuration exists
-        if content.contains("TransPort 9040") {
+        let changed = torrc::install(&torrc::render(TRANS_PORT, DNS_PORT, &config)?)?;
+        if changed {
+            Self::emit_log(app, "Tor configuration updated. Added TransPort/DNSPort.");
+        } else {
             Self::emit_log(app, "Tor is already configured correctly.");
-            return Ok(());
-        }
-
-        Self::emit_log(app, "Tor configuration incomplete. Fixing...");
-        println!("Configuring Tor for Transparent Proxy...");
-
-        let config_block = r#"
-# --- Added by Aegis Privacy Shield ---
-VirtualAddrNetworkIPv4 10.192.0.0/10
-AutomapHostsOnResolve 1
-TransPort 9040 IsolateClientAddr IsolateClientProtocol IsolateDestAddr IsolateDestPort
-DNSPort 5353
-ControlPort 9051
-CookieAuthentication 0
-# -----------------------------------
-"#;
-
-        let mut file = fs::OpenOptions::new()
-            .append(true)
-            .open(torrc_path)
-            .context("Failed to open torrc for appending")?;
-        
-        writeln!(file, "{}", config_block)?;
-        
-        Self::emit_log(app, "Tor configuration updated. Added TransPort/DNSPort.");
-        println!("Tor configuration updated.");
-        
-        Ok(())
+        }
+
+        Ok(changed)
     }
 }
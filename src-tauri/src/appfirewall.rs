@@ -0,0 +1,479 @@
+use crate::config::{self, ConfigState};
+use crate::droplog;
+use crate::iptables::ShieldMode;
+use crate::nfqueue::{NfQueue, QueuedPacket, Verdict};
+use anyhow::{Context, Result};
+use ring::digest::{Context as Digest, SHA256};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::Read;
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use tauri::{AppHandle, Emitter, Manager};
+
+pub const QUEUE_NUM: u16 = 7046;
+// Packet marks carrying the verdict into the nat and filter tables
+const MARK_DIRECT: u32 = 0xae01;
+const MARK_TOR: u32 = 0xae02;
+// Unanswered prompts fall back to the normal shield routing
+const PROMPT_TIMEOUT: u64 = 60; // seconds
+const RECV_TIMEOUT: u64 = 1; // seconds
+const RULES_FILE: &str = "firewall_rules.json";
+// Recent connection owners, checked before scanning every process's fds
+const RECENT_OWNERS: usize = 16;
+
+/// Interactive application firewall: new outbound connections of unknown
+/// programs are held until the user decides where they go
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AppFirewallSettings {
+    pub enabled: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AppAction {
+    /// Leave directly, bypassing Tor
+    Direct,
+    /// Always through Tor, allowlisted destinations included
+    Tor,
+    Deny,
+}
+
+/// A remembered decision; a rebuilt or replaced binary asks again
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct AppFirewallRule {
+    pub exe: String,
+    pub sha256: String,
+    pub action: AppAction,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct FirewallPrompt {
+    pub id: u64,
+    pub exe: String,
+    pub pid: u32,
+    pub destination: String,
+    pub port: u16,
+    pub protocol: String,
+    /// There is a rule for this path, but for a different binary
+    pub changed: bool,
+    /// Killswitch mode has no TransPort, so "Tor" drops the connection
+    pub killswitch: bool,
+}
+
+struct PendingPrompt {
+    prompt: FirewallPrompt,
+    sha256: String,
+    packets: Vec<u32>,
+    created: Instant,
+}
+
+pub struct AppFirewallState {
+    queue: Mutex<Option<Arc<NfQueue>>>,
+    rules: Mutex<Vec<AppFirewallRule>>,
+    pending: Mutex<HashMap<u64, PendingPrompt>>,
+    next_id: AtomicU64,
+}
+
+impl AppFirewallState {
+    pub fn new() -> Self {
+        Self {
+            queue: Mutex::new(None),
+            rules: Mutex::new(load_rules()),
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn rules(&self) -> Vec<AppFirewallRule> {
+        self.rules.lock().unwrap().clone()
+    }
+
+    pub fn prompts(&self) -> Vec<FirewallPrompt> {
+        self.pending
+            .lock()
+            .unwrap()
+            .values()
+            .map(|p| p.prompt.clone())
+            .collect()
+    }
+
+    pub fn delete_rule(&self, exe: &str) -> Result<()> {
+        let mut rules = self.rules.lock().unwrap();
+        rules.retain(|r| r.exe != exe);
+        save_rules(&rules)
+    }
+
+    /// Applies the user's decision to the held packets and remembers it
+    pub fn answer(&self, id: u64, action: AppAction) -> Result<()> {
+        let pending = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .ok_or_else(|| anyhow::anyhow!("The prompt has expired"))?;
+
+        {
+            let mut rules = self.rules.lock().unwrap();
+            rules.retain(|r| r.exe != pending.prompt.exe);
+            rules.push(AppFirewallRule {
+                exe: pending.prompt.exe.clone(),
+                sha256: pending.sha256.clone(),
+                action,
+            });
+            save_rules(&rules)?;
+        }
+
+        if let Some(queue) = self.queue.lock().unwrap().as_ref() {
+            for packet in pending.packets {
+                queue.verdict(packet, verdict(action))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Matchers for the ruleset's exempt and forced steps
+pub fn direct_matcher() -> String {
+    format!("-m mark --mark {:#x}", MARK_DIRECT)
+}
+
+pub fn tor_matcher() -> String {
+    format!("-m mark --mark {:#x}", MARK_TOR)
+}
+
+/// Queues the first packet of each new connection. Decided connections
+/// carry their mark in conntrack, and --queue-bypass keeps the normal
+/// shield routing if Aegis isn't listening.
+pub fn mangle_rules(tor_uid: &str, exempt: &[String], forced: &[String]) -> Vec<String> {
+    let mut rules = vec![
+        "iptables -t mangle -A OUTPUT -o lo -j RETURN".to_string(),
+        format!(
+            "iptables -t mangle -A OUTPUT -m owner --uid-owner {} -j RETURN",
+            tor_uid
+        ),
+        // DNS always goes to the DNSPort
+        "iptables -t mangle -A OUTPUT -p udp --dport 53 -j RETURN".to_string(),
+    ];
+    // Exempt traffic leaves directly whatever the answer, so don't ask.
+    // Forced traffic is never marked either: a "Direct" answer would
+    // otherwise let it skip Tor through the exempt rules.
+    rules.extend(
+        exempt
+            .iter()
+            .chain(forced)
+            .map(|m| format!("iptables -t mangle -A OUTPUT {} -j RETURN", m)),
+    );
+    rules.extend([
+        "iptables -t mangle -A OUTPUT -m mark --mark 0 -j CONNMARK --restore-mark".to_string(),
+        format!(
+            "iptables -t mangle -A OUTPUT -m mark --mark 0 -m conntrack --ctstate NEW -j NFQUEUE --queue-num {} --queue-bypass",
+            QUEUE_NUM
+        ),
+        "iptables -t mangle -A OUTPUT -m mark ! --mark 0 -j CONNMARK --save-mark".to_string(),
+    ]);
+    rules
+}
+
+fn verdict(action: AppAction) -> Verdict {
+    match action {
+        AppAction::Direct => Verdict::Repeat(MARK_DIRECT),
+        AppAction::Tor => Verdict::Repeat(MARK_TOR),
+        AppAction::Deny => Verdict::Drop,
+    }
+}
+
+/// Binds the queue and decides queued packets, prompting for unknown programs
+pub fn start_app_firewall(app: AppHandle) {
+    thread::spawn(move || {
+        let queue = match NfQueue::bind(QUEUE_NUM) {
+            Ok(q) => Arc::new(q),
+            Err(e) => {
+                println!("Application firewall unavailable: {:#}", e);
+                return;
+            }
+        };
+        let state = app.state::<AppFirewallState>();
+        *state.queue.lock().unwrap() = Some(queue.clone());
+
+        let mut hashes = HashMap::new();
+        let mut owners = VecDeque::new();
+        loop {
+            let packets = match queue.recv(Duration::from_secs(RECV_TIMEOUT)) {
+                Ok(p) => p,
+                Err(e) => {
+                    let _ = app.emit("app_log", format!("Application firewall stopped: {}", e));
+                    *state.queue.lock().unwrap() = None;
+                    return;
+                }
+            };
+
+            for packet in packets {
+                let id = packet.id;
+                let result = match decide(&app, &state, &mut hashes, &mut owners, packet) {
+                    Some(v) => queue.verdict(id, v),
+                    None => Ok(()), // held for a prompt
+                };
+                if let Err(e) = result {
+                    println!("Application firewall verdict failed: {}", e);
+                }
+            }
+
+            expire_prompts(&app, &state, &queue);
+        }
+    });
+}
+
+/// Returns the verdict, or `None` when the packet waits for the user
+fn decide(
+    app: &AppHandle,
+    state: &AppFirewallState,
+    hashes: &mut HashMap<(String, SystemTime), String>,
+    owners: &mut VecDeque<u32>,
+    packet: QueuedPacket,
+) -> Option<Verdict> {
+    let config_state = app.state::<ConfigState>();
+    let (enabled, killswitch) = {
+        let config = config_state.config.lock().unwrap();
+        (
+            config.app_firewall.enabled,
+            config.shield.mode == ShieldMode::Killswitch,
+        )
+    };
+    // Unattributable traffic takes the normal shield route
+    let conn = match parse_ipv4(&packet.payload) {
+        Some(c) if enabled => c,
+        _ => return Some(Verdict::Accept),
+    };
+    let pid = match droplog::socket_inode(conn.protocol, conn.source_port)
+        .and_then(|inode| socket_owner(inode, owners))
+    {
+        Some(pid) => pid,
+        None => return Some(Verdict::Accept),
+    };
+    let (exe, sha256) = match executable(pid, hashes) {
+        Some(e) => e,
+        None => return Some(Verdict::Accept),
+    };
+
+    let changed = {
+        let rules = state.rules.lock().unwrap();
+        if let Some(rule) = rules.iter().find(|r| r.exe == exe && r.sha256 == sha256) {
+            return Some(verdict(rule.action));
+        }
+        rules.iter().any(|r| r.exe == exe)
+    };
+
+    let mut pending = state.pending.lock().unwrap();
+    if let Some(p) = pending
+        .values_mut()
+        .find(|p| p.prompt.exe == exe && p.sha256 == sha256)
+    {
+        p.packets.push(packet.id);
+        return None;
+    }
+
+    let prompt = FirewallPrompt {
+        id: state.next_id.fetch_add(1, Ordering::Relaxed),
+        exe,
+        pid,
+        destination: conn.destination.to_string(),
+        port: conn.port,
+        protocol: conn.protocol.to_string(),
+        changed,
+        killswitch,
+    };
+    let _ = app.emit("firewall_prompt", prompt.clone());
+    pending.insert(
+        prompt.id,
+        PendingPrompt {
+            prompt,
+            sha256,
+            packets: vec![packet.id],
+            created: Instant::now(),
+        },
+    );
+    None
+}
+
+fn expire_prompts(app: &AppHandle, state: &AppFirewallState, queue: &NfQueue) {
+    let mut pending = state.pending.lock().unwrap();
+    let expired: Vec<u64> = pending
+        .iter()
+        .filter(|(_, p)| p.created.elapsed().as_secs() >= PROMPT_TIMEOUT)
+        .map(|(id, _)| *id)
+        .collect();
+
+    for id in expired {
+        if let Some(p) = pending.remove(&id) {
+            for packet in p.packets {
+                let _ = queue.verdict(packet, Verdict::Accept);
+            }
+            let _ = app.emit("firewall_prompt_expired", id);
+        }
+    }
+}
+
+struct Connection {
+    protocol: &'static str,
+    destination: Ipv4Addr,
+    source_port: u16,
+    port: u16,
+}
+
+fn parse_ipv4(packet: &[u8]) -> Option<Connection> {
+    if packet.first()? >> 4 != 4 {
+        return None;
+    }
+    let header_len = ((packet[0] & 0x0f) as usize) * 4;
+    let protocol = match packet.get(9)? {
+        6 => "TCP",
+        17 => "UDP",
+        _ => return None,
+    };
+    let dst = packet.get(16..20)?;
+    let ports = packet.get(header_len..header_len + 4)?;
+
+    Some(Connection {
+        protocol,
+        destination: Ipv4Addr::new(dst[0], dst[1], dst[2], dst[3]),
+        source_port: u16::from_be_bytes([ports[0], ports[1]]),
+        port: u16::from_be_bytes([ports[2], ports[3]]),
+    })
+}
+
+/// The process holding a socket. Programs open connections in bursts, so
+/// the last few owners are tried before every process's fds are scanned;
+/// all of this runs while the kernel holds the packet.
+fn socket_owner(inode: u64, recent: &mut VecDeque<u32>) -> Option<u32> {
+    let pid = match recent
+        .iter()
+        .copied()
+        .find(|pid| droplog::holds_socket(*pid, inode))
+    {
+        Some(pid) => pid,
+        None => droplog::socket_owner(inode)?.0,
+    };
+    recent.retain(|p| *p != pid);
+    recent.push_front(pid);
+    recent.truncate(RECENT_OWNERS);
+    Some(pid)
+}
+
+/// Path and SHA-256 of the binary a process runs. Hashes are cached by
+/// path and mtime, browsers are big.
+fn executable(
+    pid: u32,
+    hashes: &mut HashMap<(String, SystemTime), String>,
+) -> Option<(String, String)> {
+    let link = format!("/proc/{}/exe", pid);
+    let exe = fs::read_link(&link).ok()?.to_string_lossy().to_string();
+    let mtime = fs::metadata(&link).ok()?.modified().ok()?;
+
+    let key = (exe.clone(), mtime);
+    if let Some(hash) = hashes.get(&key) {
+        return Some((exe, hash.clone()));
+    }
+
+    // /proc/<pid>/exe still opens the running binary if it was replaced
+    let hash = sha256_file(&link).ok()?;
+    hashes.insert(key, hash.clone());
+    Some((exe, hash))
+}
+
+fn sha256_file(path: &str) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut digest = Digest::new(&SHA256);
+    let mut buf = [0u8; 65536];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        digest.update(&buf[..n]);
+    }
+    Ok(digest
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+fn load_rules() -> Vec<AppFirewallRule> {
+    fs::read_to_string(config::data_path(RULES_FILE))
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+fn save_rules(rules: &[AppFirewallRule]) -> Result<()> {
+    let path = config::ensure_data_path(RULES_FILE)?;
+    fs::write(path, serde_json::to_string_pretty(rules)?)
+        .context("Failed to save application firewall rules")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// IPv4 header of `header_len` bytes, then source and destination port
+    fn packet(header_len: u8, protocol: u8) -> Vec<u8> {
+        let mut p = vec![0u8; header_len as usize];
+        p[0] = 0x40 | (header_len / 4);
+        p[9] = protocol;
+        p[12..16].copy_from_slice(&[10, 0, 0, 2]);
+        p[16..20].copy_from_slice(&[203, 0, 113, 7]);
+        p.extend(51234u16.to_be_bytes());
+        p.extend(443u16.to_be_bytes());
+        p
+    }
+
+    #[test]
+    fn parses_tcp_and_udp_connections() {
+        let tcp = parse_ipv4(&packet(20, 6)).unwrap();
+        assert_eq!(tcp.protocol, "TCP");
+        assert_eq!(tcp.destination, Ipv4Addr::new(203, 0, 113, 7));
+        assert_eq!(tcp.source_port, 51234);
+        assert_eq!(tcp.port, 443);
+
+        assert_eq!(parse_ipv4(&packet(20, 17)).unwrap().protocol, "UDP");
+    }
+
+    #[test]
+    fn ports_follow_ip_options() {
+        let conn = parse_ipv4(&packet(24, 6)).unwrap();
+        assert_eq!(conn.source_port, 51234);
+        assert_eq!(conn.port, 443);
+    }
+
+    #[test]
+    fn ignores_other_protocols_and_short_packets() {
+        // ICMP
+        assert!(parse_ipv4(&packet(20, 1)).is_none());
+
+        let mut v6 = packet(20, 6);
+        v6[0] = 0x60;
+        assert!(parse_ipv4(&v6).is_none());
+
+        // Cut off in the middle of the ports
+        assert!(parse_ipv4(&packet(20, 6)[..22]).is_none());
+        assert!(parse_ipv4(&[]).is_none());
+    }
+
+    #[test]
+    fn forced_traffic_is_never_queued() {
+        let forced = "-m owner --uid-owner 1001".to_string();
+        let rules = mangle_rules("debian-tor", &[], std::slice::from_ref(&forced));
+        let returns = rules
+            .iter()
+            .position(|r| r.contains(&forced) && r.ends_with("-j RETURN"))
+            .unwrap();
+        let queue = rules.iter().position(|r| r.contains("NFQUEUE")).unwrap();
+        assert!(returns < queue);
+    }
+}
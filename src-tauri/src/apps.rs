@@ -0,0 +1,248 @@
+use crate::anonsurf::AnonsurfState;
+use crate::config::ConfigState;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const SWEEP_INTERVAL: u64 = 5; // seconds
+
+/// Aegis-managed cgroups, matched in the firewall by path
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AppGroup {
+    /// Reaches the network directly, never through Tor
+    Bypass,
+    /// Always goes through Tor, even to allowlisted destinations
+    Forced,
+}
+
+impl AppGroup {
+    /// Path relative to the cgroup v2 root, as `-m cgroup --path` expects it
+    pub fn path(&self) -> &'static str {
+        match self {
+            AppGroup::Bypass => "aegis/bypass",
+            AppGroup::Forced => "aegis/forced",
+        }
+    }
+
+    fn dir(&self) -> PathBuf {
+        PathBuf::from(CGROUP_ROOT).join(self.path())
+    }
+}
+
+/// Program names placed into each group, matched against the executable's
+/// file name and /proc/<pid>/comm (which the kernel cuts at 15 characters,
+/// but which names scripts rather than their interpreter)
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AppRules {
+    pub bypass: Vec<String>,
+    pub forced: Vec<String>,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct AppProcess {
+    pub pid: u32,
+    pub name: String,
+    pub group: AppGroup,
+}
+
+impl AppRules {
+    pub fn validate(&self) -> Result<()> {
+        for name in self.bypass.iter().chain(&self.forced) {
+            if name.trim().is_empty() || name.contains('/') {
+                return Err(anyhow::anyhow!("Invalid program name \"{}\"", name));
+            }
+            if self.bypass.contains(name) && self.forced.contains(name) {
+                return Err(anyhow::anyhow!(
+                    "{} can't be both bypassed and forced through Tor",
+                    name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn group_of(&self, names: &[String]) -> Option<AppGroup> {
+        if self.bypass.iter().any(|n| names.contains(n)) {
+            Some(AppGroup::Bypass)
+        } else if self.forced.iter().any(|n| names.contains(n)) {
+            Some(AppGroup::Forced)
+        } else {
+            None
+        }
+    }
+}
+
+/// Creates the Aegis cgroups. Returns false when the unified (v2)
+/// hierarchy isn't mounted, in which case per-app rules are left out.
+pub fn ensure_cgroups() -> bool {
+    if !PathBuf::from(CGROUP_ROOT)
+        .join("cgroup.controllers")
+        .exists()
+    {
+        return false;
+    }
+
+    [AppGroup::Bypass, AppGroup::Forced]
+        .iter()
+        .all(|group| fs::create_dir_all(group.dir()).is_ok())
+}
+
+/// Whether `ensure_cgroups` has set the cgroups up, without touching them
+pub fn cgroups_ready() -> bool {
+    [AppGroup::Bypass, AppGroup::Forced]
+        .iter()
+        .all(|group| group.dir().is_dir())
+}
+
+/// Moves a running process into an Aegis cgroup
+pub fn move_pid(pid: u32, group: AppGroup) -> Result<()> {
+    fs::write(group.dir().join("cgroup.procs"), pid.to_string())
+        .with_context(|| format!("Failed to move {} into {}", pid, group.path()))
+}
+
+/// Starts a program inside the bypass cgroup as the desktop user.
+/// The shell joins the cgroup before exec'ing, so the program's very
+/// first connection is already matched.
+pub fn launch_bypassed(app: &str) -> Result<u32> {
+    let argv: Vec<&str> = app.split_whitespace().collect();
+    if argv.is_empty() {
+        return Err(anyhow::anyhow!("No program given"));
+    }
+    if !ensure_cgroups() {
+        return Err(anyhow::anyhow!("cgroup v2 is not available"));
+    }
+
+    // Aegis runs as root through sudo; don't hand root to the launched app
+    let user = std::env::var("SUDO_USER").context("Can't tell which user to launch as")?;
+    let procs = AppGroup::Bypass.dir().join("cgroup.procs");
+    let script = format!(
+        "echo $$ > {} && exec runuser -u \"$0\" -- \"$@\"",
+        procs.display()
+    );
+
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .arg(user)
+        .args(argv)
+        .spawn()
+        .context("Failed to launch program")?;
+
+    Ok(child.id())
+}
+
+/// Processes currently in an Aegis cgroup
+pub fn list_apps() -> Vec<AppProcess> {
+    let mut apps = Vec::new();
+    for group in [AppGroup::Bypass, AppGroup::Forced] {
+        let procs = fs::read_to_string(group.dir().join("cgroup.procs")).unwrap_or_default();
+        for pid in procs.lines().filter_map(|l| l.trim().parse().ok()) {
+            apps.push(AppProcess {
+                pid,
+                name: process_name(pid).unwrap_or_default(),
+                group,
+            });
+        }
+    }
+    apps
+}
+
+/// Moves processes matching the rules into their cgroup.
+/// Returns the processes that were moved.
+pub fn sweep(rules: &AppRules) -> Vec<AppProcess> {
+    let mut moved = Vec::new();
+    let entries = match fs::read_dir("/proc") {
+        Ok(e) => e,
+        Err(_) => return moved,
+    };
+
+    for pid in entries
+        .flatten()
+        .filter_map(|e| e.file_name().to_str()?.parse().ok())
+    {
+        let names = process_names(pid);
+        let group = match rules.group_of(&names) {
+            Some(g) => g,
+            None => continue,
+        };
+        let name = names.into_iter().next().unwrap_or_default();
+        if current_cgroup(pid).as_deref() == Some(group.path()) {
+            continue;
+        }
+        if move_pid(pid, group).is_ok() {
+            moved.push(AppProcess { pid, name, group });
+        }
+    }
+    moved
+}
+
+/// Keeps configured programs in their cgroups as they start, while the
+/// shield is up
+pub fn start_app_sweeper(app: AppHandle) {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(SWEEP_INTERVAL));
+
+        // Nothing routes by cgroup with the shield off, so neither the
+        // cgroups nor the processes are touched until it goes up
+        if !*app.state::<AnonsurfState>().is_active.lock().unwrap() {
+            continue;
+        }
+        if !ensure_cgroups() {
+            let _ = app.emit(
+                "app_log",
+                "cgroup v2 not available, per-app routing disabled".to_string(),
+            );
+            return;
+        }
+
+        let rules = app
+            .state::<ConfigState>()
+            .config
+            .lock()
+            .unwrap()
+            .apps
+            .clone();
+        for p in sweep(&rules) {
+            let _ = app.emit(
+                "app_log",
+                format!("Moved {} ({}) to {}", p.name, p.pid, p.group.path()),
+            );
+        }
+    });
+}
+
+fn process_name(pid: u32) -> Option<String> {
+    process_names(pid).into_iter().next()
+}
+
+/// The executable's file name, then comm if it differs
+fn process_names(pid: u32) -> Vec<String> {
+    let exe = fs::read_link(format!("/proc/{}/exe", pid))
+        .ok()
+        .and_then(|p| Some(p.file_name()?.to_string_lossy().into_owned()))
+        .map(|n| n.trim_end_matches(" (deleted)").to_string());
+    let comm = fs::read_to_string(format!("/proc/{}/comm", pid))
+        .ok()
+        .map(|c| c.trim().to_string());
+
+    let mut names: Vec<String> = exe.into_iter().collect();
+    if let Some(comm) = comm.filter(|c| !names.contains(c)) {
+        names.push(comm);
+    }
+    names
+}
+
+/// The "0::/path" line of /proc/<pid>/cgroup, without the leading slash
+pub fn current_cgroup(pid: u32) -> Option<String> {
+    fs::read_to_string(format!("/proc/{}/cgroup", pid))
+        .ok()?
+        .lines()
+        .find_map(|l| l.strip_prefix("0::/").map(String::from))
+}
@@ -0,0 +1,204 @@
+use crate::config;
+use crate::iptables::{IptablesManager, Ruleset};
+use anyhow::{Context, Result};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+
+const SCRIPT_FILE: &str = "boot-killswitch.sh";
+const SERVICE_NAME: &str = "aegis-killswitch";
+const SYSTEMD_UNIT: &str = "/etc/systemd/system/aegis-killswitch.service";
+const OPENRC_SCRIPT: &str = "/etc/init.d/aegis-killswitch";
+
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InitSystem {
+    Systemd,
+    OpenRc,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct BootKillswitchStatus {
+    pub installed: bool,
+    /// None if neither systemd nor OpenRC is running
+    pub init: Option<InitSystem>,
+}
+
+pub fn status() -> BootKillswitchStatus {
+    BootKillswitchStatus {
+        installed: is_installed(),
+        init: detect_init(),
+    }
+}
+
+pub fn is_installed() -> bool {
+    Path::new(SYSTEMD_UNIT).exists() || Path::new(OPENRC_SCRIPT).exists()
+}
+
+/// Writes the boot script for `ruleset` and enables a service running it
+/// before networking comes up. Until Aegis starts the shield, only Tor
+/// itself gets out.
+pub fn install(ruleset: &Ruleset) -> Result<InitSystem> {
+    let init =
+        detect_init().ok_or_else(|| anyhow::anyhow!("Neither systemd nor OpenRC is running"))?;
+    let script = write_script(ruleset)?;
+
+    match init {
+        InitSystem::Systemd => {
+            fs::write(SYSTEMD_UNIT, systemd_unit(&script))
+                .context("Failed to write systemd unit")?;
+            run("systemctl", &["daemon-reload"])?;
+            run("systemctl", &["enable", SERVICE_NAME])?;
+        }
+        InitSystem::OpenRc => {
+            fs::write(OPENRC_SCRIPT, openrc_script(&script))
+                .context("Failed to write OpenRC script")?;
+            fs::set_permissions(OPENRC_SCRIPT, fs::Permissions::from_mode(0o755))?;
+            run("rc-update", &["add", SERVICE_NAME, "boot"])?;
+        }
+    }
+    Ok(init)
+}
+
+/// Disables and removes the service. The live ruleset is left alone.
+pub fn uninstall() -> Result<()> {
+    if Path::new(SYSTEMD_UNIT).exists() {
+        // Already disabled or never enabled is fine
+        let _ = run("systemctl", &["disable", SERVICE_NAME]);
+        fs::remove_file(SYSTEMD_UNIT).context("Failed to remove systemd unit")?;
+        run("systemctl", &["daemon-reload"])?;
+    }
+    if Path::new(OPENRC_SCRIPT).exists() {
+        let _ = run("rc-update", &["del", SERVICE_NAME, "boot"]);
+        fs::remove_file(OPENRC_SCRIPT).context("Failed to remove OpenRC script")?;
+    }
+
+    let script = config::data_path(SCRIPT_FILE);
+    if script.exists() {
+        fs::remove_file(script).context("Failed to remove boot script")?;
+    }
+    Ok(())
+}
+
+/// Keeps the boot script in line with the current settings. Does nothing
+/// unless the boot killswitch is installed.
+pub fn refresh(ruleset: &Ruleset) -> Result<()> {
+    if is_installed() {
+        write_script(ruleset)?;
+    }
+    Ok(())
+}
+
+fn detect_init() -> Option<InitSystem> {
+    if Path::new("/run/systemd/system").exists() {
+        Some(InitSystem::Systemd)
+    } else if Path::new("/run/openrc").exists() {
+        Some(InitSystem::OpenRc)
+    } else {
+        None
+    }
+}
+
+fn write_script(ruleset: &Ruleset) -> Result<String> {
+    let path = config::ensure_data_path(SCRIPT_FILE)?;
+    fs::write(&path, render(ruleset)).context("Failed to write boot script")?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o700))?;
+    Ok(path.display().to_string())
+}
+
+/// Unlike `execute_batch`, a failing rule doesn't stop the script: rules
+/// for cgroups that don't exist yet must not keep the rest from loading.
+/// The DROP policies go first, so a failed rule usually blocks more. Not
+/// when only forced users are shielded: everyone else is let out at the
+/// end, and a failed forced rule would let its traffic out with them. So
+/// then any failure leaves nothing but loopback.
+fn render(ruleset: &Ruleset) -> String {
+    let mut script = vec![
+        "#!/bin/sh".to_string(),
+        "# Generated by Aegis, refreshed whenever the shield starts".to_string(),
+        "PATH=/usr/sbin:/usr/bin:/sbin:/bin".to_string(),
+        "failed=".to_string(),
+        "run() { \"$@\" || { failed=1; echo \"aegis-killswitch: failed: $*\" >&2; }; }".to_string(),
+        String::new(),
+        "iptables -P OUTPUT DROP".to_string(),
+        "ip6tables -P OUTPUT DROP".to_string(),
+        "iptables -t nat -F".to_string(),
+        "iptables -t mangle -F".to_string(),
+        "iptables -F".to_string(),
+        "ip6tables -F".to_string(),
+        String::new(),
+    ];
+    script.extend(
+        IptablesManager::build_rules(ruleset)
+            .into_iter()
+            .map(|rule| format!("run {}", rule)),
+    );
+    if ruleset.owners.only_forced {
+        script.extend([
+            String::new(),
+            "if [ -n \"$failed\" ]; then".to_string(),
+            "    echo \"aegis-killswitch: blocking all but loopback\" >&2".to_string(),
+            "    iptables -t nat -F".to_string(),
+            "    iptables -t mangle -F".to_string(),
+            "    iptables -F".to_string(),
+            "    iptables -A INPUT -i lo -j ACCEPT".to_string(),
+            "    iptables -A OUTPUT -o lo -j ACCEPT".to_string(),
+            "fi".to_string(),
+        ]);
+    }
+    script.push("exit 0".to_string());
+    script.join("\n") + "\n"
+}
+
+fn systemd_unit(script: &str) -> String {
+    format!(
+        "[Unit]
+Description=Aegis boot-time killswitch
+DefaultDependencies=no
+After=local-fs.target
+Before=network-pre.target
+Wants=network-pre.target
+
+[Service]
+Type=oneshot
+RemainAfterExit=yes
+ExecStart=/bin/sh {}
+
+[Install]
+WantedBy=sysinit.target
+",
+        script
+    )
+}
+
+fn openrc_script(script: &str) -> String {
+    format!(
+        "#!/sbin/openrc-run
+description=\"Aegis boot-time killswitch\"
+
+depend() {{
+    after localmount
+    before net
+}}
+
+start() {{
+    ebegin \"Applying Aegis killswitch\"
+    /bin/sh {}
+    eend $?
+}}
+",
+        script
+    )
+}
+
+fn run(cmd: &str, args: &[&str]) -> Result<()> {
+    let status = Command::new(cmd)
+        .args(args)
+        .status()
+        .context(format!("Failed to run {}", cmd))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("{} {} failed", cmd, args.join(" ")));
+    }
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use crate::torctl::{self, TorControl};
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::path::Path;
+
+const PT_DIRS: &[&str] = &["/usr/bin", "/usr/local/bin", "/usr/sbin"];
+
+// Default bridges shipped with Tor Browser. They are public and therefore
+// often blocked; fresh ones come from https://bridges.torproject.org
+const BUILTIN_OBFS4: &[&str] = &[
+    "obfs4 192.95.36.142:443 CDF2E852BF539B82BD10E27E9115A31734E378C2 cert=qUVQ0srL1JI/vO6V6m/24anYXiJD3QP2HgzUKQtQ7GRqqUvs7P+tG43RtAqdhLOALP7DJQ iat-mode=1",
+    "obfs4 37.218.245.14:38224 D9A82D2F9C2F65A18407B1D2B764F130847F8B5D cert=bjRaMrr1BRiAW8IE9U5z27fQaYgOhX1UCmOpg2pFpoMvo6ZgQMzLsaTzzQNTlm7hNcb+Sg iat-mode=0",
+];
+const BUILTIN_SNOWFLAKE: &[&str] = &[
+    "snowflake 192.0.2.3:80 2B280B23E1107BB62ABFC40DDCC8824814F80A72 fingerprint=2B280B23E1107BB62ABFC40DDCC8824814F80A72 url=https://1098762253.rsc.cdn77.org/ fronts=www.cdn77.com,www.phpmyadmin.net ice=stun:stun.l.google.com:19302,stun:stun.antisip.com:3478,stun:stun.bluesip.net:3478,stun:stun.dus.net:3478,stun:stun.epygi.com:3478,stun:stun.sonetel.com:3478,stun:stun.uls.co.za:3478,stun:stun.voipgate.com:3478,stun:stun.voys.nl:3478 utls-imitate=hellorandomizedalpn",
+];
+const BUILTIN_MEEK_AZURE: &[&str] = &[
+    "meek_lite 192.0.2.18:80 BE776A53492E1E044A26F17306E1BC46A55A1625 url=https://meek.azureedge.net/ front=ajax.aspnetcdn.com",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transport {
+    Obfs4,
+    Snowflake,
+    MeekAzure,
+    Webtunnel,
+}
+
+impl Transport {
+    const ALL: [Transport; 4] = [
+        Transport::Obfs4,
+        Transport::Snowflake,
+        Transport::MeekAzure,
+        Transport::Webtunnel,
+    ];
+
+    /// Transport name used in "Bridge" and "ClientTransportPlugin" lines
+    fn keyword(&self) -> &'static str {
+        match self {
+            Transport::Obfs4 => "obfs4",
+            Transport::Snowflake => "snowflake",
+            Transport::MeekAzure => "meek_lite",
+            Transport::Webtunnel => "webtunnel",
+        }
+    }
+
+    fn from_keyword(keyword: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.keyword() == keyword)
+    }
+
+    /// Client binaries able to provide this transport, preferred first
+    fn binaries(&self) -> &'static [&'static str] {
+        match self {
+            Transport::Obfs4 | Transport::MeekAzure => &["lyrebird", "obfs4proxy"],
+            Transport::Snowflake => &["snowflake-client"],
+            Transport::Webtunnel => &["lyrebird", "webtunnel-client"],
+        }
+    }
+
+    fn builtin(&self) -> &'static [&'static str] {
+        match self {
+            Transport::Obfs4 => BUILTIN_OBFS4,
+            Transport::Snowflake => BUILTIN_SNOWFLAKE,
+            Transport::MeekAzure => BUILTIN_MEEK_AZURE,
+            Transport::Webtunnel => &[],
+        }
+    }
+
+    /// Keyword arguments a bridge line of this transport cannot work without
+    fn required_args(&self) -> &'static [&'static str] {
+        match self {
+            Transport::Obfs4 => &["cert", "iat-mode"],
+            Transport::Snowflake => &["url"],
+            Transport::MeekAzure => &["url"],
+            Transport::Webtunnel => &["url"],
+        }
+    }
+
+    fn hint(&self) -> &'static str {
+        match self {
+            Transport::Obfs4 => "obfs4 bridges are probably blocked or offline. Request fresh ones from bridges.torproject.org or try snowflake.",
+            Transport::Snowflake => "Snowflake needs its broker front domain and outbound UDP (WebRTC). Check that UDP isn't filtered and the system clock is correct.",
+            Transport::MeekAzure => "meek-azure depends on its CDN front domain being reachable. If the CDN is blocked, try snowflake or obfs4.",
+            Transport::Webtunnel => "WebTunnel needs the bridge's HTTPS url= endpoint to be reachable. Check the URL and request a new bridge if it was blocked.",
+        }
+    }
+
+    /// Path of the first installed client binary
+    pub fn find_binary(&self) -> Option<String> {
+        self.binaries().iter().find_map(|bin| {
+            PT_DIRS
+                .iter()
+                .map(|dir| format!("{}/{}", dir, bin))
+                .find(|path| Path::new(path).exists())
+        })
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct TransportStatus {
+    pub transport: Transport,
+    pub binary: Option<String>,
+    pub builtin_bridges: usize,
+}
+
+/// Reports which pluggable transport clients are installed
+pub fn detect_transports() -> Vec<TransportStatus> {
+    Transport::ALL
+        .into_iter()
+        .map(|transport| TransportStatus {
+            transport,
+            binary: transport.find_binary(),
+            builtin_bridges: transport.builtin().len(),
+        })
+        .collect()
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct Bridge {
+    /// `None` for a vanilla (unobfuscated) bridge
+    pub transport: Option<Transport>,
+    pub address: String,
+    pub fingerprint: Option<String>,
+    pub args: Vec<String>,
+}
+
+impl Bridge {
+    /// Parses "[Bridge] [transport] IP:ORPort [fingerprint] [k=v ...]"
+    pub fn parse(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let line = line.strip_prefix("Bridge ").unwrap_or(line);
+        let mut tokens = line.split_whitespace().peekable();
+
+        let first = tokens
+            .peek()
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Empty bridge line"))?;
+        let transport = if first.parse::<SocketAddr>().is_ok() {
+            None
+        } else {
+            tokens.next();
+            Some(
+                Transport::from_keyword(first)
+                    .ok_or_else(|| anyhow::anyhow!("Unsupported transport \"{}\"", first))?,
+            )
+        };
+
+        let address = tokens
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Bridge line has no address"))?;
+        address
+            .parse::<SocketAddr>()
+            .map_err(|_| anyhow::anyhow!("Invalid bridge address \"{}\"", address))?;
+
+        let fingerprint = match tokens.peek() {
+            Some(t) if !t.contains('=') => {
+                let fp = tokens.next().unwrap_or_default();
+                if fp.len() != 40 || !fp.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(anyhow::anyhow!("Invalid bridge fingerprint \"{}\"", fp));
+                }
+                Some(fp.to_uppercase())
+            }
+            _ => None,
+        };
+
+        let args: Vec<String> = tokens.map(String::from).collect();
+        if let Some(bad) = args.iter().find(|a| !a.contains('=')) {
+            return Err(anyhow::anyhow!("Invalid bridge argument \"{}\"", bad));
+        }
+        if let Some(t) = transport {
+            for required in t.required_args() {
+                if !args
+                    .iter()
+                    .any(|a| a.starts_with(&format!("{}=", required)))
+                {
+                    return Err(anyhow::anyhow!(
+                        "{} bridge is missing \"{}=\"",
+                        t.keyword(),
+                        required
+                    ));
+                }
+            }
+        }
+
+        Ok(Self {
+            transport,
+            address: address.to_string(),
+            fingerprint,
+            args,
+        })
+    }
+
+    pub fn to_line(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(t) = self.transport {
+            parts.push(t.keyword().to_string());
+        }
+        parts.push(self.address.clone());
+        if let Some(fp) = &self.fingerprint {
+            parts.push(fp.clone());
+        }
+        parts.extend(self.args.iter().cloned());
+        parts.join(" ")
+    }
+}
+
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct BridgeSettings {
+    pub enabled: bool,
+    /// Use Tor Browser's default bridges for this transport
+    pub builtin: Option<Transport>,
+    /// User-supplied bridge lines
+    pub bridges: Vec<String>,
+}
+
+impl BridgeSettings {
+    /// Parses every configured bridge, built-in ones included
+    pub fn resolve(&self) -> Result<Vec<Bridge>> {
+        let mut bridges = Vec::new();
+
+        if let Some(t) = self.builtin {
+            if t.builtin().is_empty() {
+                return Err(anyhow::anyhow!(
+                    "There are no built-in {} bridges, paste bridge lines instead",
+                    t.keyword()
+                ));
+            }
+            for line in t.builtin() {
+                bridges.push(Bridge::parse(line)?);
+            }
+        }
+
+        for line in self.bridges.iter().filter(|l| !l.trim().is_empty()) {
+            bridges.push(Bridge::parse(line)?);
+        }
+
+        if self.enabled && bridges.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Bridges are enabled but none are configured"
+            ));
+        }
+        Ok(bridges)
+    }
+
+    /// Checks the bridges and that every transport they use is installed
+    pub fn validate(&self) -> Result<()> {
+        for t in self.transports()? {
+            if t.find_binary().is_none() {
+                return Err(anyhow::anyhow!(
+                    "No {} client installed (looked for {})",
+                    t.keyword(),
+                    t.binaries().join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn transports(&self) -> Result<Vec<Transport>> {
+        let mut transports: Vec<Transport> = Vec::new();
+        for t in self.resolve()?.into_iter().filter_map(|b| b.transport) {
+            if !transports.contains(&t) {
+                transports.push(t);
+            }
+        }
+        Ok(transports)
+    }
+
+    /// Lines for the Aegis torrc drop-in
+    pub fn torrc_lines(&self) -> Vec<String> {
+        let bridges = match self.resolve() {
+            Ok(b) if self.enabled => b,
+            _ => return Vec::new(),
+        };
+
+        let mut lines = vec!["UseBridges 1".to_string()];
+
+        // One ClientTransportPlugin line per binary, listing its transports
+        let mut plugins: Vec<(String, Vec<&str>)> = Vec::new();
+        for t in self.transports().unwrap_or_default() {
+            let binary = t
+                .find_binary()
+                .unwrap_or_else(|| format!("{}/{}", PT_DIRS[0], t.binaries()[0]));
+            match plugins.iter_mut().find(|(b, _)| *b == binary) {
+                Some((_, names)) => names.push(t.keyword()),
+                None => plugins.push((binary, vec![t.keyword()])),
+            }
+        }
+        for (binary, names) in plugins {
+            lines.push(format!(
+                "ClientTransportPlugin {} exec {}",
+                names.join(","),
+                binary
+            ));
+        }
+
+        lines.extend(bridges.iter().map(|b| format!("Bridge {}", b.to_line())));
+        lines
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct BootstrapStatus {
+    pub progress: u8,
+    pub tag: String,
+    pub summary: String,
+    pub warning: Option<String>,
+    pub reason: Option<String>,
+    pub hint: Option<String>,
+}
+
+/// Reads Tor's bootstrap phase and, when it is stuck with a warning,
+/// adds a hint specific to the bridges in use
+pub fn bootstrap_status(settings: &BridgeSettings) -> Result<BootstrapStatus> {
+    // "NOTICE BOOTSTRAP PROGRESS=75 TAG=enough_dirinfo SUMMARY="..." [WARNING=... REASON=...]"
+    let phase = TorControl::connect()?.getinfo("status/bootstrap-phase")?;
+    let kv = torctl::keyword_args(&phase);
+
+    let warning = kv.get("WARNING").cloned();
+    let reason = kv.get("REASON").cloned();
+
+    let hint = if warning.is_none() {
+        None
+    } else if settings.enabled {
+        let transports = settings.transports().unwrap_or_default();
+        let hints: Vec<&str> = transports.iter().map(|t| t.hint()).collect();
+        if hints.is_empty() {
+            Some(
+                "Vanilla bridges are easy to block. Try obfs4, snowflake or webtunnel.".to_string(),
+            )
+        } else {
+            Some(hints.join(" "))
+        }
+    } else {
+        Some("Tor can't reach the network directly. If it is censored, enable bridges.".to_string())
+    };
+
+    Ok(BootstrapStatus {
+        progress: kv.get("PROGRESS").and_then(|p| p.parse().ok()).unwrap_or(0),
+        tag: kv.get("TAG").cloned().unwrap_or_default(),
+        summary: kv.get("SUMMARY").cloned().unwrap_or_default(),
+        warning,
+        reason,
+        hint,
+    })
+}
@@ -0,0 +1,187 @@
+use crate::geoip::GeoIpManager;
+use crate::torctl::{self, TorControl};
+use anyhow::Result;
+use std::net::Ipv4Addr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, serde::Serialize)]
+pub struct Relay {
+    pub fingerprint: String,
+    pub nickname: String,
+    pub ip: Option<String>,
+    pub country: Option<String>,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct Circuit {
+    pub id: String,
+    pub status: String,
+    pub purpose: String,
+    pub age_secs: Option<u64>,
+    pub hops: Vec<Relay>,
+    pub build_flags: Vec<String>,
+    /// Set for circuits isolated by SOCKS authentication
+    pub socks_username: Option<String>,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct Stream {
+    pub id: String,
+    pub status: String,
+    pub circuit_id: String,
+    pub target: String,
+}
+
+/// Lists Tor's circuits with each hop resolved through the consensus
+pub fn get_circuits(geoip: &GeoIpManager) -> Result<Vec<Circuit>> {
+    let mut ctl = TorControl::connect()?;
+    let status = ctl.getinfo("circuit-status")?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut circuits = Vec::new();
+    for line in status.lines().filter(|l| !l.trim().is_empty()) {
+        let args = torctl::split_args(line);
+        if args.len() < 2 {
+            continue;
+        }
+        let kv = torctl::keyword_args(line);
+
+        // "ID STATUS [PATH] [KEY=VALUE ...]" - PATH is missing while LAUNCHED
+        let hops = match args.get(2) {
+            Some(path) if path.starts_with('$') => path
+                .split(',')
+                .map(|hop| resolve_relay(&mut ctl, geoip, hop))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        circuits.push(Circuit {
+            id: args[0].clone(),
+            status: args[1].clone(),
+            purpose: kv.get("PURPOSE").cloned().unwrap_or_default(),
+            age_secs: kv
+                .get("TIME_CREATED")
+                .and_then(|t| parse_iso_time(t))
+                .map(|created| now.saturating_sub(created)),
+            hops,
+            build_flags: kv
+                .get("BUILD_FLAGS")
+                .map(|f| f.split(',').map(String::from).collect())
+                .unwrap_or_default(),
+            socks_username: kv.get("SOCKS_USERNAME").cloned(),
+        });
+    }
+
+    Ok(circuits)
+}
+
+/// Lists active streams and the circuits carrying them
+pub fn get_streams() -> Result<Vec<Stream>> {
+    let mut ctl = TorControl::connect()?;
+    let status = ctl.getinfo("stream-status")?;
+
+    Ok(status
+        .lines()
+        .filter_map(|line| {
+            // "StreamID StreamStatus CircuitID Target"
+            let mut parts = line.split_whitespace();
+            Some(Stream {
+                id: parts.next()?.to_string(),
+                status: parts.next()?.to_string(),
+                circuit_id: parts.next()?.to_string(),
+                target: parts.next()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Tears down a circuit; streams on it are moved to new circuits by Tor
+pub fn close_circuit(id: &str) -> Result<()> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+        return Err(anyhow::anyhow!("Invalid circuit id: {}", id));
+    }
+
+    TorControl::connect()?.command(&format!("CLOSECIRCUIT {}", id))?;
+    Ok(())
+}
+
+/// Resolves a "$FINGERPRINT~nickname" path entry via the "ns/id/*" router status
+fn resolve_relay(ctl: &mut TorControl, geoip: &GeoIpManager, hop: &str) -> Relay {
+    let hop = hop.trim_start_matches('$');
+    let (fingerprint, nickname) = match hop.split_once(['~', '=']) {
+        Some((fp, nick)) => (fp.to_string(), nick.to_string()),
+        None => (hop.to_string(), String::new()),
+    };
+
+    // "r <nickname> <identity> <digest> <date> <time> <IP> <ORPort> <DirPort>"
+    let ip = ctl
+        .getinfo(&format!("ns/id/{}", fingerprint))
+        .ok()
+        .and_then(|ns| {
+            ns.lines()
+                .find(|l| l.starts_with("r "))
+                .and_then(|r| r.split_whitespace().nth(6).map(String::from))
+        });
+
+    let country = ip
+        .as_deref()
+        .and_then(|ip| ip.parse::<Ipv4Addr>().ok())
+        .and_then(|ip| geoip.country_of(ip));
+
+    Relay {
+        fingerprint,
+        nickname,
+        ip,
+        country,
+    }
+}
+
+/// Parses Tor's "YYYY-MM-DDTHH:MM:SS[.frac]" UTC timestamps into Unix seconds
+pub fn parse_iso_time(value: &str) -> Option<u64> {
+    let (date, time) = value.split_once(['T', ' '])?;
+    let mut d = date.split('-').map(|p| p.parse::<i64>());
+    let (y, m, day) = (d.next()?.ok()?, d.next()?.ok()?, d.next()?.ok()?);
+    let mut t = time.split('.').next()?.split(':').map(|p| p.parse::<i64>());
+    let (hh, mm, ss) = (t.next()?.ok()?, t.next()?.ok()?, t.next()?.ok()?);
+
+    // Days since the Unix epoch (proleptic Gregorian calendar)
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    u64::try_from(days * 86400 + hh * 3600 + mm * 60 + ss).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iso_time_as_tor_writes_it() {
+        // TIME_CREATED carries microseconds
+        assert_eq!(parse_iso_time("1970-01-01T00:00:00.000000"), Some(0));
+        assert_eq!(
+            parse_iso_time("2024-02-29T12:34:56.789012"),
+            Some(1709210096)
+        );
+    }
+
+    #[test]
+    fn iso_time_around_leap_days_and_new_year() {
+        assert_eq!(parse_iso_time("2000-03-01 00:00:00"), Some(951868800));
+        assert_eq!(parse_iso_time("2023-01-31T23:59:59"), Some(1675209599));
+    }
+
+    #[test]
+    fn iso_time_rejects_garbage_and_pre_epoch_dates() {
+        assert_eq!(parse_iso_time("2024-02-29"), None);
+        assert_eq!(parse_iso_time("yesterday at noon"), None);
+        assert_eq!(parse_iso_time("1969-12-31T23:59:59"), None);
+    }
+}
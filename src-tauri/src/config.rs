@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// Aegis runs as root (see run.sh), so state lives in a system location
+// that survives GUI crashes and reboots.
+const DATA_DIR: &str = "/var/lib/aegis";
+const CONFIG_FILE: &str = "config.json";
+
+/// Returns the path of a file inside the Aegis data directory
+pub fn data_path(name: &str) -> PathBuf {
+    PathBuf::from(DATA_DIR).join(name)
+}
+
+/// Creates the data directory if needed and returns the path of `name` in it
+pub fn ensure_data_path(name: &str) -> Result<PathBuf> {
+    fs::create_dir_all(DATA_DIR).context("Failed to create Aegis data directory")?;
+    Ok(data_path(name))
+}
+
+/// User settings persisted across restarts
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AegisConfig {
+    pub rotation: crate::rotation::RotationSettings,
+    pub node_policy: crate::nodes::NodePolicy,
+    pub bridges: crate::bridges::BridgeSettings,
+    pub proxy: crate::proxy::ProxySettings,
+    pub allowlist: crate::allowlist::Allowlist,
+    pub apps: crate::apps::AppRules,
+    pub users: crate::users::UserRules,
+    pub isolation: crate::isolation::IsolationSettings,
+    pub portal: crate::portal::PortalSettings,
+    pub leak_test: crate::leaktest::LeakTestSettings,
+    pub app_firewall: crate::appfirewall::AppFirewallSettings,
+    pub leak_monitor: crate::leakmon::LeakMonitorSettings,
+    pub network_watch: crate::netwatch::NetworkWatchSettings,
+    pub network_profiles: crate::profiles::NetworkProfiles,
+    pub shield: crate::anonsurf::ShieldSettings,
+    pub time_sync: crate::timesync::TimeSyncSettings,
+    /// Plain-text "what is my IP" service, queried through the shield
+    pub echo_url: String,
+}
+
+impl Default for AegisConfig {
+    fn default() -> Self {
+        Self {
+            rotation: Default::default(),
+            node_policy: Default::default(),
+            bridges: Default::default(),
+            proxy: Default::default(),
+            allowlist: Default::default(),
+            apps: Default::default(),
+            users: Default::default(),
+            isolation: Default::default(),
+            portal: Default::default(),
+            leak_test: Default::default(),
+            app_firewall: Default::default(),
+            leak_monitor: Default::default(),
+            network_watch: Default::default(),
+            network_profiles: Default::default(),
+            shield: Default::default(),
+            time_sync: Default::default(),
+            echo_url: "https://api.ipify.org".to_string(),
+        }
+    }
+}
+
+impl AegisConfig {
+    /// Loads the saved configuration, falling back to defaults
+    pub fn load() -> Self {
+        let mut config: Self = fs::read_to_string(data_path(CONFIG_FILE))
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        // Older configs kept the password inline; the next save moves it out
+        if let Some(password) = crate::proxy::load_password() {
+            config.proxy.password = Some(password);
+        }
+        config
+    }
+
+    pub fn save(&self) -> Result<()> {
+        crate::proxy::store_password(self.proxy.password.as_deref())?;
+        let path = ensure_data_path(CONFIG_FILE)?;
+        fs::write(path, serde_json::to_string_pretty(self)?).context("Failed to save Aegis config")
+    }
+}
+
+pub struct ConfigState {
+    pub config: Mutex<AegisConfig>,
+}
+
+impl ConfigState {
+    pub fn new(config: AegisConfig) -> Self {
+        Self {
+            config: Mutex::new(config),
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use crate::config;
+use anyhow::{Context, Result};
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+const RESOLV_BACKUP: &str = "resolv.conf";
+const RESOLVED_DROPIN_DIR: &str = "/etc/systemd/resolved.conf.d";
+const RESOLVED_DROPIN: &str = "/etc/systemd/resolved.conf.d/aegis.conf";
+const RESOLVED_STUB: &str = "127.0.0.53";
+const MANAGED_HEADER: &str = "# Managed by Aegis Privacy Shield. Restored when the shield stops.";
+
+/// How the system resolves names
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResolverSetup {
+    /// systemd-resolved owns resolv.conf (stub or upstream list)
+    Resolved,
+    /// resolv.conf lists nameservers directly
+    ResolvConf,
+}
+
+/// What `redirect` changed, recorded in the shield session
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct DnsBackup {
+    pub setup: ResolverSetup,
+    /// resolv.conf was a symlink to this file (e.g. NetworkManager's)
+    pub symlink: Option<PathBuf>,
+    /// Copy of a regular resolv.conf
+    pub copy: Option<PathBuf>,
+}
+
+/// Detects the resolver setup from resolv.conf and the resolved service
+pub fn detect() -> ResolverSetup {
+    let link = fs::read_link(RESOLV_CONF).ok();
+    let managed_by_resolved = link
+        .as_ref()
+        .map(|l| l.to_string_lossy().contains("systemd/resolve"))
+        .unwrap_or(false)
+        || nameservers().iter().any(|n| n == RESOLVED_STUB);
+
+    if managed_by_resolved && resolved_active() {
+        ResolverSetup::Resolved
+    } else {
+        ResolverSetup::ResolvConf
+    }
+}
+
+/// Saves what `redirect` is going to change. Nothing is modified yet.
+pub fn backup() -> Result<DnsBackup> {
+    let setup = detect();
+    let mut backup = DnsBackup {
+        setup,
+        symlink: None,
+        copy: None,
+    };
+
+    if setup == ResolverSetup::ResolvConf {
+        match fs::read_link(RESOLV_CONF) {
+            Ok(target) => backup.symlink = Some(target),
+            Err(_) => {
+                let copy = config::ensure_data_path(RESOLV_BACKUP)?;
+                fs::copy(RESOLV_CONF, &copy).context("Failed to back up resolv.conf")?;
+                backup.copy = Some(copy);
+            }
+        }
+    }
+
+    Ok(backup)
+}
+
+/// Points the system resolver at Tor's DNSPort. resolv.conf can't carry a
+/// port, so it gets 127.0.0.1 and the firewall redirects port 53 there.
+pub fn redirect(backup: &DnsBackup, dns_port: &str) -> Result<()> {
+    match backup.setup {
+        ResolverSetup::Resolved => {
+            fs::create_dir_all(RESOLVED_DROPIN_DIR)
+                .context("Failed to create resolved drop-in directory")?;
+            // Tor's DNSPort speaks neither DNSSEC nor TLS, and LLMNR/mDNS
+            // would send names onto the LAN
+            let dropin = format!(
+                "{}\n[Resolve]\nDNS=127.0.0.1:{}\nDomains=~.\nDNSSEC=no\nDNSOverTLS=no\nLLMNR=no\nMulticastDNS=no\n",
+                MANAGED_HEADER, dns_port
+            );
+            fs::write(RESOLVED_DROPIN, dropin).context("Failed to write resolved drop-in")?;
+            restart_resolved()
+        }
+        ResolverSetup::ResolvConf => {
+            if backup.symlink.is_some() {
+                fs::remove_file(RESOLV_CONF).context("Failed to unlink resolv.conf")?;
+            }
+            fs::write(
+                RESOLV_CONF,
+                format!("{}\nnameserver 127.0.0.1\n", MANAGED_HEADER),
+            )
+            .context("Failed to write resolv.conf")
+        }
+    }
+}
+
+/// Undoes `redirect`
+pub fn restore(backup: &DnsBackup) -> Result<()> {
+    match backup.setup {
+        ResolverSetup::Resolved => {
+            match fs::remove_file(RESOLVED_DROPIN) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e).context("Failed to remove resolved drop-in")
+                }
+                _ => {}
+            }
+            restart_resolved()
+        }
+        ResolverSetup::ResolvConf => match (&backup.symlink, &backup.copy) {
+            (Some(target), _) => {
+                let _ = fs::remove_file(RESOLV_CONF);
+                symlink(target, RESOLV_CONF).context("Failed to restore resolv.conf symlink")
+            }
+            (None, Some(copy)) => {
+                fs::copy(copy, RESOLV_CONF).context("Failed to restore resolv.conf")?;
+                Ok(())
+            }
+            (None, None) => Ok(()),
+        },
+    }
+}
+
+/// Human-readable description of where names are resolved right now
+pub fn active_resolver() -> String {
+    if Path::new(RESOLVED_DROPIN).exists() {
+        return "systemd-resolved → Tor DNSPort".to_string();
+    }
+    if fs::read_to_string(RESOLV_CONF)
+        .map(|c| c.starts_with(MANAGED_HEADER))
+        .unwrap_or(false)
+    {
+        return "127.0.0.1 → Tor DNSPort".to_string();
+    }
+
+    match detect() {
+        ResolverSetup::Resolved => match resolved_current_server() {
+            Some(server) => format!("systemd-resolved ({})", server),
+            None => "systemd-resolved".to_string(),
+        },
+        ResolverSetup::ResolvConf => {
+            let servers = nameservers();
+            if servers.is_empty() {
+                "None".to_string()
+            } else {
+                servers.join(", ")
+            }
+        }
+    }
+}
+
+/// The resolvers the network handed out (DHCP or static), which the
+/// shield has replaced with Tor's DNSPort. Loopback servers are skipped.
+pub fn upstream_servers() -> Vec<String> {
+    let per_link = Command::new("resolvectl")
+        .arg("dns")
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).into_owned())
+        .unwrap_or_default();
+    let mut servers: Vec<String> = per_link
+        .lines()
+        .filter(|l| l.starts_with("Link"))
+        .filter_map(|l| l.split_once("):"))
+        .flat_map(|(_, list)| list.split_whitespace().map(String::from))
+        .collect();
+
+    if servers.is_empty() {
+        let files = [
+            PathBuf::from("/run/NetworkManager/no-stub-resolv.conf"),
+            PathBuf::from("/run/NetworkManager/resolv.conf"),
+            config::data_path(RESOLV_BACKUP),
+        ];
+        servers = files.iter().flat_map(|f| nameservers_in(f)).collect();
+    }
+
+    servers.retain(|s| {
+        s.parse::<std::net::IpAddr>()
+            .map(|ip| !ip.is_loopback())
+            .unwrap_or(false)
+    });
+    servers.dedup();
+    servers
+}
+
+fn nameservers() -> Vec<String> {
+    nameservers_in(Path::new(RESOLV_CONF))
+}
+
+fn nameservers_in(path: &Path) -> Vec<String> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("nameserver"))
+        .map(|s| s.trim().to_string())
+        .collect()
+}
+
+fn resolved_active() -> bool {
+    Command::new("systemctl")
+        .args(["is-active", "--quiet", "systemd-resolved"])
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+fn resolved_current_server() -> Option<String> {
+    let output = Command::new("resolvectl").arg("status").output().ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("Current DNS Server:"))
+        .map(|s| s.trim().to_string())
+}
+
+/// Applies the drop-in and drops answers cached from before the switch
+fn restart_resolved() -> Result<()> {
+    let status = Command::new("systemctl")
+        .args(["restart", "systemd-resolved"])
+        .status()
+        .context("Failed to restart systemd-resolved")?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("Failed to restart systemd-resolved"));
+    }
+    let _ = Command::new("resolvectl").arg("flush-caches").status();
+    Ok(())
+}
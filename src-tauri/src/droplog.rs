@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+// Kernel log prefix of packets the killswitch is about to drop
+const LOG_PREFIX: &str = "AEGIS-DROP: ";
+// The kernel log is shared, so the rules themselves are rate limited too
+const LOG_LIMIT: &str = "-m limit --limit 10/sec --limit-burst 20";
+const FLUSH_INTERVAL: u64 = 2; // seconds
+const MAX_EVENTS_PER_FLUSH: usize = 20;
+
+#[derive(Clone, serde::Serialize)]
+pub struct BlockedConnection {
+    /// Process name, or "uid N" when the socket was already gone
+    pub app: String,
+    pub pid: Option<u32>,
+    pub destination: String,
+    pub port: Option<u16>,
+    pub protocol: String,
+    /// Drops folded into this event since the last one
+    pub count: u32,
+}
+
+/// One parsed kernel log line
+struct DroppedPacket {
+    destination: String,
+    protocol: String,
+    source_port: Option<u16>,
+    port: Option<u16>,
+    uid: Option<u32>,
+}
+
+/// LOG rules for the end of OUTPUT, i.e. just before the DROP policy.
+/// IPv6 loopback isn't accepted anywhere, so it is left out explicitly.
+pub fn log_rules() -> Vec<String> {
+    ["iptables", "ip6tables"]
+        .iter()
+        .map(|cmd| {
+            format!(
+                "{} -A OUTPUT ! -o lo {} -j LOG --log-prefix '{}' --log-uid",
+                cmd, LOG_LIMIT, LOG_PREFIX
+            )
+        })
+        .collect()
+}
+
+/// Follows the kernel log and emits rate-limited `blocked_connection`
+/// events, attributing each drop to the process owning the socket
+pub fn start_drop_monitor(app: AppHandle) {
+    let (tx, rx) = mpsc::channel::<BlockedConnection>();
+
+    thread::spawn(move || {
+        let mut kmsg = match File::open("/dev/kmsg") {
+            Ok(f) => f,
+            Err(e) => {
+                println!("Drop monitor disabled, can't read /dev/kmsg: {}", e);
+                return;
+            }
+        };
+        // Only drops from now on, not the whole ring buffer
+        let _ = kmsg.seek(SeekFrom::End(0));
+
+        let mut reader = BufReader::new(kmsg);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line) {
+                Ok(0) => thread::sleep(Duration::from_millis(200)),
+                Ok(_) => {
+                    if let Some(drop) = parse(&line) {
+                        if tx.send(attribute(drop)).is_err() {
+                            return;
+                        }
+                    }
+                }
+                // EPIPE: records were overwritten before we read them
+                Err(_) => continue,
+            }
+        }
+    });
+
+    thread::spawn(move || {
+        let mut pending: HashMap<(String, String, Option<u16>, String), BlockedConnection> =
+            HashMap::new();
+        let mut last_flush = Instant::now();
+
+        loop {
+            match rx.recv_timeout(Duration::from_secs(FLUSH_INTERVAL)) {
+                Ok(blocked) => {
+                    let key = (
+                        blocked.app.clone(),
+                        blocked.destination.clone(),
+                        blocked.port,
+                        blocked.protocol.clone(),
+                    );
+                    pending
+                        .entry(key)
+                        .and_modify(|b| {
+                            b.count += 1;
+                            b.pid = blocked.pid.or(b.pid);
+                        })
+                        .or_insert(blocked);
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+
+            if last_flush.elapsed().as_secs() < FLUSH_INTERVAL {
+                continue;
+            }
+            last_flush = Instant::now();
+
+            let mut batch: Vec<BlockedConnection> = pending.drain().map(|(_, b)| b).collect();
+            batch.sort_by_key(|b| std::cmp::Reverse(b.count));
+            let dropped = batch.len().saturating_sub(MAX_EVENTS_PER_FLUSH);
+            for blocked in batch.into_iter().take(MAX_EVENTS_PER_FLUSH) {
+                let _ = app.emit("blocked_connection", blocked);
+            }
+            if dropped > 0 {
+                let _ = app.emit(
+                    "app_log",
+                    format!("{} more blocked destinations not shown", dropped),
+                );
+            }
+        }
+    });
+}
+
+/// Parses "<prio>,<seq>,<ts>,-;AEGIS-DROP: IN= OUT=eth0 ... DST=1.1.1.1 ... PROTO=TCP SPT=5 DPT=443 UID=1000"
+fn parse(record: &str) -> Option<DroppedPacket> {
+    let message = record.split_once(';')?.1;
+    let fields = message.strip_prefix(LOG_PREFIX.trim_end())?;
+
+    let mut values = HashMap::new();
+    for field in fields.split_whitespace() {
+        if let Some((key, value)) = field.split_once('=') {
+            values.insert(key, value);
+        }
+    }
+
+    Some(DroppedPacket {
+        destination: values.get("DST")?.to_string(),
+        protocol: values.get("PROTO").unwrap_or(&"?").to_string(),
+        source_port: values.get("SPT").and_then(|p| p.parse().ok()),
+        port: values.get("DPT").and_then(|p| p.parse().ok()),
+        uid: values.get("UID").and_then(|u| u.parse().ok()),
+    })
+}
+
+fn attribute(drop: DroppedPacket) -> BlockedConnection {
+    let process = drop
+        .source_port
+        .and_then(|port| socket_inode(&drop.protocol, port))
+        .and_then(socket_owner);
+
+    let (app, pid) = match process {
+        Some((pid, name)) => (name, Some(pid)),
+        None => match drop.uid {
+            Some(uid) => (format!("uid {}", uid), None),
+            None => ("unknown".to_string(), None),
+        },
+    };
+
+    BlockedConnection {
+        app,
+        pid,
+        destination: drop.destination,
+        port: drop.port,
+        protocol: drop.protocol,
+        count: 1,
+    }
+}
+
+/// A local socket as listed in /proc/net
+pub struct SocketInfo {
+    pub inode: u64,
+    pub uid: u32,
+}
+
+/// Finds the local socket bound to `port` in /proc/net
+pub fn socket_info(protocol: &str, port: u16) -> Option<SocketInfo> {
+    let tables: &[&str] = match protocol {
+        "TCP" => &["/proc/net/tcp", "/proc/net/tcp6"],
+        "UDP" => &["/proc/net/udp", "/proc/net/udp6"],
+        _ => return None,
+    };
+
+    tables.iter().find_map(|table| {
+        fs::read_to_string(table)
+            .ok()?
+            .lines()
+            .skip(1)
+            .find_map(|line| {
+                // "sl local_address rem_address st ... uid timeout inode"
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let local_port = fields.get(1)?.rsplit_once(':')?.1;
+                if u16::from_str_radix(local_port, 16).ok()? != port {
+                    return None;
+                }
+                Some(SocketInfo {
+                    inode: fields.get(9)?.parse().ok().filter(|inode| *inode != 0)?,
+                    uid: fields.get(7)?.parse().ok()?,
+                })
+            })
+    })
+}
+
+pub fn socket_inode(protocol: &str, port: u16) -> Option<u64> {
+    socket_info(protocol, port).map(|s| s.inode)
+}
+
+/// Finds the process holding a socket by scanning /proc/<pid>/fd
+pub fn socket_owner(inode: u64) -> Option<(u32, String)> {
+    fs::read_dir("/proc").ok()?.flatten().find_map(|entry| {
+        let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+        if !holds_socket(pid, inode) {
+            return None;
+        }
+        let name = fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+        Some((pid, name.trim().to_string()))
+    })
+}
+
+/// Whether one of the process's file descriptors is the socket
+pub fn holds_socket(pid: u32, inode: u64) -> bool {
+    let target = format!("socket:[{}]", inode);
+    let fds = match fs::read_dir(format!("/proc/{}/fd", pid)) {
+        Ok(fds) => fds,
+        Err(_) => return false,
+    };
+    fds.flatten().any(|fd| {
+        fs::read_link(fd.path())
+            .map(|l| l.to_string_lossy() == target)
+            .unwrap_or(false)
+    })
+}
@@ -1,33 +1,51 @@
 use anyhow::Result;
 use ipnet::Ipv4Net;
 use reqwest::blocking::Client;
+use std::collections::HashMap;
 use std::net::Ipv4Addr;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime};
 
-const ISRAEL_CIDR_URL: &str =
-    "https://raw.githubusercontent.com/herrbischoff/country-ip-blocks/master/ipv4/il.cidr";
-const CACHE_FILE: &str = "/tmp/aegis_il_cidr.txt"; // In prod, use AppData
+const COUNTRY_CIDR_URL: &str =
+    "https://raw.githubusercontent.com/herrbischoff/country-ip-blocks/master/ipv4/{cc}.cidr";
+const CACHE_FILE: &str = "/tmp/aegis_{cc}_cidr.txt"; // In prod, use AppData
 const UPDATE_INTERVAL: u64 = 86400; // 24 hours
+// Tor's own IPv4 database ("INTIPLOW,INTIPHIGH,CC"), shipped with the tor package
+const TOR_GEOIP_FILE: &str = "/usr/share/tor/geoip";
+// Countries the honeypot responds to
+const DEFAULT_TARGETS: &[&str] = &["il"];
+
+type CidrMap = HashMap<String, Vec<Ipv4Net>>;
 
 pub struct GeoIpManager {
-    cidrs: Arc<Mutex<Vec<Ipv4Net>>>,
+    targets: Vec<String>,
+    cidrs: Arc<Mutex<CidrMap>>,
     last_update: Arc<Mutex<SystemTime>>,
+    // Sorted (start, end, country) ranges for lookups of any address
+    ranges: Mutex<Option<Vec<(u32, u32, String)>>>,
 }
 
 impl GeoIpManager {
     pub fn new() -> Self {
+        Self::with_targets(DEFAULT_TARGETS)
+    }
+
+    pub fn with_targets(targets: &[&str]) -> Self {
         Self {
-            cidrs: Arc::new(Mutex::new(Vec::new())),
+            targets: targets.iter().map(|cc| cc.to_lowercase()).collect(),
+            cidrs: Arc::new(Mutex::new(HashMap::new())),
             last_update: Arc::new(Mutex::new(SystemTime::UNIX_EPOCH)),
+            ranges: Mutex::new(None),
         }
     }
 
     pub fn init(&self) -> Result<()> {
         // Try to load from cache first
-        if let Ok(content) = std::fs::read_to_string(CACHE_FILE) {
-            self.parse_and_update(&content);
-            println!("Loaded GeoIP data from cache.");
+        for cc in &self.targets {
+            if let Ok(content) = std::fs::read_to_string(cache_file(cc)) {
+                self.parse_and_update(cc, &content);
+                println!("Loaded GeoIP data for {} from cache.", cc);
+            }
         }
 
         // Check if we need update
@@ -36,6 +54,7 @@ impl GeoIpManager {
     }
 
     fn update_if_needed(&self) {
+        let targets = self.targets.clone();
         let cidrs = self.cidrs.clone();
         let last_update = self.last_update.clone();
 
@@ -50,49 +69,112 @@ impl GeoIpManager {
             };
 
             if should_update {
-                println!("Fetching fresh GeoIP data for Israel...");
                 let client = Client::builder()
                     .timeout(Duration::from_secs(10))
                     .build()
                     .unwrap();
-                if let Ok(resp) = client.get(ISRAEL_CIDR_URL).send() {
-                    if let Ok(text) = resp.text() {
-                        // Cache it
-                        let _ = std::fs::write(CACHE_FILE, &text);
-
-                        // Parse
-                        let mut list = cidrs.lock().unwrap();
-                        list.clear();
-                        for line in text.lines() {
-                            if let Ok(net) = line.trim().parse::<Ipv4Net>() {
-                                list.push(net);
-                            }
+
+                for cc in &targets {
+                    println!("Fetching fresh GeoIP data for {}...", cc);
+                    let url = COUNTRY_CIDR_URL.replace("{cc}", cc);
+                    if let Ok(resp) = client.get(url).send() {
+                        if let Ok(text) = resp.text() {
+                            // Cache it
+                            let _ = std::fs::write(cache_file(cc), &text);
+
+                            // Parse
+                            let list = parse_cidrs(&text);
+                            println!("GeoIP data for {} updated. {} prefixes loaded.", cc, list.len());
+                            cidrs.lock().unwrap().insert(cc.clone(), list);
                         }
-                        *last_update.lock().unwrap() = SystemTime::now();
-                        println!("GeoIP data updated. {} prefixes loaded.", list.len());
                     }
                 }
+                *last_update.lock().unwrap() = SystemTime::now();
             }
         });
     }
 
-    fn parse_and_update(&self, content: &str) {
-        let mut list = self.cidrs.lock().unwrap();
-        list.clear();
-        for line in content.lines() {
-            if let Ok(net) = line.trim().parse::<Ipv4Net>() {
-                list.push(net);
+    fn parse_and_update(&self, cc: &str, content: &str) {
+        self.cidrs
+            .lock()
+            .unwrap()
+            .insert(cc.to_string(), parse_cidrs(content));
+    }
+
+    /// Returns the lowercase country code of any IPv4 address, if known
+    pub fn country_of(&self, ip: Ipv4Addr) -> Option<String> {
+        let cidrs = self.cidrs.lock().unwrap();
+        for (cc, list) in cidrs.iter() {
+            if list.iter().any(|net| net.contains(&ip)) {
+                return Some(cc.clone());
+            }
+        }
+        drop(cidrs);
+
+        let mut cached = self.ranges.lock().unwrap();
+        if cached.is_none() {
+            // Only keep a usable table, so the database is picked up once
+            // tor gets installed or the file becomes readable
+            let loaded = load_tor_geoip();
+            if loaded.is_empty() {
+                return None;
             }
+            *cached = Some(loaded);
+        }
+        let ranges = cached.as_ref()?;
+        let ip = u32::from(ip);
+        let idx = ranges.partition_point(|(start, _, _)| *start <= ip);
+        if idx == 0 {
+            return None;
+        }
+
+        let (_, end, cc) = &ranges[idx - 1];
+        if ip <= *end {
+            Some(cc.to_lowercase())
+        } else {
+            None
         }
     }
 
-    pub fn is_target(&self, ip: Ipv4Addr) -> bool {
+    /// Returns the target country the address belongs to, if any
+    pub fn target_country(&self, ip: Ipv4Addr) -> Option<String> {
         let list = self.cidrs.lock().unwrap();
-        for net in list.iter() {
-            if net.contains(&ip) {
-                return true;
+        for cc in &self.targets {
+            if let Some(nets) = list.get(cc) {
+                if nets.iter().any(|net| net.contains(&ip)) {
+                    return Some(cc.clone());
+                }
             }
         }
-        false
+        None
     }
 }
+
+fn cache_file(cc: &str) -> String {
+    CACHE_FILE.replace("{cc}", cc)
+}
+
+fn parse_cidrs(content: &str) -> Vec<Ipv4Net> {
+    content
+        .lines()
+        .filter_map(|line| line.trim().parse::<Ipv4Net>().ok())
+        .collect()
+}
+
+fn load_tor_geoip() -> Vec<(u32, u32, String)> {
+    let content = std::fs::read_to_string(TOR_GEOIP_FILE).unwrap_or_default();
+    let mut ranges: Vec<(u32, u32, String)> = content
+        .lines()
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| {
+            let mut parts = l.split(',');
+            let start = parts.next()?.trim().parse().ok()?;
+            let end = parts.next()?.trim().parse().ok()?;
+            let cc = parts.next()?.trim();
+            Some((start, end, cc.to_string()))
+        })
+        .collect();
+
+    ranges.sort_by_key(|(start, _, _)| *start);
+    ranges
+}
@@ -66,7 +66,7 @@ fn handle_connection(
     };
 
     // Check if target
-    if geoip.is_target(ip) {
+    if let Some(country) = geoip.target_country(ip) {
         println!("TRACKING DETECTED from: {}", ip);
 
         // 1. Get Message
@@ -80,7 +80,11 @@ fn handle_connection(
         // 3. Log to Frontend
         let _ = app.emit(
             "security_alert",
-            format!("Blocked tracking attempt from Israel ({})", ip),
+            format!(
+                "Blocked tracking attempt from {} ({})",
+                country.to_uppercase(),
+                ip
+            ),
         );
     } else {
         // Optional: Just close or pretend to be dead
@@ -1,17 +1,125 @@
+use crate::allowlist::Allowlist;
+use crate::appfirewall;
+use crate::apps::AppGroup;
+use crate::config;
+use crate::droplog;
+use crate::netns;
+use crate::users::OwnerMatches;
 use anyhow::{Context, Result};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::process::Command;
 
+// Comment prefix of every rule the shield generates
+const RULE_TAG: &str = "aegis:";
+
+/// Saved pre-shield ruleset (iptables-save format)
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct FirewallSnapshot {
+    pub v4: PathBuf,
+    pub v6: PathBuf,
+}
+
+/// How traffic gets to Tor while the shield is up
+#[derive(Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShieldMode {
+    /// TCP and DNS are redirected into Tor's TransPort and DNSPort
+    #[default]
+    Transparent,
+    /// Nothing is redirected: only Tor, SOCKS-aware programs talking to
+    /// a SocksPort and exempt traffic get out
+    Killswitch,
+}
+
+/// Inputs of the generated ruleset
+pub struct Ruleset {
+    pub mode: ShieldMode,
+    /// SocksPorts reachable in killswitch mode, besides those on loopback
+    pub socks_ports: Vec<SocketAddr>,
+    pub tor_uid: String,
+    pub dns_port: String,
+    pub trans_port: String,
+    /// When Tor reaches the network through an upstream proxy, the Tor
+    /// user may only talk to that address
+    pub tor_egress: Option<SocketAddr>,
+    /// Destinations reached directly, bypassing Tor
+    pub allowlist: Allowlist,
+    /// Whether the Aegis cgroups exist and per-app rules can be matched
+    pub app_groups: bool,
+    pub owners: OwnerMatches,
+    pub netns: bool,
+    /// Whether new connections are queued for the application firewall
+    pub app_firewall: bool,
+    /// The captive portal user, let out directly while its exception is open
+    pub portal_uid: Option<u32>,
+}
+
+/// What `verify_rules` found missing from or added to the live ruleset
+pub struct RuleDrift {
+    pub problems: Vec<String>,
+    /// A DROP policy or most rules are gone, or something else was added
+    /// to OUTPUT: the shield is open, not just caught halfway through a
+    /// re-apply
+    pub severe: bool,
+}
+
 pub struct IptablesManager;
 
 impl IptablesManager {
-    /// Backup current iptables rules to a file
-    pub fn backup_rules() -> Result<()> {
-        // Simple backup: same as before
-        Ok(())
+    /// Backup current iptables rules to the Aegis data directory
+    pub fn backup_rules() -> Result<FirewallSnapshot> {
+        let snapshot = FirewallSnapshot {
+            v4: config::ensure_data_path("firewall.v4")?,
+            v6: config::data_path("firewall.v6"),
+        };
+
+        execute_batch(&[
+            format!("iptables-save > {}", snapshot.v4.display()),
+            format!("ip6tables-save > {}", snapshot.v6.display()),
+        ])?;
+
+        Ok(snapshot)
+    }
+
+    /// Replaces the live ruleset with a snapshot taken by `backup_rules`
+    pub fn restore_rules(snapshot: &FirewallSnapshot) -> Result<()> {
+        // iptables-restore only touches tables present in the dump, so start
+        // from a clean slate in case the snapshot was empty
+        let mut commands = flush_commands();
+        commands.push(format!("iptables-restore < {}", snapshot.v4.display()));
+        commands.push(format!("ip6tables-restore < {}", snapshot.v6.display()));
+
+        execute_batch(&commands)
     }
 
     /// Apply transparent proxy rules using batched pkexec
-    pub fn apply_rules(tor_uid: &str, dns_port: &str, trans_port: &str) -> Result<()> {
+    pub fn apply_rules(ruleset: &Ruleset) -> Result<()> {
+        execute_batch(&Self::build_rules(ruleset))
+    }
+
+    /// Rebuilds the ruleset under a live shield. The DROP policies stay in
+    /// place while the chains are flushed, so nothing leaks in between.
+    pub fn reapply_rules(ruleset: &Ruleset) -> Result<()> {
+        let mut commands = vec![
+            "iptables -t nat -F".to_string(),
+            "iptables -t mangle -F".to_string(),
+            "iptables -F".to_string(),
+            "ip6tables -F".to_string(),
+        ];
+        commands.extend(Self::build_rules(ruleset));
+
+        execute_batch(&commands)
+    }
+
+    /// Generates the transparent proxy ruleset as iptables commands
+    pub fn build_rules(ruleset: &Ruleset) -> Vec<String> {
+        let Ruleset {
+            tor_uid,
+            dns_port,
+            trans_port,
+            ..
+        } = ruleset;
         let mut commands = Vec::new();
 
         // 1. Policy: DROP EVERYTHING
@@ -28,74 +136,324 @@ impl IptablesManager {
             "iptables -A INPUT -m state --state ESTABLISHED,RELATED -j ACCEPT"
         ));
 
-        // 4. DNS Redirection (UDP) -> Tor DNSPort
-        commands.push(format!(
-            "iptables -t nat -A OUTPUT -p udp --dport 53 -j REDIRECT --to-ports {}",
-            dns_port
-        ));
+        // 4. Exempt Apps, Users and application firewall verdicts skip Tor
+        // entirely (DNS included)
+        let mut exempt = Vec::new();
+        let mut forced = Vec::new();
+        if ruleset.app_groups {
+            exempt.push(format!("-m cgroup --path {}", AppGroup::Bypass.path()));
+            forced.push(format!("-m cgroup --path {}", AppGroup::Forced.path()));
+        }
+        if let Some(uid) = ruleset.portal_uid {
+            exempt.push(format!("-m owner --uid-owner {}", uid));
+        }
+        exempt.extend(ruleset.owners.exempt.iter().cloned());
+        forced.extend(ruleset.owners.forced.iter().cloned());
+        if ruleset.app_firewall {
+            commands.extend(appfirewall::mangle_rules(tor_uid, &exempt, &forced));
+            exempt.push(appfirewall::direct_matcher());
+            forced.push(appfirewall::tor_matcher());
+        }
 
-        // 5. Allow Tor Process Output
-        commands.push(format!(
-            "iptables -A OUTPUT -m owner --uid-owner {} -j ACCEPT",
-            tor_uid
-        ));
+        for matcher in &exempt {
+            commands.extend(exempt_rules(matcher));
+        }
 
-        // 6. Transparent Proxy Redirection (TCP) -> Tor TransPort
-        commands.push(format!(
-            "iptables -t nat -A OUTPUT -p tcp -m owner --uid-owner {} -j RETURN",
-            tor_uid
-        ));
-        commands.push(format!("iptables -t nat -A OUTPUT -o lo -j RETURN"));
-        commands.push(format!(
-            "iptables -t nat -A OUTPUT -p tcp --syn -j REDIRECT --to-ports {}",
-            trans_port
-        ));
+        // 5. Forced Apps and Users always go through Tor, allowlist or not.
+        // Without redirection that means a SocksPort or nothing.
+        let transparent = ruleset.mode == ShieldMode::Transparent;
+        if !transparent {
+            for socks in &ruleset.socks_ports {
+                commands.push(format!(
+                    "iptables -A OUTPUT -d {}/32 -p tcp --dport {} -j ACCEPT",
+                    socks.ip(),
+                    socks.port()
+                ));
+            }
+        }
+        for matcher in &forced {
+            if transparent {
+                commands.extend(forced_rules(matcher, dns_port, trans_port));
+            } else {
+                commands.push(format!("iptables -A OUTPUT {} -j DROP", matcher));
+            }
+        }
 
-        // 7. Allow Redirected Output (to localhost)
-        commands.push(format!(
-            "iptables -A OUTPUT -d 127.0.0.1/32 -p tcp --dport {} -j ACCEPT",
-            trans_port
-        ));
-        commands.push(format!(
-            "iptables -A OUTPUT -d 127.0.0.1/32 -p udp --dport {} -j ACCEPT",
-            dns_port
-        ));
+        // 6. When only the forced users are shielded, everyone else is exempt
+        if ruleset.owners.only_forced {
+            commands.push(format!("iptables -t nat -A OUTPUT -j RETURN"));
+            commands.push(format!("iptables -A OUTPUT -j ACCEPT"));
+        }
+
+        // 7. DNS Redirection (UDP) -> Tor DNSPort. The DNSPort is UDP only,
+        // so TCP DNS and DNS-over-TLS are refused, making resolvers fall
+        // back to UDP instead of resolving around it.
+        if transparent {
+            commands.push(format!(
+                "iptables -t nat -A OUTPUT -p udp --dport 53 -j REDIRECT --to-ports {}",
+                dns_port
+            ));
+        }
+        for port in ["53", "853"] {
+            commands.push(format!(
+                "iptables -t nat -A OUTPUT -p tcp --dport {} ! -d 127.0.0.0/8 -j RETURN",
+                port
+            ));
+            // Tor itself may use relays listening on these ports
+            commands.push(format!(
+                "iptables -A OUTPUT -p tcp --dport {} ! -d 127.0.0.0/8 -m owner ! --uid-owner {} -j REJECT --reject-with tcp-reset",
+                port, tor_uid
+            ));
+        }
+
+        // 8. Allowlisted Destinations bypass Tor (after the DNS redirect, so
+        // an allowlisted LAN resolver still can't see queries)
+        commands.extend(ruleset.allowlist.nat_rules());
+        commands.extend(ruleset.allowlist.filter_rules());
+
+        // 9. Allow Tor Process Output (only towards the upstream proxy, if any)
+        match ruleset.tor_egress {
+            Some(proxy) => commands.push(format!(
+                "iptables -A OUTPUT -m owner --uid-owner {} -d {}/32 -p tcp --dport {} -j ACCEPT",
+                tor_uid,
+                proxy.ip(),
+                proxy.port()
+            )),
+            None => commands.push(format!(
+                "iptables -A OUTPUT -m owner --uid-owner {} -j ACCEPT",
+                tor_uid
+            )),
+        }
+
+        if transparent {
+            // 10. Transparent Proxy Redirection (TCP) -> Tor TransPort
+            commands.push(format!(
+                "iptables -t nat -A OUTPUT -p tcp -m owner --uid-owner {} -j RETURN",
+                tor_uid
+            ));
+            commands.push(format!("iptables -t nat -A OUTPUT -o lo -j RETURN"));
+            commands.push(format!(
+                "iptables -t nat -A OUTPUT -p tcp --syn -j REDIRECT --to-ports {}",
+                trans_port
+            ));
+
+            // 11. Allow Redirected Output (to localhost)
+            commands.push(format!(
+                "iptables -A OUTPUT -d 127.0.0.1/32 -p tcp --dport {} -j ACCEPT",
+                trans_port
+            ));
+            commands.push(format!(
+                "iptables -A OUTPUT -d 127.0.0.1/32 -p udp --dport {} -j ACCEPT",
+                dns_port
+            ));
+
+            // 12. Tor Namespace (host side), if one is up. The namespace
+            // relies on redirection, so it stays cut off in killswitch mode.
+            if ruleset.netns {
+                commands.extend(netns::append_rules());
+            }
+        }
 
-        // 8. IPv6 Blocking
+        // 13. Log whatever is left before the policy drops it
+        commands.extend(droplog::log_rules());
+
+        // 14. IPv6 Blocking
         commands.push(format!("ip6tables -P INPUT DROP"));
         commands.push(format!("ip6tables -P OUTPUT DROP"));
         commands.push(format!("ip6tables -P FORWARD DROP"));
 
-        execute_batch(&commands)
+        tag_rules(commands)
+    }
+
+    /// Compares the live ruleset with the one `build_rules` generates.
+    /// Tagged rules must be there exactly as generated, and the OUTPUT
+    /// chains of the filter and nat tables must hold nothing else.
+    pub fn verify_rules(ruleset: &Ruleset) -> Result<RuleDrift> {
+        let v4 = save_output("iptables-save")?;
+        let v6 = save_output("ip6tables-save")?;
+
+        let mut tagged = 0;
+        let mut policies = 0;
+        let mut checks = Vec::new();
+        let mut problems = Vec::new();
+        for command in Self::build_rules(ruleset) {
+            let words: Vec<&str> = command.split_whitespace().collect();
+            let live = if words[0] == "ip6tables" { &v6 } else { &v4 };
+
+            if let Some(tag) = words.iter().find(|w| w.starts_with(RULE_TAG)) {
+                tagged += 1;
+                // -C matches the whole rule, so a tag on an altered rule
+                // doesn't pass for the generated one
+                checks.push(format!(
+                    "{} >/dev/null 2>&1 || echo {}",
+                    command.replacen(" -A ", " -C ", 1),
+                    tag
+                ));
+            } else if let ["-P", chain, policy] = words[1..] {
+                // iptables-save lists policies as ":OUTPUT DROP [0:0]"
+                let expected = format!(":{} {}", chain, policy);
+                if !live.lines().any(|l| l.starts_with(&expected)) {
+                    policies += 1;
+                    problems.push(format!("{} {} policy is not {}", words[0], chain, policy));
+                }
+            }
+        }
+
+        let missing = script_output(&checks.join("\n"))?.lines().count();
+        if missing > 0 {
+            problems.push(format!("{} rule(s) missing or altered", missing));
+        }
+
+        let untagged: Vec<String> = untagged_output_rules(&v4, &["filter", "nat"])
+            .into_iter()
+            .chain(untagged_output_rules(&v6, &["filter"]))
+            .collect();
+        for rule in &untagged {
+            problems.push(format!("Unexpected rule {}", rule));
+        }
+
+        Ok(RuleDrift {
+            severe: policies > 0 || missing * 2 > tagged || !untagged.is_empty(),
+            problems,
+        })
+    }
+
+    /// Checks the live filter table for the Aegis DROP policy.
+    /// Runs unprivileged-first: Aegis itself is launched as root via run.sh.
+    pub fn is_killswitch_installed() -> bool {
+        let output = match Command::new("iptables").args(["-S", "OUTPUT"]).output() {
+            Ok(o) if o.status.success() => o,
+            _ => return false,
+        };
+
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .any(|l| l.trim() == "-P OUTPUT DROP")
     }
 
     /// Flush all rules using batched pkexec
     pub fn flush_rules() -> Result<()> {
-        let mut commands = Vec::new();
+        execute_batch(&flush_commands())
+    }
+}
 
-        // Reset policies
-        commands.push(format!("iptables -P INPUT ACCEPT"));
-        commands.push(format!("iptables -P OUTPUT ACCEPT"));
-        commands.push(format!("iptables -P FORWARD ACCEPT"));
+/// Marks each appended rule with its position, so `verify_rules` can
+/// find it in iptables-save output
+fn tag_rules(commands: Vec<String>) -> Vec<String> {
+    commands
+        .into_iter()
+        .enumerate()
+        .map(|(i, command)| {
+            // Right after "-A <chain>"
+            let chain_start = match command.find(" -A ") {
+                Some(pos) => pos + 4,
+                None => return command,
+            };
+            let insert_at = command[chain_start..]
+                .find(' ')
+                .map(|p| chain_start + p)
+                .unwrap_or(command.len());
+            format!(
+                "{} -m comment --comment {}{}{}",
+                &command[..insert_at],
+                RULE_TAG,
+                i,
+                &command[insert_at..]
+            )
+        })
+        .collect()
+}
 
-        // Flush & Delete chains
-        commands.push(format!("iptables -t nat -F"));
-        commands.push(format!("iptables -t nat -X"));
-        commands.push(format!("iptables -F"));
-        commands.push(format!("iptables -X"));
+/// Lets traffic matching `matcher` leave directly
+fn exempt_rules(matcher: &str) -> Vec<String> {
+    vec![
+        format!("iptables -t nat -A OUTPUT {} -j RETURN", matcher),
+        format!("iptables -A OUTPUT {} -j ACCEPT", matcher),
+    ]
+}
 
-        // Reset IPv6
-        commands.push(format!("ip6tables -P INPUT ACCEPT"));
-        commands.push(format!("ip6tables -P OUTPUT ACCEPT"));
-        commands.push(format!("ip6tables -P FORWARD ACCEPT"));
-        commands.push(format!("ip6tables -F"));
+/// Sends traffic matching `matcher` to Tor and drops whatever Tor can't
+/// carry; redirected packets leave through lo, which is accepted earlier
+fn forced_rules(matcher: &str, dns_port: &str, trans_port: &str) -> Vec<String> {
+    vec![
+        format!(
+            "iptables -t nat -A OUTPUT {} -p udp --dport 53 -j REDIRECT --to-ports {}",
+            matcher, dns_port
+        ),
+        format!(
+            "iptables -t nat -A OUTPUT {} -p tcp --syn ! -d 127.0.0.0/8 -j REDIRECT --to-ports {}",
+            matcher, trans_port
+        ),
+        format!("iptables -t nat -A OUTPUT {} -j RETURN", matcher),
+        format!("iptables -A OUTPUT {} -j DROP", matcher),
+    ]
+}
 
-        execute_batch(&commands)
+/// Commands resetting both address families to an open, empty ruleset
+fn flush_commands() -> Vec<String> {
+    let mut commands = Vec::new();
+
+    // Reset policies
+    commands.push(format!("iptables -P INPUT ACCEPT"));
+    commands.push(format!("iptables -P OUTPUT ACCEPT"));
+    commands.push(format!("iptables -P FORWARD ACCEPT"));
+
+    // Flush & Delete chains
+    commands.push(format!("iptables -t nat -F"));
+    commands.push(format!("iptables -t nat -X"));
+    commands.push(format!("iptables -t mangle -F"));
+    commands.push(format!("iptables -F"));
+    commands.push(format!("iptables -X"));
+
+    // Reset IPv6
+    commands.push(format!("ip6tables -P INPUT ACCEPT"));
+    commands.push(format!("ip6tables -P OUTPUT ACCEPT"));
+    commands.push(format!("ip6tables -P FORWARD ACCEPT"));
+    commands.push(format!("ip6tables -F"));
+
+    commands
+}
+
+fn save_output(cmd: &str) -> Result<String> {
+    let output = Command::new(cmd)
+        .output()
+        .context(format!("Failed to run {}", cmd))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("{} failed", cmd));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Runs a script that only reads state; Aegis itself runs as root
+fn script_output(script: &str) -> Result<String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .output()
+        .context("Failed to run check script")?;
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// OUTPUT rules in `tables` of an iptables-save dump that carry no Aegis
+/// tag, as "<table> <rule>"
+fn untagged_output_rules(save: &str, tables: &[&str]) -> Vec<String> {
+    let mut table = "";
+    let mut rules = Vec::new();
+    for line in save.lines() {
+        if let Some(name) = line.strip_prefix('*') {
+            table = name.trim();
+        } else if line.starts_with("-A OUTPUT ")
+            && tables.contains(&table)
+            && !line.contains(RULE_TAG)
+        {
+            rules.push(format!("{} {}", table, line));
+        }
     }
+    rules
 }
 
 /// Executes a list of commands as a single privileged script
-fn execute_batch(commands: &[String]) -> Result<()> {
+pub fn execute_batch(commands: &[String]) -> Result<()> {
     if commands.is_empty() {
         return Ok(());
     }
@@ -122,3 +480,58 @@ fn execute_batch(commands: &[String]) -> Result<()> {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAVE: &str = "\
+# Generated by iptables-save v1.8.9
+*mangle
+:OUTPUT ACCEPT [0:0]
+-A OUTPUT -j MARK --set-xmark 0x1/0xffffffff
+COMMIT
+*nat
+:OUTPUT ACCEPT [0:0]
+-A OUTPUT -o lo -m comment --comment \"aegis:4\" -j RETURN
+-A OUTPUT -d 192.0.2.1/32 -j RETURN
+COMMIT
+*filter
+:INPUT DROP [0:0]
+:OUTPUT DROP [0:0]
+-A INPUT -p tcp -m tcp --dport 22 -j ACCEPT
+-A OUTPUT -o lo -m comment --comment aegis:1 -j ACCEPT
+-A OUTPUT -p tcp -m tcp --dport 443 -j ACCEPT
+COMMIT
+";
+
+    #[test]
+    fn finds_untagged_output_rules() {
+        assert_eq!(
+            untagged_output_rules(SAVE, &["filter", "nat"]),
+            vec![
+                "nat -A OUTPUT -d 192.0.2.1/32 -j RETURN",
+                "filter -A OUTPUT -p tcp -m tcp --dport 443 -j ACCEPT",
+            ]
+        );
+    }
+
+    #[test]
+    fn other_tables_and_chains_are_not_checked() {
+        assert!(untagged_output_rules(SAVE, &["raw"]).is_empty());
+        assert_eq!(untagged_output_rules(SAVE, &["filter"]).len(), 1);
+    }
+
+    #[test]
+    fn tags_follow_the_chain_name() {
+        let tagged = tag_rules(vec![
+            "iptables -P OUTPUT DROP".to_string(),
+            "iptables -t nat -A OUTPUT -o lo -j RETURN".to_string(),
+        ]);
+        assert_eq!(tagged[0], "iptables -P OUTPUT DROP");
+        assert_eq!(
+            tagged[1],
+            "iptables -t nat -A OUTPUT -m comment --comment aegis:1 -o lo -j RETURN"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use crate::circuits::Circuit;
+use crate::config;
+use anyhow::{Context, Result};
+use std::fs;
+use std::process::Command;
+
+// Isolated SocksPorts are handed out from here, clear of Tor's defaults
+// (9040 TransPort, 9050 SocksPort, 9051 ControlPort)
+const BASE_PORT: u16 = 9060;
+const RESERVED_PORTS: &[u16] = &[5353, 9040, 9050, 9051];
+
+/// An application with its own SocksPort and SOCKS credentials
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct IsolatedApp {
+    /// Also the SOCKS username, so circuits can be traced back to the app
+    pub name: String,
+    pub command: String,
+    /// 0 picks the next free port on save
+    pub port: u16,
+}
+
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct IsolationSettings {
+    pub apps: Vec<IsolatedApp>,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct AppCircuits {
+    pub app: String,
+    pub circuits: Vec<String>,
+}
+
+impl IsolationSettings {
+    /// Checks names and commands and assigns ports to new entries
+    pub fn normalize(mut self) -> Result<Self> {
+        for app in &self.apps {
+            if app.name.is_empty()
+                || !app
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(anyhow::anyhow!(
+                    "Invalid app name \"{}\": use letters, digits, '-' and '_'",
+                    app.name
+                ));
+            }
+            if app.command.trim().is_empty() {
+                return Err(anyhow::anyhow!("{}: no command given", app.name));
+            }
+            if self.apps.iter().filter(|a| a.name == app.name).count() > 1 {
+                return Err(anyhow::anyhow!("Duplicate app name \"{}\"", app.name));
+            }
+            if RESERVED_PORTS.contains(&app.port) {
+                return Err(anyhow::anyhow!("Port {} is already used by Tor", app.port));
+            }
+            if app.port != 0 && self.apps.iter().filter(|a| a.port == app.port).count() > 1 {
+                return Err(anyhow::anyhow!("Port {} is used twice", app.port));
+            }
+        }
+
+        let mut next = BASE_PORT;
+        for i in 0..self.apps.len() {
+            if self.apps[i].port != 0 {
+                continue;
+            }
+            while self.apps.iter().any(|a| a.port == next) {
+                next += 1;
+            }
+            self.apps[i].port = next;
+        }
+        Ok(self)
+    }
+
+    /// Lines for the Aegis torrc drop-in, one SocksPort per app
+    pub fn torrc_lines(&self) -> Vec<String> {
+        if self.apps.is_empty() {
+            return Vec::new();
+        }
+        // Any SocksPort line replaces Tor's default one, which other
+        // SOCKS clients and the killswitch mode still rely on
+        let mut lines = vec!["SocksPort 9050".to_string()];
+        lines.extend(
+            self.apps
+                .iter()
+                .map(|a| format!("SocksPort 127.0.0.1:{} IsolateSOCKSAuth", a.port)),
+        );
+        lines
+    }
+
+    fn find(&self, name: &str) -> Result<&IsolatedApp> {
+        self.apps
+            .iter()
+            .find(|a| a.name == name)
+            .ok_or_else(|| anyhow::anyhow!("No isolated app named \"{}\"", name))
+    }
+
+    /// Starts an app through torsocks on its own SocksPort, as the desktop
+    /// user. Every launch gets a fresh password, so separate instances
+    /// don't share circuits either.
+    pub fn launch(&self, name: &str) -> Result<u32> {
+        let app = self.find(name)?;
+        let argv: Vec<&str> = app.command.split_whitespace().collect();
+
+        let conf = config::ensure_data_path(&format!("torsocks-{}.conf", app.name))?;
+        fs::write(
+            &conf,
+            format!("TorAddress 127.0.0.1\nTorPort {}\n", app.port),
+        )
+        .context("Failed to write torsocks configuration")?;
+
+        let user = std::env::var("SUDO_USER").context("Can't tell which user to launch as")?;
+        let password = format!("{:016x}", rand::random::<u64>());
+
+        let child = Command::new("runuser")
+            .args(["-u", &user, "--", "env"])
+            .arg(format!("TORSOCKS_CONF_FILE={}", conf.display()))
+            .arg(format!("TORSOCKS_USERNAME={}", app.name))
+            .arg(format!("TORSOCKS_PASSWORD={}", password))
+            .arg("torsocks")
+            .args(argv)
+            .spawn()
+            .context("Failed to launch through torsocks")?;
+
+        Ok(child.id())
+    }
+}
+
+/// Groups circuits by the isolated app (SOCKS username) holding them
+pub fn circuits_by_app(settings: &IsolationSettings, circuits: &[Circuit]) -> Vec<AppCircuits> {
+    settings
+        .apps
+        .iter()
+        .map(|app| AppCircuits {
+            app: app.name.clone(),
+            circuits: circuits
+                .iter()
+                .filter(|c| c.socks_username.as_deref() == Some(app.name.as_str()))
+                .map(|c| c.id.clone())
+                .collect(),
+        })
+        .collect()
+}
@@ -0,0 +1,455 @@
+use crate::anonsurf::{self, Anonsurf, AnonsurfState};
+use crate::appfirewall::{AppAction, AppFirewallState};
+use crate::apps::{self, AppGroup};
+use crate::config::{AegisConfig, ConfigState};
+use crate::droplog;
+use crate::iptables::IptablesManager;
+use crate::portal::PortalState;
+use crate::users;
+use anyhow::{Context, Result};
+use ipnet::Ipv4Net;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::mem;
+use std::net::Ipv4Addr;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+
+// Verification interval floor, in seconds
+const MIN_INTERVAL: u64 = 5;
+// A check can race a settings change that is re-applying the rules. Only
+// small drifts get this benefit of the doubt: a lost DROP policy or a
+// mostly empty ruleset is acted on at once.
+const FAILURES_BEFORE_ALERT: u32 = 2;
+const ALERT_COOLDOWN: u64 = 60; // seconds
+const REFRESH_INTERVAL: u64 = 1; // seconds, packet watch settings and exemptions
+const DHCP_SERVER_PORT: u16 = 67;
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct LeakMonitorSettings {
+    /// Periodically compare the live ruleset with the generated one
+    pub verify_rules: bool,
+    pub interval_secs: u64,
+    /// Watch physical interfaces for packets that didn't come from Tor.
+    /// Costly on busy links, so off by default.
+    pub packet_watch: bool,
+}
+
+impl Default for LeakMonitorSettings {
+    fn default() -> Self {
+        Self {
+            verify_rules: true,
+            interval_secs: 15,
+            packet_watch: false,
+        }
+    }
+}
+
+impl LeakMonitorSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.interval_secs < MIN_INTERVAL {
+            return Err(anyhow::anyhow!(
+                "The check interval must be at least {} seconds",
+                MIN_INTERVAL
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Starts the ruleset verifier and the packet watcher. Both re-apply the
+/// ruleset when they find a hole.
+pub fn start_leak_monitor(app: AppHandle) {
+    let verifier = app.clone();
+    thread::spawn(move || verify_loop(verifier));
+    thread::spawn(move || watch_loop(app));
+}
+
+fn verify_loop(app: AppHandle) {
+    let mut failures = 0;
+    loop {
+        let config = current_config(&app);
+        thread::sleep(Duration::from_secs(
+            config.leak_monitor.interval_secs.max(MIN_INTERVAL),
+        ));
+
+        if !is_active(&app) || !config.leak_monitor.verify_rules {
+            failures = 0;
+            continue;
+        }
+        // Settings may have changed while sleeping
+        let ruleset = match Anonsurf::ruleset(&app, &current_config(&app)) {
+            Ok(r) => r,
+            Err(_) => continue,
+        };
+
+        match IptablesManager::verify_rules(&ruleset) {
+            Ok(drift) if drift.problems.is_empty() => failures = 0,
+            Ok(drift) => {
+                failures += 1;
+                if drift.severe || failures >= FAILURES_BEFORE_ALERT {
+                    failures = 0;
+                    respond(
+                        &app,
+                        &format!(
+                            "Firewall ruleset was modified: {}",
+                            drift.problems.join(", ")
+                        ),
+                    );
+                }
+            }
+            Err(e) => println!("Ruleset verification failed: {}", e),
+        }
+    }
+}
+
+fn watch_loop(app: AppHandle) {
+    loop {
+        if !watch_enabled(&app) {
+            thread::sleep(Duration::from_secs(2));
+            continue;
+        }
+
+        let socket = match PacketSocket::open() {
+            Ok(s) => s,
+            Err(e) => {
+                let _ = app.emit("app_log", format!("Packet watch unavailable: {:#}", e));
+                thread::sleep(Duration::from_secs(30));
+                continue;
+            }
+        };
+        let _ = app.emit(
+            "app_log",
+            "Packet watch started on physical interfaces".to_string(),
+        );
+
+        let mut physical: HashMap<i32, Option<String>> = HashMap::new();
+        let mut exemptions = Exemptions::load(&app);
+        let mut refreshed = Instant::now();
+        let mut last_alert: Option<Instant> = None;
+
+        loop {
+            if refreshed.elapsed().as_secs() >= REFRESH_INTERVAL {
+                if !watch_enabled(&app) {
+                    break;
+                }
+                exemptions = Exemptions::load(&app);
+                refreshed = Instant::now();
+            }
+
+            let packet = match socket.recv(Duration::from_secs(REFRESH_INTERVAL)) {
+                Ok(Some(p)) => p,
+                Ok(None) => continue,
+                Err(e) => {
+                    println!("Packet watch stopped: {}", e);
+                    break;
+                }
+            };
+            let interface = match physical
+                .entry(packet.ifindex)
+                .or_insert_with(|| physical_interface(packet.ifindex))
+            {
+                Some(name) => name.clone(),
+                None => continue,
+            };
+
+            let leak = match exemptions.classify(&packet) {
+                Some(l) => l,
+                None => continue,
+            };
+            if last_alert
+                .map(|t| t.elapsed().as_secs() < ALERT_COOLDOWN)
+                .unwrap_or(false)
+            {
+                continue;
+            }
+            last_alert = Some(Instant::now());
+
+            respond(&app, &format!("{} on {}", leak, interface));
+        }
+    }
+}
+
+/// Alerts and puts the generated ruleset back. A start, stop or resume
+/// in progress is rewriting the rules itself, so it is left to finish.
+fn respond(app: &AppHandle, reason: &str) {
+    let state = app.state::<AnonsurfState>();
+    let _transition = match state.transition.try_lock() {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+    if !*state.is_active.lock().unwrap() {
+        return;
+    }
+
+    let _ = app.emit(
+        "security_alert",
+        format!("Leak monitor: {}. Re-applying the shield ruleset.", reason),
+    );
+    let result = Anonsurf::ruleset(app, &current_config(app))
+        .and_then(|ruleset| IptablesManager::reapply_rules(&ruleset));
+    if let Err(e) = result {
+        let _ = app.emit(
+            "security_alert",
+            format!("Leak monitor: failed to re-apply the ruleset: {}", e),
+        );
+    }
+}
+
+fn current_config(app: &AppHandle) -> AegisConfig {
+    app.state::<ConfigState>().config.lock().unwrap().clone()
+}
+
+fn is_active(app: &AppHandle) -> bool {
+    *app.state::<AnonsurfState>().is_active.lock().unwrap()
+}
+
+fn watch_enabled(app: &AppHandle) -> bool {
+    is_active(app) && current_config(app).leak_monitor.packet_watch
+}
+
+/// Who may legitimately reach the network without Tor right now
+struct Exemptions {
+    tor_uid: Option<u32>,
+    allowlist: Vec<Ipv4Net>,
+    exempt: Vec<String>,
+    forced: Vec<String>,
+    only_forced: bool,
+    portal_uid: Option<u32>,
+    direct_exes: Vec<String>,
+}
+
+impl Exemptions {
+    fn load(app: &AppHandle) -> Self {
+        let config = current_config(app);
+        let owners = config.users.resolve().unwrap_or_default();
+        let direct_exes = if config.app_firewall.enabled {
+            app.state::<AppFirewallState>()
+                .rules()
+                .into_iter()
+                .filter(|r| r.action == AppAction::Direct)
+                .map(|r| r.exe)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            tor_uid: users::uid_of(anonsurf::TOR_UID),
+            allowlist: config
+                .allowlist
+                .rules
+                .iter()
+                .filter_map(|r| r.network().ok())
+                .collect(),
+            exempt: owners.exempt,
+            forced: owners.forced,
+            only_forced: owners.only_forced,
+            portal_uid: app.state::<PortalState>().current().map(|p| p.uid),
+            direct_exes,
+        }
+    }
+
+    /// Describes the packet if nothing explains it leaving outside Tor
+    fn classify(&self, packet: &CapturedPacket) -> Option<String> {
+        // IPv6 is dropped outright while the shield is up
+        if packet.protocol == libc::ETH_P_IPV6 as u16 {
+            return Some("IPv6 packet left the machine".to_string());
+        }
+        if packet.protocol != libc::ETH_P_IP as u16 {
+            return None;
+        }
+
+        let data = &packet.data;
+        let header_len = ((*data.first()? & 0x0f) as usize) * 4;
+        let protocol = match data.get(9)? {
+            6 => "TCP",
+            17 => "UDP",
+            _ => return None,
+        };
+        let dst = data.get(16..20)?;
+        let destination = Ipv4Addr::new(dst[0], dst[1], dst[2], dst[3]);
+        let ports = data.get(header_len..header_len + 4)?;
+        let source_port = u16::from_be_bytes([ports[0], ports[1]]);
+        let port = u16::from_be_bytes([ports[2], ports[3]]);
+
+        // DHCP clients use raw sockets, the firewall never sees them
+        if protocol == "UDP" && port == DHCP_SERVER_PORT {
+            return None;
+        }
+        // No local socket: kernel replies or forwarded traffic
+        let socket = droplog::socket_info(protocol, source_port)?;
+        if Some(socket.uid) == self.tor_uid
+            || Some(socket.uid) == self.portal_uid
+            || self.allowlist.iter().any(|n| n.contains(&destination))
+        {
+            return None;
+        }
+
+        let owner = droplog::socket_owner(socket.inode);
+        let gids = owner
+            .as_ref()
+            .map(|(pid, _)| groups(*pid))
+            .unwrap_or_default();
+        if matches_owner(&self.exempt, socket.uid, &gids)
+            || (self.only_forced && !matches_owner(&self.forced, socket.uid, &gids))
+        {
+            return None;
+        }
+        if let Some((pid, _)) = &owner {
+            let bypassed = apps::current_cgroup(*pid)
+                .map(|c| c.starts_with(AppGroup::Bypass.path()))
+                .unwrap_or(false);
+            let direct = fs::read_link(format!("/proc/{}/exe", pid))
+                .map(|e| self.direct_exes.contains(&e.to_string_lossy().to_string()))
+                .unwrap_or(false);
+            if bypassed || direct {
+                return None;
+            }
+        }
+
+        let sender = match owner {
+            Some((pid, name)) => format!("{} ({})", name, pid),
+            None => format!("uid {}", socket.uid),
+        };
+        Some(format!(
+            "{} sent {} to {}:{} outside Tor",
+            sender, protocol, destination, port
+        ))
+    }
+}
+
+/// Whether one of the `-m owner` matchers matches the uid or a group
+fn matches_owner(matchers: &[String], uid: u32, gids: &[u32]) -> bool {
+    matchers.iter().any(|m| {
+        let words: Vec<&str> = m.split_whitespace().collect();
+        match words.as_slice() {
+            [.., "--uid-owner", id] => id.parse() == Ok(uid),
+            [.., "--gid-owner", id] => id.parse().map(|g| gids.contains(&g)).unwrap_or(false),
+            _ => false,
+        }
+    })
+}
+
+/// Primary and supplementary groups of a process
+fn groups(pid: u32) -> Vec<u32> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+    status
+        .lines()
+        .filter_map(|l| {
+            l.strip_prefix("Gid:")
+                .map(|g| g.split_whitespace().take(1).collect::<Vec<_>>())
+                .or_else(|| {
+                    l.strip_prefix("Groups:")
+                        .map(|g| g.split_whitespace().collect())
+                })
+        })
+        .flatten()
+        .filter_map(|g| g.parse().ok())
+        .collect()
+}
+
+/// Interface name if it is backed by a device, i.e. not lo, veth or tun
+fn physical_interface(ifindex: i32) -> Option<String> {
+    let mut buf = [0 as libc::c_char; libc::IF_NAMESIZE];
+    let name = unsafe { libc::if_indextoname(ifindex as u32, buf.as_mut_ptr()) };
+    if name.is_null() {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(name) }
+        .to_string_lossy()
+        .to_string();
+
+    Path::new("/sys/class/net")
+        .join(&name)
+        .join("device")
+        .exists()
+        .then_some(name)
+}
+
+struct CapturedPacket {
+    ifindex: i32,
+    /// Ethertype, host byte order
+    protocol: u16,
+    /// From the network header on
+    data: Vec<u8>,
+}
+
+/// AF_PACKET socket seeing every packet after the firewall let it out
+struct PacketSocket {
+    fd: OwnedFd,
+}
+
+impl PacketSocket {
+    fn open() -> Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                (libc::ETH_P_ALL as u16).to_be() as i32,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("Failed to open packet socket");
+        }
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    /// Next outgoing packet, or `None` if nothing left within `timeout`
+    fn recv(&self, timeout: Duration) -> Result<Option<CapturedPacket>> {
+        let tv = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: 0,
+        };
+        unsafe {
+            libc::setsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &tv as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as u32,
+            );
+        }
+
+        let deadline = Instant::now() + timeout;
+        // Only the headers are inspected
+        let mut buf = [0u8; 128];
+        while Instant::now() < deadline {
+            let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+            let mut addr_len = mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+            let n = unsafe {
+                libc::recvfrom(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                    &mut addr as *mut libc::sockaddr_ll as *mut libc::sockaddr,
+                    &mut addr_len,
+                )
+            };
+            if n < 0 {
+                let e = io::Error::last_os_error();
+                return match e.raw_os_error() {
+                    Some(libc::EAGAIN) | Some(libc::EINTR) => Ok(None),
+                    _ => Err(e).context("Failed to read from packet socket"),
+                };
+            }
+            if addr.sll_pkttype != libc::PACKET_OUTGOING {
+                continue;
+            }
+            return Ok(Some(CapturedPacket {
+                ifindex: addr.sll_ifindex,
+                protocol: u16::from_be(addr.sll_protocol),
+                data: buf[..n as usize].to_vec(),
+            }));
+        }
+        Ok(None)
+    }
+}
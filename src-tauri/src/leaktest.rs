@@ -0,0 +1,502 @@
+use crate::anonsurf;
+use crate::rotation;
+use crate::torctl::TorControl;
+use anyhow::Result;
+use ipnet::Ipv4Net;
+use std::net::{Ipv4Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::process::Command;
+use std::time::Duration;
+
+const PROBE_TIMEOUT: u64 = 5; // seconds
+
+// Tor answers resolves of .onion names from VirtualAddrNetworkIPv4
+const TOR_VIRTUAL_NET: &str = "10.192.0.0/10";
+
+/// Endpoints the leak test probes. Defaults are public services; tests
+/// and air-gapped setups can point them at local stand-ins.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct LeakTestSettings {
+    /// DNS server queried directly, bypassing the system resolver
+    pub dns_server: String,
+    /// Name only Tor can resolve; a real resolver answers NXDOMAIN
+    pub dns_probe_name: String,
+    /// Non-Tor TCP destination, must be blocked or carried by Tor
+    pub tcp_target: String,
+    /// UDP service that answers any datagram; any reply is a leak.
+    /// Not port 53, which the shield redirects to the DNSPort.
+    pub udp_target: String,
+    /// IPv6 literal, must be unreachable
+    pub ipv6_target: String,
+    /// Ask the echo service for the real IP, in the clear, each time the
+    /// shield starts. Without it the exit IP is only compared with the
+    /// addresses of local interfaces.
+    pub record_real_ip: bool,
+}
+
+impl Default for LeakTestSettings {
+    fn default() -> Self {
+        Self {
+            dns_server: "1.1.1.1:53".to_string(),
+            dns_probe_name: "2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion"
+                .to_string(),
+            tcp_target: "1.1.1.1:443".to_string(),
+            udp_target: "pool.ntp.org:123".to_string(),
+            ipv6_target: "[2606:4700:4700::1111]:443".to_string(),
+            record_real_ip: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    /// Couldn't be decided, e.g. the real IP is unknown
+    Skipped,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct LeakCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct LeakReport {
+    pub checks: Vec<LeakCheck>,
+    /// No check failed; skipped ones don't count against it
+    pub passed: bool,
+}
+
+impl LeakTestSettings {
+    pub fn validate(&self) -> Result<()> {
+        // The DNS server is an address so the test never depends on DNS
+        self.dns_server
+            .parse::<SocketAddr>()
+            .map_err(|_| anyhow::anyhow!("DNS server must be an IP:port"))?;
+        if !self.dns_probe_name.ends_with(".onion") {
+            return Err(anyhow::anyhow!("The DNS probe name must be a .onion name"));
+        }
+        for target in [&self.tcp_target, &self.udp_target] {
+            match target.rsplit_once(':') {
+                Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {}
+                _ => return Err(anyhow::anyhow!("Invalid target: {}", target)),
+            }
+        }
+        if self.udp_target.ends_with(":53") {
+            return Err(anyhow::anyhow!(
+                "The UDP target can't use port 53, the shield redirects it to Tor"
+            ));
+        }
+        match self.ipv6_target.parse::<SocketAddr>() {
+            Ok(addr) if addr.is_ipv6() => Ok(()),
+            _ => Err(anyhow::anyhow!(
+                "IPv6 target must be an [IPv6]:port literal"
+            )),
+        }
+    }
+}
+
+/// Probes the shield from the outside in. Meant to run while it is active.
+/// `real_ip` is the public IP from before the shield went up, if recorded.
+pub fn run(
+    settings: &LeakTestSettings,
+    echo_url: &str,
+    real_ip: Option<&str>,
+) -> Result<LeakReport> {
+    settings.validate()?;
+
+    let checks = vec![
+        check_dns(settings),
+        check_tcp(settings),
+        check_udp(settings),
+        check_ipv6(settings),
+        check_exit_ip(echo_url, anonsurf::TOR_SOCKS_PROXY, real_ip),
+    ];
+    let passed = checks.iter().all(|c| c.status != CheckStatus::Fail);
+    Ok(LeakReport { checks, passed })
+}
+
+fn check(name: &str, status: CheckStatus, detail: impl Into<String>) -> LeakCheck {
+    LeakCheck {
+        name: name.to_string(),
+        status,
+        detail: detail.into(),
+    }
+}
+
+/// A direct query must be dropped or answered by Tor's DNSPort
+fn check_dns(settings: &LeakTestSettings) -> LeakCheck {
+    const NAME: &str = "DNS via DNSPort";
+    let server: SocketAddr = match settings.dns_server.parse() {
+        Ok(s) => s,
+        Err(_) => return check(NAME, CheckStatus::Skipped, "Invalid DNS server"),
+    };
+
+    let answers = match dns_query(server, &settings.dns_probe_name) {
+        Ok(Some(a)) => a,
+        Ok(None) | Err(_) => {
+            return check(
+                NAME,
+                CheckStatus::Pass,
+                format!("Query to {} was blocked", server),
+            )
+        }
+    };
+
+    let virtual_net: Ipv4Net = TOR_VIRTUAL_NET.parse().unwrap();
+    if !answers.is_empty() && answers.iter().all(|ip| virtual_net.contains(ip)) {
+        check(
+            NAME,
+            CheckStatus::Pass,
+            format!("Query to {} was answered by Tor", server),
+        )
+    } else {
+        check(
+            NAME,
+            CheckStatus::Fail,
+            format!("{} answered directly, DNS leaks outside Tor", server),
+        )
+    }
+}
+
+/// A plain connection must fail or show up as a Tor stream
+fn check_tcp(settings: &LeakTestSettings) -> LeakCheck {
+    const NAME: &str = "TCP outside Tor";
+    let target = match resolve_v4(&settings.tcp_target) {
+        Some(t) => t,
+        None => {
+            return check(
+                NAME,
+                CheckStatus::Skipped,
+                "Couldn't resolve the TCP target",
+            )
+        }
+    };
+
+    // Held open while Tor is asked about it
+    let _stream = match TcpStream::connect_timeout(&target, Duration::from_secs(PROBE_TIMEOUT)) {
+        Ok(s) => s,
+        Err(_) => {
+            return check(
+                NAME,
+                CheckStatus::Pass,
+                format!("Connection to {} was blocked", target),
+            )
+        }
+    };
+
+    let carried = TorControl::connect()
+        .and_then(|mut ctl| ctl.getinfo("stream-status"))
+        .map(|status| {
+            status
+                .lines()
+                .any(|l| l.split_whitespace().nth(3) == Some(&target.to_string()))
+        })
+        .unwrap_or(false);
+
+    if carried {
+        check(
+            NAME,
+            CheckStatus::Pass,
+            format!("Connection to {} was carried by Tor", target),
+        )
+    } else {
+        check(
+            NAME,
+            CheckStatus::Fail,
+            format!("Connected to {} without Tor", target),
+        )
+    }
+}
+
+/// Tor doesn't carry UDP, so no datagram may get an answer
+fn check_udp(settings: &LeakTestSettings) -> LeakCheck {
+    const NAME: &str = "UDP outside Tor";
+    let target = match resolve_v4(&settings.udp_target) {
+        Some(t) => t,
+        None => {
+            return check(
+                NAME,
+                CheckStatus::Skipped,
+                "Couldn't resolve the UDP target",
+            )
+        }
+    };
+
+    // An NTP client request; any UDP echo stand-in answers it too
+    let mut request = [0u8; 48];
+    request[0] = 0x1b;
+
+    match udp_exchange(target, &request) {
+        Ok(Some(_)) => check(
+            NAME,
+            CheckStatus::Fail,
+            format!("{} answered over UDP outside Tor", target),
+        ),
+        _ => check(
+            NAME,
+            CheckStatus::Pass,
+            format!("Datagrams to {} were blocked", target),
+        ),
+    }
+}
+
+/// TransPort is IPv4 only, so IPv6 must not get anywhere
+fn check_ipv6(settings: &LeakTestSettings) -> LeakCheck {
+    const NAME: &str = "IPv6";
+    let target: SocketAddr = match settings.ipv6_target.parse() {
+        Ok(t) => t,
+        Err(_) => return check(NAME, CheckStatus::Skipped, "Invalid IPv6 target"),
+    };
+
+    match TcpStream::connect_timeout(&target, Duration::from_secs(PROBE_TIMEOUT)) {
+        Ok(_) => check(
+            NAME,
+            CheckStatus::Fail,
+            format!("Connected to {} over IPv6", target),
+        ),
+        Err(_) => check(
+            NAME,
+            CheckStatus::Pass,
+            format!("Connection to {} was blocked", target),
+        ),
+    }
+}
+
+/// Compares the exit IP with the local addresses and, if it was recorded,
+/// the real IP from before the shield went up
+fn check_exit_ip(echo_url: &str, socks: &str, real_ip: Option<&str>) -> LeakCheck {
+    const NAME: &str = "Exit IP";
+    let ip = match rotation::fetch_exit_ip(echo_url, Some(socks)) {
+        Ok(ip) => ip,
+        Err(e) => {
+            return check(
+                NAME,
+                CheckStatus::Skipped,
+                format!("Echo service unreachable: {}", e),
+            )
+        }
+    };
+
+    if real_ip == Some(ip.as_str()) {
+        check(
+            NAME,
+            CheckStatus::Fail,
+            format!("Apparent IP {} is the real IP", ip),
+        )
+    } else if local_addresses().contains(&ip) {
+        check(
+            NAME,
+            CheckStatus::Fail,
+            format!("Apparent IP {} belongs to this machine", ip),
+        )
+    } else if real_ip.is_some() {
+        check(NAME, CheckStatus::Pass, format!("Apparent IP is {}", ip))
+    } else {
+        // Behind NAT the real IP is on no local interface
+        check(
+            NAME,
+            CheckStatus::Skipped,
+            format!("Apparent IP is {}, the real IP wasn't recorded", ip),
+        )
+    }
+}
+
+/// Addresses of the local interfaces, from `ip -o addr`
+fn local_addresses() -> Vec<String> {
+    let output = match Command::new("ip").args(["-o", "addr", "show"]).output() {
+        Ok(o) => o,
+        Err(_) => return Vec::new(),
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|l| {
+            let mut fields = l.split_whitespace().skip_while(|f| !f.starts_with("inet"));
+            fields.nth(1)?.split('/').next().map(String::from)
+        })
+        .collect()
+}
+
+fn resolve_v4(target: &str) -> Option<SocketAddr> {
+    target.to_socket_addrs().ok()?.find(|a| a.is_ipv4())
+}
+
+/// Sends one datagram and waits for a reply. `None` means no answer.
+fn udp_exchange(target: SocketAddr, payload: &[u8]) -> Result<Option<Vec<u8>>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(Duration::from_secs(PROBE_TIMEOUT)))?;
+    socket.connect(target)?;
+    socket.send(payload)?;
+
+    let mut buf = [0u8; 512];
+    match socket.recv(&mut buf) {
+        Ok(n) => Ok(Some(buf[..n].to_vec())),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Asks `server` for A records of `name`. `None` means no answer at all.
+fn dns_query(server: SocketAddr, name: &str) -> Result<Option<Vec<Ipv4Addr>>> {
+    let id: u16 = rand::random();
+    let mut query = id.to_be_bytes().to_vec();
+    // Recursion desired, one question
+    query.extend([0x01, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        query.push(label.len() as u8);
+        query.extend(label.as_bytes());
+    }
+    query.extend([0, 0, 1, 0, 1]); // root, type A, class IN
+
+    let reply = match udp_exchange(server, &query)? {
+        Some(r) if r.len() >= 12 && r[..2] == id.to_be_bytes() => r,
+        _ => return Ok(None),
+    };
+    Ok(Some(parse_a_records(&reply).unwrap_or_default()))
+}
+
+fn parse_a_records(reply: &[u8]) -> Option<Vec<Ipv4Addr>> {
+    let answers = u16::from_be_bytes([reply[6], reply[7]]);
+    // Skip the header and the echoed question
+    let mut pos = skip_name(reply, 12)? + 4;
+
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        pos = skip_name(reply, pos)?;
+        let header = reply.get(pos..pos + 10)?;
+        let kind = u16::from_be_bytes([header[0], header[1]]);
+        let len = u16::from_be_bytes([header[8], header[9]]) as usize;
+        let data = reply.get(pos + 10..pos + 10 + len)?;
+        if kind == 1 && len == 4 {
+            records.push(Ipv4Addr::new(data[0], data[1], data[2], data[3]));
+        }
+        pos += 10 + len;
+    }
+    Some(records)
+}
+
+/// Returns the offset after a (possibly compressed) name
+fn skip_name(msg: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *msg.get(pos)?;
+        if len == 0 {
+            return Some(pos + 1);
+        }
+        if len & 0xc0 == 0xc0 {
+            return Some(pos + 2);
+        }
+        pos += 1 + len as usize;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation::tests::{echo_stand_in, ECHO_URL};
+    use std::thread;
+
+    /// Local stand-in for a DNS server, answering one query with `answer`
+    fn dns_stand_in(answer: Ipv4Addr) -> LeakTestSettings {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server = socket.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            let (n, from) = socket.recv_from(&mut buf).unwrap();
+            let mut reply = buf[..n].to_vec();
+            reply[2] = 0x81; // response, recursion desired
+            reply[3] = 0x80; // recursion available
+            reply[7] = 1; // one answer
+
+            // Name pointing at the question, type A, class IN, TTL 60
+            reply.extend([0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4]);
+            reply.extend(answer.octets());
+            socket.send_to(&reply, from).unwrap();
+        });
+        LeakTestSettings {
+            dns_server: server,
+            ..LeakTestSettings::default()
+        }
+    }
+
+    /// Local stand-in for a UDP service, echoing one datagram back
+    fn udp_stand_in() -> LeakTestSettings {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = socket.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            let (n, from) = socket.recv_from(&mut buf).unwrap();
+            socket.send_to(&buf[..n], from).unwrap();
+        });
+        LeakTestSettings {
+            udp_target: target,
+            ..LeakTestSettings::default()
+        }
+    }
+
+    /// A loopback UDP port nobody listens on, like a blocked destination
+    fn closed_port() -> String {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.local_addr().unwrap().to_string()
+    }
+
+    #[test]
+    fn dns_answered_by_tor_passes() {
+        let settings = dns_stand_in(Ipv4Addr::new(10, 192, 0, 5));
+        assert!(check_dns(&settings).status == CheckStatus::Pass);
+    }
+
+    #[test]
+    fn dns_answered_directly_fails() {
+        let settings = dns_stand_in(Ipv4Addr::new(198, 51, 100, 1));
+        assert!(check_dns(&settings).status == CheckStatus::Fail);
+    }
+
+    #[test]
+    fn dns_without_answer_passes() {
+        let settings = LeakTestSettings {
+            dns_server: closed_port(),
+            ..LeakTestSettings::default()
+        };
+        assert!(check_dns(&settings).status == CheckStatus::Pass);
+    }
+
+    #[test]
+    fn udp_answer_fails() {
+        let settings = udp_stand_in();
+        assert!(check_udp(&settings).status == CheckStatus::Fail);
+    }
+
+    #[test]
+    fn udp_without_answer_passes() {
+        let settings = LeakTestSettings {
+            udp_target: closed_port(),
+            ..LeakTestSettings::default()
+        };
+        assert!(check_udp(&settings).status == CheckStatus::Pass);
+    }
+
+    #[test]
+    fn exit_ip_equal_to_the_real_ip_fails() {
+        let socks = echo_stand_in("203.0.113.7");
+        let result = check_exit_ip(ECHO_URL, &socks, Some("203.0.113.7"));
+        assert!(result.status == CheckStatus::Fail);
+    }
+
+    #[test]
+    fn exit_ip_other_than_the_real_ip_passes() {
+        let socks = echo_stand_in("198.51.100.9");
+        let result = check_exit_ip(ECHO_URL, &socks, Some("203.0.113.7"));
+        assert!(result.status == CheckStatus::Pass);
+    }
+
+    #[test]
+    fn exit_ip_without_the_real_ip_is_skipped() {
+        let socks = echo_stand_in("198.51.100.9");
+        let result = check_exit_ip(ECHO_URL, &socks, None);
+        assert!(result.status == CheckStatus::Skipped);
+        assert!(result.detail.contains("198.51.100.9"));
+    }
+}
@@ -1,16 +1,45 @@
+use std::sync::Arc;
 use tauri::State;
+mod allowlist;
 mod anonsurf;
+mod appfirewall;
+mod apps;
+mod bootguard;
+mod bridges;
+mod circuits;
+mod config;
+mod dns;
+mod droplog;
 mod geoip;
 mod honeypot;
 mod iptables;
+mod isolation;
+mod leakmon;
+mod leaktest;
+mod netns;
+mod netwatch;
+mod nfqueue;
+mod nodes;
+mod onion;
+mod portal;
+mod profiles;
+mod proxy;
+mod rotation;
+mod session;
 mod stats;
 mod stealth;
+mod timesync;
+mod torctl;
+mod torrc;
+mod users;
+mod watchdog;
 
 use anonsurf::{Anonsurf, AnonsurfState};
 use tauri::AppHandle;
 
 #[tauri::command]
 fn start_anonsurf(app: AppHandle, state: State<AnonsurfState>) -> Result<String, String> {
+    let _transition = state.transition.lock().unwrap();
     match Anonsurf::start(app) {
         Ok(_) => {
             *state.is_active.lock().unwrap() = true;
@@ -21,13 +50,74 @@ fn start_anonsurf(app: AppHandle, state: State<AnonsurfState>) -> Result<String,
 }
 
 #[tauri::command]
-fn stop_anonsurf(state: State<AnonsurfState>) -> Result<String, String> {
-    match Anonsurf::stop() {
+fn stop_anonsurf(
+    app: AppHandle,
+    state: State<AnonsurfState>,
+    stop_tor: Option<bool>,
+) -> Result<anonsurf::TeardownReport, String> {
+    let opts = anonsurf::StopOptions {
+        stop_tor: stop_tor.unwrap_or(false),
+    };
+    // The exception is part of the ruleset; don't carry it into the next start
+    let _ = portal::close(&app);
+    let _transition = state.transition.lock().unwrap();
+    let report = Anonsurf::stop(&opts);
+    *state.is_active.lock().unwrap() = report.killswitch_active;
+
+    if report.killswitch_active {
+        Err(format!(
+            "Error stopping Anonsurf, killswitch still active: {}",
+            report.failed.join("; ")
+        ))
+    } else {
+        Ok(report)
+    }
+}
+
+#[tauri::command]
+fn get_orphaned_session(state: State<session::SessionState>) -> Option<session::OrphanedSession> {
+    state.orphaned.lock().unwrap().clone()
+}
+
+#[tauri::command]
+fn resume_session(
+    app: AppHandle,
+    state: State<AnonsurfState>,
+    session_state: State<session::SessionState>,
+) -> Result<String, String> {
+    let orphan = match session_state.orphaned.lock().unwrap().clone() {
+        Some(o) => o,
+        None => return Err("No previous session to resume".to_string()),
+    };
+
+    let _transition = state.transition.lock().unwrap();
+    match Anonsurf::resume(&app, &orphan) {
         Ok(_) => {
-            *state.is_active.lock().unwrap() = false;
-            Ok("Anonsurf stopped successfully".to_string())
+            *state.is_active.lock().unwrap() = true;
+            *session_state.orphaned.lock().unwrap() = None;
+            Ok("Previous session resumed".to_string())
         }
-        Err(e) => Err(format!("Error stopping Anonsurf: {}", e)),
+        Err(e) => Err(format!("Error resuming session: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn teardown_session(
+    state: State<AnonsurfState>,
+    session_state: State<session::SessionState>,
+) -> Result<anonsurf::TeardownReport, String> {
+    let _transition = state.transition.lock().unwrap();
+    let report = Anonsurf::stop(&anonsurf::StopOptions::default());
+    *state.is_active.lock().unwrap() = report.killswitch_active;
+    *session_state.orphaned.lock().unwrap() = None;
+
+    if report.killswitch_active {
+        Err(format!(
+            "Error tearing down session, killswitch still active: {}",
+            report.failed.join("; ")
+        ))
+    } else {
+        Ok(report)
     }
 }
 
@@ -81,6 +171,38 @@ fn set_utc() -> Result<String, String> {
     }
 }
 
+#[tauri::command]
+fn get_time_sync_settings(state: State<config::ConfigState>) -> timesync::TimeSyncSettings {
+    state.config.lock().unwrap().time_sync.clone()
+}
+
+#[tauri::command]
+fn set_time_sync_settings(
+    state: State<config::ConfigState>,
+    settings: timesync::TimeSyncSettings,
+) -> Result<String, String> {
+    settings.validate().map_err(|e| e.to_string())?;
+
+    let mut config = state.config.lock().unwrap();
+    config.time_sync = settings;
+    match config.save() {
+        Ok(_) => Ok("Time sync settings saved".to_string()),
+        Err(e) => Err(format!("Failed to save time sync settings: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn sync_time(
+    state: State<AnonsurfState>,
+    config_state: State<config::ConfigState>,
+) -> Result<String, String> {
+    if !*state.is_active.lock().unwrap() {
+        return Err("Time is only fetched through Tor, start the shield first".to_string());
+    }
+    let config = config_state.config.lock().unwrap().clone();
+    timesync::sync(&config).map_err(|e| format!("Time sync failed: {}", e))
+}
+
 #[tauri::command]
 fn clean_logs() -> Result<String, String> {
     match stealth::clean_logs() {
@@ -93,6 +215,7 @@ fn clean_logs() -> Result<String, String> {
 struct SystemIdentity {
     mac: String,
     hostname: String,
+    resolver: String,
 }
 
 #[tauri::command]
@@ -100,6 +223,7 @@ fn get_system_identity(interface: String) -> SystemIdentity {
     SystemIdentity {
         mac: stealth::get_mac(&interface),
         hostname: stealth::get_hostname(),
+        resolver: dns::active_resolver(),
     }
 }
 
@@ -108,11 +232,710 @@ fn check_status(state: State<AnonsurfState>) -> bool {
     *state.is_active.lock().unwrap()
 }
 
+#[tauri::command]
+fn get_rotation_settings(state: State<config::ConfigState>) -> rotation::RotationSettings {
+    state.config.lock().unwrap().rotation.clone()
+}
+
+#[tauri::command]
+fn set_rotation_settings(
+    state: State<config::ConfigState>,
+    settings: rotation::RotationSettings,
+) -> Result<String, String> {
+    settings.validate().map_err(|e| e.to_string())?;
+
+    let mut config = state.config.lock().unwrap();
+    config.rotation = settings;
+    match config.save() {
+        Ok(_) => Ok("Rotation settings saved".to_string()),
+        Err(e) => Err(format!("Failed to save rotation settings: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn get_node_policy(state: State<config::ConfigState>) -> nodes::NodePolicy {
+    state.config.lock().unwrap().node_policy.clone()
+}
+
+#[tauri::command]
+fn set_node_policy(
+    state: State<config::ConfigState>,
+    policy: nodes::NodePolicy,
+) -> Result<String, String> {
+    let policy = policy.normalize().map_err(|e| e.to_string())?;
+
+    let mut config = state.config.lock().unwrap();
+    config.node_policy = policy.clone();
+    config
+        .save()
+        .map_err(|e| format!("Failed to save node policy: {}", e))?;
+    Anonsurf::refresh_tor_config(&config)
+        .map_err(|e| format!("Failed to update Tor configuration: {}", e))?;
+    drop(config);
+
+    // Takes effect immediately when Tor is up; otherwise on next start
+    if Anonsurf::is_tor_running() {
+        policy
+            .apply()
+            .map_err(|e| format!("Failed to apply node policy: {}", e))?;
+        Ok("Node policy applied, new identity requested".to_string())
+    } else {
+        Ok("Node policy saved".to_string())
+    }
+}
+
+#[tauri::command]
+fn get_bridge_settings(state: State<config::ConfigState>) -> bridges::BridgeSettings {
+    state.config.lock().unwrap().bridges.clone()
+}
+
+#[tauri::command]
+fn set_bridge_settings(
+    state: State<config::ConfigState>,
+    settings: bridges::BridgeSettings,
+) -> Result<String, String> {
+    settings.validate().map_err(|e| e.to_string())?;
+
+    let mut config = state.config.lock().unwrap();
+    config.bridges = settings;
+    config
+        .save()
+        .map_err(|e| format!("Failed to save bridge settings: {}", e))?;
+    Anonsurf::refresh_tor_config(&config)
+        .map_err(|e| format!("Failed to update Tor configuration: {}", e))?;
+    drop(config);
+
+    // Without our drop-in, Tor doesn't use the bridges until the shield starts
+    if Anonsurf::is_tor_running() && torrc::is_installed() {
+        Anonsurf::reload_tor_config().map_err(|e| format!("Failed to reload Tor: {}", e))?;
+        Ok("Bridge settings applied".to_string())
+    } else {
+        Ok("Bridge settings saved".to_string())
+    }
+}
+
+#[tauri::command]
+fn detect_transports() -> Vec<bridges::TransportStatus> {
+    bridges::detect_transports()
+}
+
+#[tauri::command]
+fn parse_bridge_line(line: String) -> Result<bridges::Bridge, String> {
+    bridges::Bridge::parse(&line).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_bootstrap_status(
+    state: State<config::ConfigState>,
+) -> Result<bridges::BootstrapStatus, String> {
+    let settings = state.config.lock().unwrap().bridges.clone();
+    bridges::bootstrap_status(&settings)
+        .map_err(|e| format!("Failed to read bootstrap status: {}", e))
+}
+
+#[tauri::command]
+fn get_proxy_settings(state: State<config::ConfigState>) -> proxy::ProxySettings {
+    state.config.lock().unwrap().proxy.clone()
+}
+
+#[tauri::command]
+fn set_proxy_settings(
+    state: State<AnonsurfState>,
+    config_state: State<config::ConfigState>,
+    mut settings: proxy::ProxySettings,
+) -> Result<String, String> {
+    // The proxy becomes part of the killswitch; changing it under a live
+    // shield would need the firewall rebuilt as well
+    if *state.is_active.lock().unwrap() {
+        return Err("Stop the shield before changing the upstream proxy".to_string());
+    }
+
+    let mut config = config_state.config.lock().unwrap();
+    // The frontend never sees the password, so no password means unchanged
+    if settings.password.is_none() && settings.username == config.proxy.username {
+        settings.password = config.proxy.password.clone();
+    }
+    settings.validate().map_err(|e| e.to_string())?;
+    settings.resolve().map_err(|e| e.to_string())?;
+    config.proxy = settings;
+    match config.save() {
+        Ok(_) => Ok("Proxy settings saved".to_string()),
+        Err(e) => Err(format!("Failed to save proxy settings: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn check_proxy(
+    state: State<config::ConfigState>,
+    mut settings: proxy::ProxySettings,
+) -> Result<String, String> {
+    let config = state.config.lock().unwrap().clone();
+    if settings.password.is_none() && settings.username == config.proxy.username {
+        settings.password = config.proxy.password.clone();
+    }
+    settings.validate().map_err(|e| e.to_string())?;
+    settings.resolve().map_err(|e| e.to_string())?;
+    match settings.check_connectivity(&config.bridges) {
+        Ok(_) => Ok("Proxy is reachable".to_string()),
+        Err(e) => Err(format!("Proxy check failed: {:#}", e)),
+    }
+}
+
+#[tauri::command]
+fn get_allowlist(state: State<config::ConfigState>) -> allowlist::Allowlist {
+    state.config.lock().unwrap().allowlist.clone()
+}
+
+#[tauri::command]
+fn get_allowlist_preset() -> Vec<allowlist::AllowRule> {
+    allowlist::Allowlist::local_networks()
+}
+
+#[tauri::command]
+fn check_allowlist(allowlist: allowlist::Allowlist) -> Result<Vec<String>, String> {
+    allowlist.validate().map_err(|e| e.to_string())?;
+    allowlist.warnings().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_allowlist(
+    app: AppHandle,
+    state: State<AnonsurfState>,
+    config_state: State<config::ConfigState>,
+    allowlist: allowlist::Allowlist,
+    accept_public: Option<bool>,
+) -> Result<String, String> {
+    let warnings = check_allowlist(allowlist.clone())?;
+    if !warnings.is_empty() && !accept_public.unwrap_or(false) {
+        return Err(warnings.join("; "));
+    }
+
+    let mut config = config_state.config.lock().unwrap();
+    config.allowlist = allowlist;
+    config
+        .save()
+        .map_err(|e| format!("Failed to save allowlist: {}", e))?;
+
+    if !*state.is_active.lock().unwrap() {
+        return Ok("Allowlist saved".to_string());
+    }
+    let ruleset = Anonsurf::ruleset(&app, &config).map_err(|e| e.to_string())?;
+    drop(config);
+
+    match iptables::IptablesManager::reapply_rules(&ruleset) {
+        Ok(_) => Ok("Allowlist saved and applied".to_string()),
+        Err(e) => Err(format!("Allowlist saved but not applied: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn preview_ruleset(
+    app: AppHandle,
+    state: State<config::ConfigState>,
+) -> Result<Vec<String>, String> {
+    let config = state.config.lock().unwrap().clone();
+    let ruleset = Anonsurf::ruleset(&app, &config).map_err(|e| e.to_string())?;
+    Ok(iptables::IptablesManager::build_rules(&ruleset))
+}
+
+#[tauri::command]
+fn get_app_rules(state: State<config::ConfigState>) -> apps::AppRules {
+    state.config.lock().unwrap().apps.clone()
+}
+
+#[tauri::command]
+fn set_app_rules(
+    state: State<config::ConfigState>,
+    rules: apps::AppRules,
+) -> Result<String, String> {
+    rules.validate().map_err(|e| e.to_string())?;
+
+    let mut config = state.config.lock().unwrap();
+    config.apps = rules;
+    config
+        .save()
+        .map_err(|e| format!("Failed to save app rules: {}", e))?;
+
+    // Running programs are picked up right away rather than on the next sweep
+    let moved = apps::sweep(&config.apps);
+    Ok(format!(
+        "App rules saved, {} running process(es) moved",
+        moved.len()
+    ))
+}
+
+#[tauri::command]
+fn launch_bypassed(app: String) -> Result<String, String> {
+    match apps::launch_bypassed(&app) {
+        Ok(pid) => Ok(format!("Launched {} outside Tor (pid {})", app, pid)),
+        Err(e) => Err(format!("Failed to launch {}: {}", app, e)),
+    }
+}
+
+#[tauri::command]
+fn list_routed_apps() -> Vec<apps::AppProcess> {
+    apps::list_apps()
+}
+
+#[tauri::command]
+fn get_user_rules(state: State<config::ConfigState>) -> users::UserRules {
+    state.config.lock().unwrap().users.clone()
+}
+
+#[tauri::command]
+fn set_user_rules(
+    app: AppHandle,
+    state: State<AnonsurfState>,
+    config_state: State<config::ConfigState>,
+    rules: users::UserRules,
+) -> Result<String, String> {
+    rules.validate().map_err(|e| e.to_string())?;
+
+    let mut config = config_state.config.lock().unwrap();
+    config.users = rules;
+    config
+        .save()
+        .map_err(|e| format!("Failed to save user rules: {}", e))?;
+
+    if !*state.is_active.lock().unwrap() {
+        return Ok("User rules saved".to_string());
+    }
+    let ruleset = Anonsurf::ruleset(&app, &config).map_err(|e| e.to_string())?;
+    drop(config);
+
+    match iptables::IptablesManager::reapply_rules(&ruleset) {
+        Ok(_) => Ok("User rules saved and applied".to_string()),
+        Err(e) => Err(format!("User rules saved but not applied: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn list_local_accounts() -> Vec<users::Account> {
+    users::list_accounts()
+}
+
+#[tauri::command]
+fn get_isolation_settings(state: State<config::ConfigState>) -> isolation::IsolationSettings {
+    state.config.lock().unwrap().isolation.clone()
+}
+
+#[tauri::command]
+fn set_isolation_settings(
+    state: State<config::ConfigState>,
+    settings: isolation::IsolationSettings,
+) -> Result<isolation::IsolationSettings, String> {
+    let settings = settings.normalize().map_err(|e| e.to_string())?;
+
+    let mut config = state.config.lock().unwrap();
+    config.isolation = settings.clone();
+    config
+        .save()
+        .map_err(|e| format!("Failed to save isolation settings: {}", e))?;
+    Anonsurf::refresh_tor_config(&config)
+        .map_err(|e| format!("Failed to update Tor configuration: {}", e))?;
+    drop(config);
+
+    if Anonsurf::is_tor_running() {
+        Anonsurf::reload_tor_config().map_err(|e| format!("Failed to reload Tor: {}", e))?;
+    }
+    Ok(settings)
+}
+
+#[tauri::command]
+fn launch_isolated(state: State<config::ConfigState>, name: String) -> Result<String, String> {
+    let settings = state.config.lock().unwrap().isolation.clone();
+    match settings.launch(&name) {
+        Ok(pid) => Ok(format!(
+            "Launched {} on its own circuits (pid {})",
+            name, pid
+        )),
+        Err(e) => Err(format!("Failed to launch {}: {}", name, e)),
+    }
+}
+
+#[tauri::command]
+fn get_isolated_circuits(
+    state: State<config::ConfigState>,
+    geoip: State<Arc<geoip::GeoIpManager>>,
+) -> Result<Vec<isolation::AppCircuits>, String> {
+    let settings = state.config.lock().unwrap().isolation.clone();
+    let circuits =
+        circuits::get_circuits(&geoip).map_err(|e| format!("Failed to read circuits: {}", e))?;
+    Ok(isolation::circuits_by_app(&settings, &circuits))
+}
+
+#[tauri::command]
+fn get_tor_namespace() -> netns::NamespaceStatus {
+    netns::status()
+}
+
+#[tauri::command]
+fn start_tor_namespace(app: AppHandle) -> Result<String, String> {
+    netns::create().map_err(|e| format!("Failed to create Tor namespace: {}", e))?;
+    // Tor has to pick up the listeners on the namespace link
+    Anonsurf::ensure_tor(&app).map_err(|e| format!("Failed to configure Tor: {}", e))?;
+    Ok("Tor namespace is up".to_string())
+}
+
+#[tauri::command]
+fn stop_tor_namespace(
+    state: State<AnonsurfState>,
+    config_state: State<config::ConfigState>,
+) -> Result<String, String> {
+    netns::destroy().map_err(|e| format!("Failed to remove Tor namespace: {}", e))?;
+
+    // Without the shield, nothing else needs our Tor configuration
+    let result = if *state.is_active.lock().unwrap() {
+        Anonsurf::refresh_tor_config(&config_state.config.lock().unwrap())
+    } else {
+        torrc::revert()
+    };
+    result.map_err(|e| format!("Failed to update Tor configuration: {}", e))?;
+
+    if Anonsurf::is_tor_running() {
+        Anonsurf::reload_tor_config().map_err(|e| format!("Failed to reload Tor: {}", e))?;
+    }
+    Ok("Tor namespace removed".to_string())
+}
+
+#[tauri::command]
+fn launch_in_tor_namespace(app: AppHandle, cmd: String) -> Result<String, String> {
+    if !netns::is_up() {
+        start_tor_namespace(app)?;
+    }
+    match netns::launch(&cmd) {
+        Ok(pid) => Ok(format!(
+            "Launched {} in the Tor namespace (pid {})",
+            cmd, pid
+        )),
+        Err(e) => Err(format!("Failed to launch {}: {}", cmd, e)),
+    }
+}
+
+#[tauri::command]
+fn get_portal_settings(state: State<config::ConfigState>) -> portal::PortalSettings {
+    state.config.lock().unwrap().portal.clone()
+}
+
+#[tauri::command]
+fn set_portal_settings(
+    state: State<config::ConfigState>,
+    settings: portal::PortalSettings,
+) -> Result<String, String> {
+    settings.validate().map_err(|e| e.to_string())?;
+
+    let mut config = state.config.lock().unwrap();
+    config.portal = settings;
+    match config.save() {
+        Ok(_) => Ok("Captive portal settings saved".to_string()),
+        Err(e) => Err(format!("Failed to save captive portal settings: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn open_captive_portal(app: AppHandle) -> Result<portal::PortalProbe, String> {
+    portal::open(&app).map_err(|e| format!("Captive portal mode failed: {}", e))
+}
+
+#[tauri::command]
+fn close_captive_portal(app: AppHandle) -> Result<String, String> {
+    match portal::close(&app) {
+        Ok(_) => Ok("Captive portal exception closed".to_string()),
+        Err(e) => Err(format!("Failed to close captive portal exception: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn get_captive_portal(state: State<portal::PortalState>) -> Option<portal::PortalSession> {
+    state.current()
+}
+
+#[tauri::command]
+fn create_onion(request: onion::OnionRequest) -> Result<onion::CreatedOnion, String> {
+    onion::create(&request).map_err(|e| format!("Failed to create onion service: {}", e))
+}
+
+#[tauri::command]
+fn list_onions() -> Result<Vec<onion::OnionService>, String> {
+    onion::list().map_err(|e| format!("Failed to list onion services: {}", e))
+}
+
+#[tauri::command]
+fn delete_onion(service_id: String) -> Result<String, String> {
+    match onion::delete(&service_id) {
+        Ok(_) => Ok(format!("Onion service {} removed", service_id)),
+        Err(e) => Err(format!("Failed to remove onion service: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn get_leak_test_settings(state: State<config::ConfigState>) -> leaktest::LeakTestSettings {
+    state.config.lock().unwrap().leak_test.clone()
+}
+
+#[tauri::command]
+fn set_leak_test_settings(
+    state: State<config::ConfigState>,
+    settings: leaktest::LeakTestSettings,
+) -> Result<String, String> {
+    settings.validate().map_err(|e| e.to_string())?;
+
+    let mut config = state.config.lock().unwrap();
+    config.leak_test = settings;
+    match config.save() {
+        Ok(_) => Ok("Leak test settings saved".to_string()),
+        Err(e) => Err(format!("Failed to save leak test settings: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn get_leak_monitor_settings(state: State<config::ConfigState>) -> leakmon::LeakMonitorSettings {
+    state.config.lock().unwrap().leak_monitor.clone()
+}
+
+#[tauri::command]
+fn set_leak_monitor_settings(
+    state: State<config::ConfigState>,
+    settings: leakmon::LeakMonitorSettings,
+) -> Result<String, String> {
+    settings.validate().map_err(|e| e.to_string())?;
+
+    let mut config = state.config.lock().unwrap();
+    config.leak_monitor = settings;
+    match config.save() {
+        Ok(_) => Ok("Leak monitor settings saved".to_string()),
+        Err(e) => Err(format!("Failed to save leak monitor settings: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn get_network_watch_settings(state: State<config::ConfigState>) -> netwatch::NetworkWatchSettings {
+    state.config.lock().unwrap().network_watch.clone()
+}
+
+#[tauri::command]
+fn set_network_watch_settings(
+    state: State<config::ConfigState>,
+    settings: netwatch::NetworkWatchSettings,
+) -> Result<String, String> {
+    let mut config = state.config.lock().unwrap();
+    config.network_watch = settings;
+    match config.save() {
+        Ok(_) => Ok("Network change settings saved".to_string()),
+        Err(e) => Err(format!("Failed to save network change settings: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn get_shield_settings(state: State<config::ConfigState>) -> anonsurf::ShieldSettings {
+    state.config.lock().unwrap().shield.clone()
+}
+
+#[tauri::command]
+fn set_shield_settings(
+    app: AppHandle,
+    state: State<AnonsurfState>,
+    config_state: State<config::ConfigState>,
+    settings: anonsurf::ShieldSettings,
+) -> Result<String, String> {
+    settings.validate().map_err(|e| e.to_string())?;
+
+    let active = *state.is_active.lock().unwrap();
+    let mut config = config_state.config.lock().unwrap();
+    // The system resolver is only redirected when the shield starts
+    if active && settings.mode != config.shield.mode {
+        return Err("Stop the shield to change its mode".to_string());
+    }
+    config.shield = settings;
+    config
+        .save()
+        .map_err(|e| format!("Failed to save shield settings: {}", e))?;
+
+    if !active {
+        return Ok("Shield settings saved".to_string());
+    }
+    let ruleset = Anonsurf::ruleset(&app, &config).map_err(|e| e.to_string())?;
+    drop(config);
+
+    match iptables::IptablesManager::reapply_rules(&ruleset) {
+        Ok(_) => Ok("Shield settings saved and applied".to_string()),
+        Err(e) => Err(format!("Shield settings saved but not applied: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn get_network_profiles(state: State<config::ConfigState>) -> profiles::NetworkProfiles {
+    state.config.lock().unwrap().network_profiles.clone()
+}
+
+#[tauri::command]
+fn set_network_profiles(
+    state: State<config::ConfigState>,
+    mut profiles: profiles::NetworkProfiles,
+) -> Result<String, String> {
+    profiles.normalize().map_err(|e| e.to_string())?;
+
+    let mut config = state.config.lock().unwrap();
+    config.network_profiles = profiles;
+    match config.save() {
+        Ok(_) => Ok("Network profiles saved".to_string()),
+        Err(e) => Err(format!("Failed to save network profiles: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn get_current_network() -> Option<profiles::NetworkIdentity> {
+    profiles::current_network()
+}
+
+#[tauri::command]
+fn get_boot_killswitch() -> bootguard::BootKillswitchStatus {
+    bootguard::status()
+}
+
+#[tauri::command]
+fn install_boot_killswitch(
+    app: AppHandle,
+    state: State<config::ConfigState>,
+) -> Result<String, String> {
+    let config = state.config.lock().unwrap().clone();
+    let mut ruleset = Anonsurf::ruleset(&app, &config).map_err(|e| e.to_string())?;
+    // An open captive portal exception must not outlive this boot
+    ruleset.portal_uid = None;
+    match bootguard::install(&ruleset) {
+        Ok(_) => Ok("Boot killswitch installed".to_string()),
+        Err(e) => Err(format!("Failed to install boot killswitch: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn uninstall_boot_killswitch() -> Result<String, String> {
+    match bootguard::uninstall() {
+        Ok(_) => Ok("Boot killswitch removed".to_string()),
+        Err(e) => Err(format!("Failed to remove boot killswitch: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn run_leak_test(
+    state: State<AnonsurfState>,
+    config: State<config::ConfigState>,
+) -> Result<leaktest::LeakReport, String> {
+    if !*state.is_active.lock().unwrap() {
+        return Err("The leak test needs the shield to be active".to_string());
+    }
+    let config = config.config.lock().unwrap().clone();
+    let real_ip = state.real_ip.lock().unwrap().clone();
+    leaktest::run(&config.leak_test, &config.echo_url, real_ip.as_deref())
+        .map_err(|e| format!("Leak test failed: {}", e))
+}
+
+#[tauri::command]
+fn get_app_firewall_settings(
+    state: State<config::ConfigState>,
+) -> appfirewall::AppFirewallSettings {
+    state.config.lock().unwrap().app_firewall.clone()
+}
+
+#[tauri::command]
+fn set_app_firewall_settings(
+    app: AppHandle,
+    state: State<AnonsurfState>,
+    config_state: State<config::ConfigState>,
+    settings: appfirewall::AppFirewallSettings,
+) -> Result<String, String> {
+    let mut config = config_state.config.lock().unwrap();
+    config.app_firewall = settings;
+    config
+        .save()
+        .map_err(|e| format!("Failed to save application firewall settings: {}", e))?;
+
+    if !*state.is_active.lock().unwrap() {
+        return Ok("Application firewall settings saved".to_string());
+    }
+    let ruleset = Anonsurf::ruleset(&app, &config).map_err(|e| e.to_string())?;
+    drop(config);
+
+    match iptables::IptablesManager::reapply_rules(&ruleset) {
+        Ok(_) => Ok("Application firewall settings saved and applied".to_string()),
+        Err(e) => Err(format!(
+            "Application firewall settings saved but not applied: {}",
+            e
+        )),
+    }
+}
+
+#[tauri::command]
+fn answer_firewall_prompt(
+    state: State<appfirewall::AppFirewallState>,
+    id: u64,
+    action: appfirewall::AppAction,
+) -> Result<String, String> {
+    match state.answer(id, action) {
+        Ok(_) => Ok("Decision saved".to_string()),
+        Err(e) => Err(format!("Failed to apply decision: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn get_firewall_prompts(
+    state: State<appfirewall::AppFirewallState>,
+) -> Vec<appfirewall::FirewallPrompt> {
+    state.prompts()
+}
+
+#[tauri::command]
+fn list_firewall_rules(
+    state: State<appfirewall::AppFirewallState>,
+) -> Vec<appfirewall::AppFirewallRule> {
+    state.rules()
+}
+
+#[tauri::command]
+fn delete_firewall_rule(
+    state: State<appfirewall::AppFirewallState>,
+    exe: String,
+) -> Result<String, String> {
+    match state.delete_rule(&exe) {
+        Ok(_) => Ok(format!("Rule for {} removed", exe)),
+        Err(e) => Err(format!("Failed to remove rule: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn get_circuits(
+    geoip: State<Arc<geoip::GeoIpManager>>,
+) -> Result<Vec<circuits::Circuit>, String> {
+    circuits::get_circuits(&geoip).map_err(|e| format!("Failed to read circuits: {}", e))
+}
+
+#[tauri::command]
+fn get_streams() -> Result<Vec<circuits::Stream>, String> {
+    circuits::get_streams().map_err(|e| format!("Failed to read streams: {}", e))
+}
+
+#[tauri::command]
+fn close_circuit(id: String) -> Result<String, String> {
+    match circuits::close_circuit(&id) {
+        Ok(_) => Ok(format!("Circuit {} closed", id)),
+        Err(e) => Err(format!("Failed to close circuit: {}", e)),
+    }
+}
+
+#[tauri::command]
+fn get_shield_health(state: State<watchdog::WatchdogState>) -> watchdog::ShieldHealth {
+    state.health.lock().unwrap().clone()
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // Initialize GeoIP
-    let geoip = std::sync::Arc::new(crate::geoip::GeoIpManager::new());
+    let geoip = Arc::new(crate::geoip::GeoIpManager::new());
     let _ = geoip.init(); // Load async/bg
+    let geoip_state = geoip.clone();
+
+    // Look for a shield left behind by a crashed or killed instance
+    let orphaned = session::detect_orphaned();
 
     tauri::Builder::default()
         .setup(move |app| {
@@ -122,21 +945,123 @@ pub fn run() {
             // Start Honeypot
             honeypot::start_honeypot_listener(app.handle().clone(), geoip.clone());
 
+            // Start Tor Watchdog
+            watchdog::start_tor_watchdog(app.handle().clone());
+
+            // Start scheduled identity rotation
+            rotation::start_identity_rotation(app.handle().clone());
+
+            // Keep bypassed/forced programs in their cgroups
+            apps::start_app_sweeper(app.handle().clone());
+
+            // Report what the killswitch blocks, per process
+            droplog::start_drop_monitor(app.handle().clone());
+
+            // Decide connections queued by the application firewall
+            appfirewall::start_app_firewall(app.handle().clone());
+
+            // Catch flushed rulesets and packets leaving outside Tor
+            leakmon::start_leak_monitor(app.handle().clone());
+
+            // React to Wi-Fi reconnects, new interfaces and route changes
+            netwatch::start_network_watch(app.handle().clone());
+
+            // Watch Tor for a wrong system clock
+            timesync::start_time_watch(app.handle().clone());
+
             Ok(())
         })
         .manage(AnonsurfState::new())
+        .manage(geoip_state)
+        .manage(config::ConfigState::new(config::AegisConfig::load()))
+        .manage(session::SessionState::new(orphaned))
         .manage(honeypot::HoneypotState::new())
+        .manage(watchdog::WatchdogState::new())
+        .manage(portal::PortalState::new())
+        .manage(appfirewall::AppFirewallState::new())
+        .manage(profiles::ProfileState::new())
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             start_anonsurf,
             stop_anonsurf,
             refresh_identity,
             check_status,
+            get_shield_health,
+            get_rotation_settings,
+            get_node_policy,
+            set_node_policy,
+            get_bridge_settings,
+            set_bridge_settings,
+            detect_transports,
+            parse_bridge_line,
+            get_bootstrap_status,
+            get_proxy_settings,
+            set_proxy_settings,
+            check_proxy,
+            get_allowlist,
+            get_allowlist_preset,
+            check_allowlist,
+            set_allowlist,
+            preview_ruleset,
+            get_app_rules,
+            set_app_rules,
+            launch_bypassed,
+            list_routed_apps,
+            get_user_rules,
+            set_user_rules,
+            list_local_accounts,
+            get_isolation_settings,
+            set_isolation_settings,
+            launch_isolated,
+            get_isolated_circuits,
+            get_tor_namespace,
+            start_tor_namespace,
+            stop_tor_namespace,
+            launch_in_tor_namespace,
+            get_portal_settings,
+            set_portal_settings,
+            open_captive_portal,
+            close_captive_portal,
+            get_captive_portal,
+            create_onion,
+            list_onions,
+            delete_onion,
+            get_leak_test_settings,
+            set_leak_test_settings,
+            run_leak_test,
+            get_leak_monitor_settings,
+            set_leak_monitor_settings,
+            get_network_watch_settings,
+            set_network_watch_settings,
+            get_shield_settings,
+            set_shield_settings,
+            get_network_profiles,
+            set_network_profiles,
+            get_current_network,
+            get_boot_killswitch,
+            install_boot_killswitch,
+            uninstall_boot_killswitch,
+            get_app_firewall_settings,
+            set_app_firewall_settings,
+            answer_firewall_prompt,
+            get_firewall_prompts,
+            list_firewall_rules,
+            delete_firewall_rule,
+            get_circuits,
+            get_streams,
+            close_circuit,
+            set_rotation_settings,
+            get_orphaned_session,
+            resume_session,
+            teardown_session,
             spoof_mac,
             restore_mac,
             randomize_hostname,
             wipe_ram,
             set_utc,
+            get_time_sync_settings,
+            set_time_sync_settings,
+            sync_time,
             clean_logs,
             get_system_identity,
             set_defense_message
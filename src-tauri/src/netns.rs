@@ -0,0 +1,214 @@
+use crate::iptables;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+// The namespace reaches the host over a /30 veth link; the host end is
+// the only address programs inside can talk to
+const NAMESPACE: &str = "aegis";
+const HOST_VETH: &str = "aegis0";
+const NS_VETH: &str = "aegis1";
+const HOST_ADDR: &str = "10.200.1.1";
+const NS_ADDR: &str = "10.200.1.2";
+const PREFIX_LEN: u8 = 30;
+
+// Tor listeners bound to the host end, separate from the global shield's
+const NS_TRANS_PORT: &str = "9041";
+const NS_DNS_PORT: &str = "5354";
+
+#[derive(Clone, serde::Serialize)]
+pub struct NamespaceStatus {
+    pub up: bool,
+    pub name: String,
+    pub host_addr: String,
+    pub ns_addr: String,
+}
+
+/// True while the host end of the veth pair exists
+pub fn is_up() -> bool {
+    Path::new("/sys/class/net").join(HOST_VETH).exists()
+}
+
+pub fn status() -> NamespaceStatus {
+    NamespaceStatus {
+        up: is_up(),
+        name: NAMESPACE.to_string(),
+        host_addr: HOST_ADDR.to_string(),
+        ns_addr: NS_ADDR.to_string(),
+    }
+}
+
+/// Tor listeners for the namespace. Only emitted while the veth exists:
+/// Tor refuses to start if it can't bind a configured address.
+pub fn torrc_lines() -> Vec<String> {
+    if !is_up() {
+        return Vec::new();
+    }
+    vec![
+        format!("TransPort {}:{}", HOST_ADDR, NS_TRANS_PORT),
+        format!("DNSPort {}:{}", HOST_ADDR, NS_DNS_PORT),
+    ]
+}
+
+/// Creates the namespace, its veth link and both sides' firewall rules
+pub fn create() -> Result<()> {
+    if is_up() {
+        return Ok(());
+    }
+
+    // Programs inside resolve through the host end (redirected to Tor)
+    let resolv_dir = Path::new("/etc/netns").join(NAMESPACE);
+    fs::create_dir_all(&resolv_dir).context("Failed to create namespace config directory")?;
+    fs::write(
+        resolv_dir.join("resolv.conf"),
+        format!("nameserver {}\n", HOST_ADDR),
+    )
+    .context("Failed to write namespace resolv.conf")?;
+
+    let ns = format!("ip netns exec {}", NAMESPACE);
+    let commands = vec![
+        format!("ip netns add {}", NAMESPACE),
+        format!("ip link add {} type veth peer name {}", HOST_VETH, NS_VETH),
+        format!("ip link set {} netns {}", NS_VETH, NAMESPACE),
+        format!("ip addr add {}/{} dev {}", HOST_ADDR, PREFIX_LEN, HOST_VETH),
+        format!("ip link set {} up", HOST_VETH),
+        format!(
+            "{} ip addr add {}/{} dev {}",
+            ns, NS_ADDR, PREFIX_LEN, NS_VETH
+        ),
+        format!("{} ip link set {} up", ns, NS_VETH),
+        format!("{} ip link set lo up", ns),
+        format!("{} ip route add default via {}", ns, HOST_ADDR),
+        // Inside: nothing but TCP and DNS towards the host end
+        format!("{} iptables -P INPUT DROP", ns),
+        format!("{} iptables -P FORWARD DROP", ns),
+        format!("{} iptables -P OUTPUT DROP", ns),
+        format!("{} iptables -A INPUT -i lo -j ACCEPT", ns),
+        format!("{} iptables -A OUTPUT -o lo -j ACCEPT", ns),
+        format!(
+            "{} iptables -A INPUT -m state --state ESTABLISHED,RELATED -j ACCEPT",
+            ns
+        ),
+        format!("{} iptables -A OUTPUT -o {} -p tcp -j ACCEPT", ns, NS_VETH),
+        format!(
+            "{} iptables -A OUTPUT -o {} -p udp --dport 53 -j ACCEPT",
+            ns, NS_VETH
+        ),
+        format!("{} ip6tables -P INPUT DROP", ns),
+        format!("{} ip6tables -P FORWARD DROP", ns),
+        format!("{} ip6tables -P OUTPUT DROP", ns),
+    ];
+
+    iptables::execute_batch(&commands)?;
+    install_host_rules()
+}
+
+/// Puts the host rules back in front, e.g. after the shield restored a
+/// snapshot taken before the namespace existed
+pub fn install_host_rules() -> Result<()> {
+    // Drop any copies first, then insert in reverse so the rules end up in
+    // the order listed
+    let mut commands = delete_rules();
+    commands.extend(
+        host_rules()
+            .iter()
+            .rev()
+            .map(|(table, chain, spec)| format!("iptables -t {} -I {} {}", table, chain, spec)),
+    );
+
+    iptables::execute_batch(&commands)
+}
+
+/// Removes the namespace; the veth pair goes with it
+pub fn destroy() -> Result<()> {
+    let mut commands = delete_rules();
+    commands.push(format!("ip netns delete {}", NAMESPACE));
+
+    iptables::execute_batch(&commands)?;
+    let _ = fs::remove_dir_all(Path::new("/etc/netns").join(NAMESPACE));
+    Ok(())
+}
+
+/// Host rules may already be gone (e.g. flushed by the shield), so
+/// deleting them is best-effort
+fn delete_rules() -> Vec<String> {
+    host_rules()
+        .iter()
+        .map(|(table, chain, spec)| {
+            format!("(iptables -t {} -D {} {} || true)", table, chain, spec)
+        })
+        .collect()
+}
+
+/// Host rules for the namespace, as appended by the global ruleset
+pub fn append_rules() -> Vec<String> {
+    host_rules()
+        .iter()
+        .map(|(table, chain, spec)| format!("iptables -t {} -A {} {}", table, chain, spec))
+        .collect()
+}
+
+/// Runs a program inside the namespace as the desktop user
+pub fn launch(cmd: &str) -> Result<u32> {
+    let argv: Vec<&str> = cmd.split_whitespace().collect();
+    if argv.is_empty() {
+        return Err(anyhow::anyhow!("No program given"));
+    }
+
+    let user = std::env::var("SUDO_USER").context("Can't tell which user to launch as")?;
+    let child = Command::new("ip")
+        .args(["netns", "exec", NAMESPACE, "runuser", "-u", &user, "--"])
+        .args(argv)
+        .spawn()
+        .context("Failed to launch in the Tor namespace")?;
+
+    Ok(child.id())
+}
+
+/// (table, chain, rule) on the host side. Everything from the namespace is
+/// redirected into Tor; it can't reach other host services or be forwarded.
+fn host_rules() -> Vec<(&'static str, &'static str, String)> {
+    vec![
+        (
+            "nat",
+            "PREROUTING",
+            format!(
+                "-i {} -p udp --dport 53 -j REDIRECT --to-ports {}",
+                HOST_VETH, NS_DNS_PORT
+            ),
+        ),
+        (
+            "nat",
+            "PREROUTING",
+            format!(
+                "-i {} -p tcp --syn -j REDIRECT --to-ports {}",
+                HOST_VETH, NS_TRANS_PORT
+            ),
+        ),
+        (
+            "filter",
+            "INPUT",
+            format!(
+                "-i {} -p tcp --dport {} -j ACCEPT",
+                HOST_VETH, NS_TRANS_PORT
+            ),
+        ),
+        (
+            "filter",
+            "INPUT",
+            format!("-i {} -p udp --dport {} -j ACCEPT", HOST_VETH, NS_DNS_PORT),
+        ),
+        ("filter", "INPUT", format!("-i {} -j DROP", HOST_VETH)),
+        ("filter", "FORWARD", format!("-i {} -j DROP", HOST_VETH)),
+        ("filter", "FORWARD", format!("-o {} -j DROP", HOST_VETH)),
+        (
+            "filter",
+            "OUTPUT",
+            format!(
+                "-o {} -m state --state ESTABLISHED,RELATED -j ACCEPT",
+                HOST_VETH
+            ),
+        ),
+    ]
+}
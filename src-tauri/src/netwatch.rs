@@ -0,0 +1,426 @@
+use crate::anonsurf::{Anonsurf, AnonsurfState};
+use crate::config::{AegisConfig, ConfigState};
+use crate::iptables::IptablesManager;
+use crate::profiles;
+use crate::stealth;
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+
+// Link, address and route changes come in bursts; react once it settles
+const SETTLE_TIME: u64 = 2; // seconds
+const RECV_TIMEOUT: u64 = 1; // seconds
+const RECONNECT_DELAY: u64 = 5; // seconds
+const RECV_BUFFER: usize = 65536;
+
+// Spoofing takes the link down and up again, which must not trigger
+// another spoof
+const SPOOF_COOLDOWN: u64 = 30; // seconds
+
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct NetworkWatchSettings {
+    /// Give every physical interface a random MAC when it comes up
+    pub spoof_mac: bool,
+}
+
+/// Default route: outgoing interface and gateway
+#[derive(Clone, PartialEq, Eq)]
+pub struct Uplink {
+    pub interface: String,
+    pub gateway: String,
+}
+
+/// What changed since the last reaction
+#[derive(Default)]
+struct Changes {
+    /// Interfaces that went from down to up
+    links_up: HashSet<String>,
+    links_down: HashSet<String>,
+    addresses: bool,
+    routes: bool,
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        self.links_up.is_empty() && self.links_down.is_empty() && !self.addresses && !self.routes
+    }
+}
+
+/// Listens for rtnetlink link, address and route events and keeps the
+/// shield consistent with the network it runs on
+pub fn start_network_watch(app: AppHandle) {
+    thread::spawn(move || loop {
+        if let Err(e) = run_listener(&app) {
+            println!("Network watch paused: {:#}", e);
+        }
+        thread::sleep(Duration::from_secs(RECONNECT_DELAY));
+    });
+}
+
+fn run_listener(app: &AppHandle) -> Result<()> {
+    let socket = RouteSocket::open()?;
+    let mut uplink = default_uplink();
+    let mut links_up = current_links();
+    let mut spoofed: HashMap<String, Instant> = HashMap::new();
+    let mut changes = Changes::default();
+    let mut last_event = Instant::now();
+
+    loop {
+        let messages = socket.recv(Duration::from_secs(RECV_TIMEOUT))?;
+        if !messages.is_empty() {
+            last_event = Instant::now();
+        }
+
+        for (kind, body) in &messages {
+            match *kind {
+                libc::RTM_NEWLINK | libc::RTM_DELLINK => {
+                    let link = match parse_link(body) {
+                        Some(l) => l,
+                        None => continue,
+                    };
+                    let up = *kind == libc::RTM_NEWLINK && link.up;
+                    let was_up = links_up.insert(link.index, up).unwrap_or(false);
+                    if up && !was_up {
+                        // Right away: the network profile should win the race with DHCP
+                        profiles::link_up(app, &link.name);
+                        changes.links_down.remove(&link.name);
+                        changes.links_up.insert(link.name);
+                    } else if !up && was_up {
+                        changes.links_up.remove(&link.name);
+                        changes.links_down.insert(link.name);
+                    }
+                }
+                libc::RTM_NEWADDR | libc::RTM_DELADDR => changes.addresses = true,
+                libc::RTM_NEWROUTE | libc::RTM_DELROUTE => changes.routes = true,
+                _ => {}
+            }
+        }
+
+        if changes.is_empty() || last_event.elapsed().as_secs() < SETTLE_TIME {
+            continue;
+        }
+        let settled = mem::take(&mut changes);
+        react(app, settled, &mut uplink, &mut spoofed);
+    }
+}
+
+fn react(
+    app: &AppHandle,
+    changes: Changes,
+    uplink: &mut Option<Uplink>,
+    spoofed: &mut HashMap<String, Instant>,
+) {
+    let config = app.state::<ConfigState>().config.lock().unwrap().clone();
+    let active = *app.state::<AnonsurfState>().is_active.lock().unwrap();
+
+    for name in &changes.links_down {
+        log(app, format!("Interface {} went down", name));
+    }
+    for name in &changes.links_up {
+        log(app, format!("Interface {} came up", name));
+        // With profiles on, the profile decides about the MAC
+        if config.network_watch.spoof_mac && !config.network_profiles.enabled && is_physical(name) {
+            spoof(app, name, spoofed);
+        }
+    }
+
+    let current = default_uplink();
+    let uplink_changed = current != *uplink;
+    if changes.links_down.iter().any(|n| is_physical(n)) || (uplink_changed && current.is_none()) {
+        profiles::network_lost(app);
+    }
+    if uplink_changed {
+        match &current {
+            Some(u) => log(
+                app,
+                format!("Uplink changed to {} via {}", u.interface, u.gateway),
+            ),
+            None => log(app, "No default route, the machine is offline".to_string()),
+        }
+        *uplink = current.clone();
+        if let Some(u) = &current {
+            profiles::uplink_changed(app, u);
+        }
+    }
+
+    if !active {
+        return;
+    }
+
+    revalidate_firewall(app, &config);
+
+    // Circuits built over the old uplink are dead or about to be
+    if uplink_changed && current.is_some() {
+        match Anonsurf::new_identity() {
+            Ok(_) => log(
+                app,
+                "Requested fresh Tor circuits for the new uplink".to_string(),
+            ),
+            Err(e) => log(app, format!("Failed to renew Tor circuits: {}", e)),
+        }
+    }
+}
+
+fn spoof(app: &AppHandle, name: &str, spoofed: &mut HashMap<String, Instant>) {
+    if spoofed
+        .get(name)
+        .map(|t| t.elapsed().as_secs() < SPOOF_COOLDOWN)
+        .unwrap_or(false)
+    {
+        return;
+    }
+    spoofed.insert(name.to_string(), Instant::now());
+
+    match stealth::spoof_mac(name) {
+        Ok(_) => log(
+            app,
+            format!("Spoofed MAC of {}: {}", name, stealth::get_mac(name)),
+        ),
+        Err(e) => log(app, format!("Failed to spoof MAC of {}: {}", name, e)),
+    }
+}
+
+/// DHCP and interface changes don't touch iptables, but whatever manages
+/// the network might; put the ruleset back if it did. A start, stop or
+/// resume in progress is rewriting the rules itself, so it is left alone.
+fn revalidate_firewall(app: &AppHandle, config: &AegisConfig) {
+    let state = app.state::<AnonsurfState>();
+    let _transition = match state.transition.try_lock() {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+    if !*state.is_active.lock().unwrap() {
+        return;
+    }
+
+    let ruleset = match Anonsurf::ruleset(app, config) {
+        Ok(r) => r,
+        Err(e) => {
+            log(app, format!("Failed to build ruleset: {}", e));
+            return;
+        }
+    };
+
+    match IptablesManager::verify_rules(&ruleset) {
+        Ok(drift) if drift.problems.is_empty() => log(
+            app,
+            "Firewall still intact after network change".to_string(),
+        ),
+        Ok(drift) => {
+            log(
+                app,
+                format!(
+                    "Firewall changed with the network ({}), re-applying",
+                    drift.problems.join(", ")
+                ),
+            );
+            if let Err(e) = IptablesManager::reapply_rules(&ruleset) {
+                let _ = app.emit(
+                    "security_alert",
+                    format!(
+                        "Failed to re-apply the ruleset after a network change: {}",
+                        e
+                    ),
+                );
+            }
+        }
+        Err(e) => log(app, format!("Failed to verify the firewall: {}", e)),
+    }
+}
+
+fn log(app: &AppHandle, message: String) {
+    let _ = app.emit("app_log", format!("Network: {}", message));
+}
+
+/// Up state of the existing interfaces by index, so that attribute
+/// changes on a running link aren't taken for it coming up
+fn current_links() -> HashMap<i32, bool> {
+    let entries = match fs::read_dir("/sys/class/net") {
+        Ok(e) => e,
+        Err(_) => return HashMap::new(),
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let read = |file: &str| fs::read_to_string(entry.path().join(file)).ok();
+            let index = read("ifindex")?.trim().parse().ok()?;
+            let up = read("operstate")?.trim() == "up";
+            Some((index, up))
+        })
+        .collect()
+}
+
+/// Interfaces backed by a device, i.e. not lo, veth, tun or bridges
+fn is_physical(name: &str) -> bool {
+    Path::new("/sys/class/net")
+        .join(name)
+        .join("device")
+        .exists()
+}
+
+/// Reads the IPv4 default route from /proc/net/route
+pub fn default_uplink() -> Option<Uplink> {
+    let table = fs::read_to_string("/proc/net/route").ok()?;
+    table.lines().skip(1).find_map(|line| {
+        // "Iface Destination Gateway Flags RefCnt Use Metric Mask ..."
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.get(1) != Some(&"00000000") || fields.get(7) != Some(&"00000000") {
+            return None;
+        }
+        // Little endian hex
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        Some(Uplink {
+            interface: fields[0].to_string(),
+            gateway: std::net::Ipv4Addr::from(gateway.swap_bytes()).to_string(),
+        })
+    })
+}
+
+struct Link {
+    index: i32,
+    name: String,
+    up: bool,
+}
+
+/// ifinfomsg followed by attributes
+fn parse_link(body: &[u8]) -> Option<Link> {
+    let header = body.get(..16)?;
+    let index = i32::from_ne_bytes([header[4], header[5], header[6], header[7]]);
+    let flags = u32::from_ne_bytes([header[8], header[9], header[10], header[11]]);
+
+    let mut name = None;
+    let mut offset = 16;
+    while let Some(attr) = body.get(offset..offset + 4) {
+        let len = u16::from_ne_bytes([attr[0], attr[1]]) as usize;
+        let kind = u16::from_ne_bytes([attr[2], attr[3]]);
+        let data = body.get(offset + 4..offset + len.max(4))?;
+        if kind == libc::IFLA_IFNAME {
+            name = Some(
+                String::from_utf8_lossy(data)
+                    .trim_end_matches('\0')
+                    .to_string(),
+            );
+        }
+        offset += align(len.max(4));
+    }
+
+    // Carrier, not just administratively up
+    let up = flags & libc::IFF_UP as u32 != 0 && flags & libc::IFF_LOWER_UP as u32 != 0;
+    Some(Link {
+        index,
+        name: name?,
+        up,
+    })
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// NETLINK_ROUTE socket subscribed to link, address and route groups
+struct RouteSocket {
+    fd: OwnedFd,
+}
+
+impl RouteSocket {
+    fn open() -> Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("Failed to open rtnetlink socket");
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as u16;
+        addr.nl_groups = (libc::RTMGRP_LINK
+            | libc::RTMGRP_IPV4_IFADDR
+            | libc::RTMGRP_IPV4_ROUTE
+            | libc::RTMGRP_IPV6_IFADDR
+            | libc::RTMGRP_IPV6_ROUTE) as u32;
+        let bound = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as u32,
+            )
+        };
+        if bound < 0 {
+            return Err(io::Error::last_os_error()).context("Failed to bind rtnetlink socket");
+        }
+
+        let tv = libc::timeval {
+            tv_sec: RECV_TIMEOUT as libc::time_t,
+            tv_usec: 0,
+        };
+        unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &tv as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as u32,
+            );
+        }
+
+        Ok(Self { fd })
+    }
+
+    /// (message type, payload after nlmsghdr) of everything received
+    /// within `timeout`; empty if nothing happened
+    fn recv(&self, timeout: Duration) -> Result<Vec<(u16, Vec<u8>)>> {
+        let deadline = Instant::now() + timeout;
+        let mut buf = vec![0u8; RECV_BUFFER];
+        let mut messages = Vec::new();
+
+        while Instant::now() < deadline {
+            let n = unsafe {
+                libc::recv(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                )
+            };
+            if n < 0 {
+                let e = io::Error::last_os_error();
+                match e.raw_os_error() {
+                    Some(libc::EAGAIN) | Some(libc::EINTR) => break,
+                    // Overrun: events were lost, treat it as "everything changed"
+                    Some(libc::ENOBUFS) => {
+                        messages.push((libc::RTM_NEWROUTE, Vec::new()));
+                        continue;
+                    }
+                    _ => return Err(e).context("Failed to read from rtnetlink socket"),
+                }
+            }
+
+            let mut offset = 0;
+            let received = &buf[..n as usize];
+            while let Some(header) = received.get(offset..offset + 16) {
+                let len = u32::from_ne_bytes([header[0], header[1], header[2], header[3]]) as usize;
+                let kind = u16::from_ne_bytes([header[4], header[5]]);
+                let body = match received.get(offset + 16..offset + len) {
+                    Some(b) => b,
+                    None => break,
+                };
+                messages.push((kind, body.to_vec()));
+                offset += align(len.max(16));
+            }
+        }
+        Ok(messages)
+    }
+}
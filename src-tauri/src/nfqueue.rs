@@ -0,0 +1,315 @@
+use anyhow::{Context, Result};
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::Duration;
+
+// linux/netfilter/nfnetlink_queue.h
+const NFNL_SUBSYS_QUEUE: u16 = 3;
+const NFQNL_MSG_PACKET: u16 = 0;
+const NFQNL_MSG_VERDICT: u16 = 1;
+const NFQNL_MSG_CONFIG: u16 = 2;
+const NFQA_CFG_CMD: u16 = 1;
+const NFQA_CFG_PARAMS: u16 = 2;
+const NFQNL_CFG_CMD_BIND: u8 = 1;
+const NFQNL_COPY_PACKET: u8 = 2;
+const NFQA_PACKET_HDR: u16 = 1;
+const NFQA_VERDICT_HDR: u16 = 2;
+const NFQA_MARK: u16 = 3;
+const NFQA_PAYLOAD: u16 = 10;
+const NLA_TYPE_MASK: u16 = 0x3fff;
+const NF_DROP: u32 = 0;
+const NF_ACCEPT: u32 = 1;
+const NF_REPEAT: u32 = 4;
+
+// Only the IP and transport headers are needed to identify a connection
+const COPY_RANGE: u32 = 128;
+const RECV_BUFFER: usize = 65536;
+
+pub enum Verdict {
+    Drop,
+    Accept,
+    /// Sends the packet through the hook again with this mark set
+    Repeat(u32),
+}
+
+pub struct QueuedPacket {
+    pub id: u32,
+    /// The packet from the IP header on, cut at COPY_RANGE
+    pub payload: Vec<u8>,
+}
+
+/// A bound NFQUEUE netlink socket. Receiving and sending verdicts can
+/// happen from different threads.
+pub struct NfQueue {
+    fd: OwnedFd,
+    queue: u16,
+}
+
+impl NfQueue {
+    pub fn bind(queue: u16) -> Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_NETFILTER,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("Failed to open netfilter socket");
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as u16;
+        let bound = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as u32,
+            )
+        };
+        if bound < 0 {
+            return Err(io::Error::last_os_error()).context("Failed to bind netfilter socket");
+        }
+
+        let nfq = Self { fd, queue };
+        // Command, padding, protocol family (ignored for BIND)
+        nfq.configure(&attr(NFQA_CFG_CMD, &[NFQNL_CFG_CMD_BIND, 0, 0, 0]))
+            .context(format!("Failed to bind NFQUEUE {}", queue))?;
+
+        let mut params = COPY_RANGE.to_be_bytes().to_vec();
+        params.push(NFQNL_COPY_PACKET);
+        nfq.configure(&attr(NFQA_CFG_PARAMS, &params))
+            .context("Failed to set NFQUEUE copy mode")?;
+
+        Ok(nfq)
+    }
+
+    /// Waits up to `timeout` for queued packets; an empty list means none came
+    pub fn recv(&self, timeout: Duration) -> Result<Vec<QueuedPacket>> {
+        let tv = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+        };
+        unsafe {
+            libc::setsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &tv as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as u32,
+            );
+        }
+
+        let buf = match self.recv_raw() {
+            Ok(b) => b,
+            Err(e)
+                if matches!(
+                    e.raw_os_error(),
+                    Some(libc::EAGAIN) | Some(libc::EINTR) | Some(libc::ENOBUFS)
+                ) =>
+            {
+                // ENOBUFS: the kernel dropped messages we were too slow for
+                return Ok(Vec::new());
+            }
+            Err(e) => return Err(e).context("Failed to read from NFQUEUE"),
+        };
+
+        Ok(messages(&buf)
+            .filter(|(kind, _)| *kind == (NFNL_SUBSYS_QUEUE << 8) | NFQNL_MSG_PACKET)
+            .filter_map(|(_, body)| parse_packet(body))
+            .collect())
+    }
+
+    pub fn verdict(&self, id: u32, verdict: Verdict) -> Result<()> {
+        let (code, mark) = match verdict {
+            Verdict::Drop => (NF_DROP, None),
+            Verdict::Accept => (NF_ACCEPT, None),
+            Verdict::Repeat(mark) => (NF_REPEAT, Some(mark)),
+        };
+
+        let mut header = code.to_be_bytes().to_vec();
+        header.extend(id.to_be_bytes());
+        let mut attrs = attr(NFQA_VERDICT_HDR, &header);
+        if let Some(mark) = mark {
+            attrs.extend(attr(NFQA_MARK, &mark.to_be_bytes()));
+        }
+
+        self.send(&self.message(NFQNL_MSG_VERDICT, 0, &attrs))
+            .context("Failed to send NFQUEUE verdict")
+    }
+
+    /// Sends a config message and waits for the kernel's acknowledgement
+    fn configure(&self, attrs: &[u8]) -> Result<()> {
+        self.send(&self.message(NFQNL_MSG_CONFIG, libc::NLM_F_ACK as u16, attrs))?;
+
+        let buf = self.recv_raw()?;
+        for (kind, body) in messages(&buf) {
+            if kind == libc::NLMSG_ERROR as u16 && body.len() >= 4 {
+                let errno = i32::from_ne_bytes([body[0], body[1], body[2], body[3]]);
+                if errno != 0 {
+                    return Err(io::Error::from_raw_os_error(-errno).into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// nlmsghdr + nfgenmsg + attributes
+    fn message(&self, kind: u16, flags: u16, attrs: &[u8]) -> Vec<u8> {
+        let len = 16 + 4 + attrs.len();
+        let mut msg = Vec::with_capacity(len);
+        msg.extend((len as u32).to_ne_bytes());
+        msg.extend(((NFNL_SUBSYS_QUEUE << 8) | kind).to_ne_bytes());
+        msg.extend((libc::NLM_F_REQUEST as u16 | flags).to_ne_bytes());
+        msg.extend(0u32.to_ne_bytes()); // sequence
+        msg.extend(0u32.to_ne_bytes()); // port id, 0 = kernel
+        msg.push(libc::AF_UNSPEC as u8);
+        msg.push(0); // NFNETLINK_V0
+        msg.extend(self.queue.to_be_bytes());
+        msg.extend(attrs);
+        msg
+    }
+
+    fn send(&self, msg: &[u8]) -> io::Result<()> {
+        let sent = unsafe {
+            libc::send(
+                self.fd.as_raw_fd(),
+                msg.as_ptr() as *const libc::c_void,
+                msg.len(),
+                0,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn recv_raw(&self) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; RECV_BUFFER];
+        let n = unsafe {
+            libc::recv(
+                self.fd.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(n as usize);
+        Ok(buf)
+    }
+}
+
+fn attr(kind: u16, data: &[u8]) -> Vec<u8> {
+    let mut a = Vec::with_capacity(align(4 + data.len()));
+    a.extend(((4 + data.len()) as u16).to_ne_bytes());
+    a.extend(kind.to_ne_bytes());
+    a.extend(data);
+    a.resize(align(a.len()), 0);
+    a
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Splits a netlink datagram into (message type, payload after nlmsghdr)
+fn messages(buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let header = buf.get(offset..offset + 16)?;
+        let len = u32::from_ne_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let kind = u16::from_ne_bytes([header[4], header[5]]);
+        let body = buf.get(offset + 16..offset + len)?;
+        offset += align(len.max(16));
+        Some((kind, body))
+    })
+}
+
+/// Payload of NFQNL_MSG_PACKET: nfgenmsg, then attributes
+fn parse_packet(body: &[u8]) -> Option<QueuedPacket> {
+    let mut packet = QueuedPacket {
+        id: 0,
+        payload: Vec::new(),
+    };
+    let mut has_id = false;
+
+    let mut offset = 4;
+    while let Some(header) = body.get(offset..offset + 4) {
+        let len = u16::from_ne_bytes([header[0], header[1]]) as usize;
+        let kind = u16::from_ne_bytes([header[2], header[3]]) & NLA_TYPE_MASK;
+        let data = body.get(offset + 4..offset + len.max(4))?;
+
+        match kind {
+            NFQA_PACKET_HDR if data.len() >= 4 => {
+                packet.id = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                has_id = true;
+            }
+            NFQA_PAYLOAD => packet.payload = data.to_vec(),
+            _ => {}
+        }
+        offset += align(len.max(4));
+    }
+
+    has_id.then_some(packet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// nfgenmsg (family, version, resource id), then the attributes
+    fn body(attrs: &[Vec<u8>]) -> Vec<u8> {
+        let mut b = vec![libc::AF_INET as u8, 0, 0, 0];
+        for a in attrs {
+            b.extend(a);
+        }
+        b
+    }
+
+    /// nfqnl_msg_packet_hdr: packet id, hardware protocol, hook
+    fn packet_hdr(id: u32) -> Vec<u8> {
+        let mut hdr = id.to_be_bytes().to_vec();
+        hdr.extend(0x0800u16.to_be_bytes());
+        hdr.push(3);
+        attr(NFQA_PACKET_HDR, &hdr)
+    }
+
+    #[test]
+    fn parses_id_and_payload() {
+        // An unknown attribute in between is skipped, padding included
+        let body = body(&[
+            packet_hdr(42),
+            attr(NFQA_MARK, &7u32.to_be_bytes()),
+            attr(NFQA_PAYLOAD, &[0x45, 0, 0, 40, 1]),
+        ]);
+        let packet = parse_packet(&body).unwrap();
+        assert_eq!(packet.id, 42);
+        assert_eq!(packet.payload, vec![0x45, 0, 0, 40, 1]);
+    }
+
+    #[test]
+    fn nested_flag_is_masked_off() {
+        let mut hdr = packet_hdr(9);
+        let kind = (NFQA_PACKET_HDR | 0x8000).to_ne_bytes();
+        hdr[2..4].copy_from_slice(&kind);
+        assert_eq!(parse_packet(&body(&[hdr])).unwrap().id, 9);
+    }
+
+    #[test]
+    fn needs_a_packet_id() {
+        assert!(parse_packet(&body(&[attr(NFQA_PAYLOAD, &[0x45])])).is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_attributes() {
+        let mut body = body(&[packet_hdr(1), attr(NFQA_PAYLOAD, &[0x45; 8])]);
+        body.truncate(body.len() - 4);
+        assert!(parse_packet(&body).is_none());
+    }
+}
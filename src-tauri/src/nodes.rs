@@ -0,0 +1,85 @@
+use crate::torctl::TorControl;
+use anyhow::Result;
+
+/// Country restrictions for relay selection, stored as Tor "{cc}" entries
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct NodePolicy {
+    pub exit_nodes: Vec<String>,
+    pub entry_nodes: Vec<String>,
+    pub exclude_nodes: Vec<String>,
+    pub exclude_exit_nodes: Vec<String>,
+    pub strict_nodes: bool,
+}
+
+impl NodePolicy {
+    /// Lowercases every entry and checks it against Tor's "{cc}" syntax
+    pub fn normalize(mut self) -> Result<Self> {
+        for list in [
+            &mut self.exit_nodes,
+            &mut self.entry_nodes,
+            &mut self.exclude_nodes,
+            &mut self.exclude_exit_nodes,
+        ] {
+            for entry in list.iter_mut() {
+                *entry = entry.trim().to_lowercase();
+                if !is_country_code(entry) {
+                    return Err(anyhow::anyhow!(
+                        "Invalid country \"{}\": expected Tor's {{cc}} syntax, e.g. {{de}}",
+                        entry
+                    ));
+                }
+            }
+            list.sort();
+            list.dedup();
+        }
+        Ok(self)
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("ExitNodes", self.exit_nodes.join(",")),
+            ("EntryNodes", self.entry_nodes.join(",")),
+            ("ExcludeNodes", self.exclude_nodes.join(",")),
+            ("ExcludeExitNodes", self.exclude_exit_nodes.join(",")),
+            ("StrictNodes", if self.strict_nodes { "1" } else { "0" }.to_string()),
+        ]
+    }
+
+    /// Lines for the Aegis torrc drop-in (empty lists are left out)
+    pub fn torrc_lines(&self) -> Vec<String> {
+        self.options()
+            .into_iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(key, value)| format!("{} {}", key, value))
+            .collect()
+    }
+
+    /// Applies the policy to the running Tor and discards circuits built
+    /// under the old one
+    pub fn apply(&self) -> Result<()> {
+        // An option without a value resets it to Tor's default
+        let args: Vec<String> = self
+            .options()
+            .into_iter()
+            .map(|(key, value)| {
+                if value.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}=\"{}\"", key, value)
+                }
+            })
+            .collect();
+
+        let mut ctl = TorControl::connect()?;
+        ctl.command(&format!("SETCONF {}", args.join(" ")))?;
+        ctl.signal("NEWNYM")
+    }
+}
+
+fn is_country_code(entry: &str) -> bool {
+    match entry.strip_prefix('{').and_then(|e| e.strip_suffix('}')) {
+        Some(cc) => cc == "??" || (cc.len() == 2 && cc.chars().all(|c| c.is_ascii_lowercase())),
+        None => false,
+    }
+}
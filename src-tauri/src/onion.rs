@@ -0,0 +1,339 @@
+use crate::config;
+use crate::torctl::TorControl;
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::thread;
+use std::time::Duration;
+
+const ONIONS_FILE: &str = "onions.json";
+// Encrypts persistent service keys at rest; readable by root only
+const KEY_FILE: &str = "onion.key";
+
+/// `virtual_port` on the onion address maps to `target_port` on loopback
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct OnionPort {
+    pub virtual_port: u16,
+    pub target_port: u16,
+}
+
+/// A v3 client authorization key; the private half is only shown once
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct ClientAuth {
+    pub name: String,
+    pub public_key: String,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct OnionService {
+    pub service_id: String,
+    pub ports: Vec<OnionPort>,
+    pub persistent: bool,
+    #[serde(default)]
+    pub clients: Vec<ClientAuth>,
+    /// ED25519-V3 key sealed with the Aegis onion key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encrypted_key: Option<String>,
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct OnionRequest {
+    pub ports: Vec<OnionPort>,
+    #[serde(default)]
+    pub persistent: bool,
+    /// Names of clients to generate authorization keys for
+    #[serde(default)]
+    pub clients: Vec<String>,
+}
+
+/// What the UI needs to hand out: the address and each client's key line
+#[derive(Clone, serde::Serialize)]
+pub struct CreatedOnion {
+    pub service: OnionService,
+    pub address: String,
+    pub client_keys: Vec<ClientKey>,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct ClientKey {
+    pub name: String,
+    /// "<service id>:descriptor:x25519:<key>", a .auth_private file for
+    /// the client's ClientOnionAuthDir
+    pub auth_line: String,
+}
+
+impl OnionService {
+    pub fn address(&self) -> String {
+        format!("{}.onion", self.service_id)
+    }
+}
+
+/// Creates a detached service: it outlives the control connection, and
+/// persistent ones are re-added with the same key after Tor restarts
+pub fn create(request: &OnionRequest) -> Result<CreatedOnion> {
+    if request.ports.is_empty() {
+        return Err(anyhow::anyhow!("An onion service needs at least one port"));
+    }
+    if request
+        .ports
+        .iter()
+        .any(|p| p.virtual_port == 0 || p.target_port == 0)
+    {
+        return Err(anyhow::anyhow!("Ports must be between 1 and 65535"));
+    }
+
+    let mut clients = Vec::new();
+    let mut secrets = Vec::new();
+    for name in &request.clients {
+        let (secret, public) = client_keypair();
+        clients.push(ClientAuth {
+            name: name.clone(),
+            public_key: public,
+        });
+        secrets.push((name.clone(), secret));
+    }
+
+    let key = if request.persistent {
+        "NEW:ED25519-V3"
+    } else {
+        "NEW:ED25519-V3 Flags=DiscardPK"
+    };
+    let reply = add_onion(key, &request.ports, &clients)?;
+
+    let service_id = reply_value(&reply, "ServiceID")
+        .ok_or_else(|| anyhow::anyhow!("Tor didn't return a service id"))?;
+    let encrypted_key = match reply_value(&reply, "PrivateKey") {
+        Some(k) if request.persistent => Some(seal(&service_id, &k)?),
+        _ => None,
+    };
+
+    let service = OnionService {
+        service_id,
+        ports: request.ports.clone(),
+        persistent: request.persistent,
+        clients,
+        encrypted_key,
+    };
+
+    let mut services = load();
+    services.push(service.clone());
+    save(&services)?;
+
+    let address = service.address();
+    let client_keys = secrets
+        .into_iter()
+        .map(|(name, secret)| ClientKey {
+            name,
+            auth_line: format!("{}:descriptor:x25519:{}", service.service_id, secret),
+        })
+        .collect();
+
+    Ok(CreatedOnion {
+        service,
+        address,
+        client_keys,
+    })
+}
+
+/// Services Aegis knows about that Tor is currently publishing
+pub fn list() -> Result<Vec<OnionService>> {
+    let running = TorControl::connect()?.getinfo("onions/detached")?;
+    let running: Vec<&str> = running.split_whitespace().collect();
+
+    Ok(load()
+        .into_iter()
+        .filter(|s| running.contains(&s.service_id.as_str()))
+        .collect())
+}
+
+/// Stored services, running or not
+pub fn load() -> Vec<OnionService> {
+    fs::read_to_string(config::data_path(ONIONS_FILE))
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+pub fn delete(service_id: &str) -> Result<()> {
+    if !service_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(anyhow::anyhow!("Invalid service id: {}", service_id));
+    }
+
+    // Tor forgets ephemeral services on restart, so a missing one is fine
+    let removed = TorControl::connect()?.command(&format!("DEL_ONION {}", service_id));
+
+    let mut services = load();
+    let before = services.len();
+    services.retain(|s| s.service_id != service_id);
+    if services.len() == before {
+        return removed.map(|_| ());
+    }
+    save(&services)
+}
+
+/// Re-adds persistent services, e.g. after Tor was restarted. Ephemeral
+/// ones are gone for good and dropped from the store. Returns how many
+/// came back and why the others didn't; those stay in the store.
+pub fn restore() -> Result<(usize, Vec<String>)> {
+    let mut ctl = connect_with_retry()?;
+    let running = ctl.getinfo("onions/detached").unwrap_or_default();
+
+    let services: Vec<OnionService> = load().into_iter().filter(|s| s.persistent).collect();
+    let mut restored = 0;
+    let mut failed = Vec::new();
+    for service in &services {
+        if running
+            .split_whitespace()
+            .any(|id| id == service.service_id)
+        {
+            continue;
+        }
+        let key = match &service.encrypted_key {
+            Some(k) => open_sealed(&service.service_id, k),
+            None => continue,
+        };
+        match key.and_then(|k| add_onion(&k, &service.ports, &service.clients)) {
+            Ok(_) => restored += 1,
+            Err(e) => failed.push(format!("{}: {}", service.address(), e)),
+        }
+    }
+
+    save(&services)?;
+    Ok((restored, failed))
+}
+
+fn add_onion(key: &str, ports: &[OnionPort], clients: &[ClientAuth]) -> Result<Vec<String>> {
+    let mut cmd = format!("ADD_ONION {}", key);
+    // Detach goes into the one Flags= argument Tor accepts
+    if cmd.contains("Flags=") {
+        cmd.push_str(",Detach");
+    } else {
+        cmd.push_str(" Flags=Detach");
+    }
+    for p in ports {
+        cmd.push_str(&format!(
+            " Port={},127.0.0.1:{}",
+            p.virtual_port, p.target_port
+        ));
+    }
+    for c in clients {
+        cmd.push_str(&format!(" ClientAuthV3={}", c.public_key));
+    }
+
+    TorControl::connect()?.command(&cmd)
+}
+
+fn reply_value(reply: &[String], key: &str) -> Option<String> {
+    let prefix = format!("{}=", key);
+    reply
+        .iter()
+        .find_map(|l| l.strip_prefix(&prefix).map(String::from))
+}
+
+fn connect_with_retry() -> Result<TorControl> {
+    let mut last = None;
+    for _ in 0..5 {
+        match TorControl::connect() {
+            Ok(ctl) => return Ok(ctl),
+            Err(e) => last = Some(e),
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+    Err(last.unwrap_or_else(|| anyhow::anyhow!("Tor control port unavailable")))
+}
+
+fn save(services: &[OnionService]) -> Result<()> {
+    let path = config::ensure_data_path(ONIONS_FILE)?;
+    fs::write(path, serde_json::to_string_pretty(services)?)
+        .context("Failed to save onion services")
+}
+
+/// Returns (private, public) x25519 keys, base32 as Tor expects them
+fn client_keypair() -> (String, String) {
+    let mut secret: [u8; 32] = rand::random();
+    secret[0] &= 248;
+    secret[31] &= 127;
+    secret[31] |= 64;
+    let public = x25519_dalek::x25519(secret, x25519_dalek::X25519_BASEPOINT_BYTES);
+    (base32(&secret), base32(&public))
+}
+
+/// RFC 4648 base32 without padding
+fn base32(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut out = String::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+/// Loads the sealing key, creating it on first use
+fn sealing_key() -> Result<LessSafeKey> {
+    let path = config::ensure_data_path(KEY_FILE)?;
+    let bytes = match fs::read(&path) {
+        Ok(b) if b.len() == 32 => b,
+        _ => {
+            let key: [u8; 32] = rand::random();
+            fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(&path)
+                .and_then(|mut f| f.write_all(&key))
+                .context("Failed to write onion key file")?;
+            key.to_vec()
+        }
+    };
+
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &bytes)
+        .map_err(|_| anyhow::anyhow!("Invalid onion key file"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Encrypts a service key, bound to its service id
+fn seal(service_id: &str, private_key: &str) -> Result<String> {
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let mut data = private_key.as_bytes().to_vec();
+    sealing_key()?
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(service_id.as_bytes()),
+            &mut data,
+        )
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt onion key"))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(data);
+    Ok(BASE64.encode(sealed))
+}
+
+fn open_sealed(service_id: &str, sealed: &str) -> Result<String> {
+    let sealed = BASE64.decode(sealed).context("Corrupt onion key")?;
+    if sealed.len() < NONCE_LEN {
+        return Err(anyhow::anyhow!("Corrupt onion key"));
+    }
+    let (nonce, data) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| anyhow::anyhow!("Corrupt onion key"))?;
+
+    let mut data = data.to_vec();
+    let plain = sealing_key()?
+        .open_in_place(nonce, Aad::from(service_id.as_bytes()), &mut data)
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt key of {}", service_id))?;
+    String::from_utf8(plain.to_vec()).context("Corrupt onion key")
+}
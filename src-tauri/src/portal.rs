@@ -0,0 +1,444 @@
+use crate::anonsurf::{Anonsurf, AnonsurfState};
+use crate::bridges;
+use crate::config::{self, ConfigState};
+use crate::dns;
+use crate::iptables::{self, IptablesManager};
+use anyhow::{Context, Result};
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+
+// Unprivileged system user the portal browser runs as; it is the only
+// thing let through the killswitch while the exception is open
+const PORTAL_USER: &str = "aegis-portal";
+const PORTAL_HOME: &str = "portal-home";
+const PORTAL_RESOLV: &str = "portal-resolv.conf";
+const PORTAL_NSSWITCH: &str = "portal-nsswitch.conf";
+const PROBE_TIMEOUT: u64 = 5; // seconds
+const CHECK_INTERVAL: u64 = 5; // seconds
+const MAX_OPEN: u64 = 900; // seconds, closes even if Tor never bootstraps
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PortalSettings {
+    /// Plain HTTP page with a known body; portals intercept or redirect it
+    pub probe_url: String,
+    pub expected_body: String,
+    /// Browser command, run as the portal user with a throwaway profile
+    pub browser: String,
+}
+
+impl Default for PortalSettings {
+    fn default() -> Self {
+        Self {
+            probe_url: "http://detectportal.firefox.com/success.txt".to_string(),
+            expected_body: "success".to_string(),
+            browser: "firefox --no-remote --new-instance".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct PortalProbe {
+    pub portal: bool,
+    pub status: Option<u16>,
+    /// Where the portal redirected the probe, if it did
+    pub location: Option<String>,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct PortalSession {
+    pub uid: u32,
+    pub browser_pid: Option<u32>,
+    pub opened_secs_ago: u64,
+}
+
+struct OpenPortal {
+    uid: u32,
+    browser_pid: Option<u32>,
+    opened: Instant,
+}
+
+pub struct PortalState {
+    session: Mutex<Option<OpenPortal>>,
+}
+
+impl PortalState {
+    pub fn new() -> Self {
+        Self {
+            session: Mutex::new(None),
+        }
+    }
+
+    pub fn current(&self) -> Option<PortalSession> {
+        self.session
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|p| PortalSession {
+                uid: p.uid,
+                browser_pid: p.browser_pid,
+                opened_secs_ago: p.opened.elapsed().as_secs(),
+            })
+    }
+}
+
+impl PortalSettings {
+    pub fn validate(&self) -> Result<()> {
+        if !self.probe_url.starts_with("http://") {
+            return Err(anyhow::anyhow!(
+                "The probe URL must be plain http://, portals can't intercept HTTPS"
+            ));
+        }
+        if self.browser.trim().is_empty() {
+            return Err(anyhow::anyhow!("No browser command given"));
+        }
+        Ok(())
+    }
+
+    /// Fetches the probe page as the portal user, i.e. through the exception
+    pub fn probe(&self) -> Result<PortalProbe> {
+        self.probe_with(as_portal_user("curl"))
+    }
+
+    fn probe_with(&self, mut curl: Command) -> Result<PortalProbe> {
+        let output = curl
+            .args(["-s", "-m"])
+            .arg(PROBE_TIMEOUT.to_string())
+            .args(["-w", "\n%{http_code} %{redirect_url}"])
+            .arg(&self.probe_url)
+            .output()
+            .context("Failed to run the portal probe")?;
+
+        // Body, then "<status> <redirect url>" on the last line
+        let text = String::from_utf8_lossy(&output.stdout);
+        let (body, meta) = text.rsplit_once('\n').unwrap_or(("", &text));
+        let mut meta = meta.split_whitespace();
+        let status = meta.next().and_then(|s| s.parse().ok()).filter(|s| *s != 0);
+        let location = meta.next().map(String::from);
+
+        if status.is_none() {
+            return Err(anyhow::anyhow!("No network: the probe got no answer"));
+        }
+
+        Ok(PortalProbe {
+            portal: status != Some(200) || body.trim() != self.expected_body,
+            status,
+            location,
+        })
+    }
+}
+
+/// Opens the portal exception, checks for a portal and, if there is one,
+/// launches the browser. The exception closes once Tor has bootstrapped.
+pub fn open(app: &AppHandle) -> Result<PortalProbe> {
+    if !*app.state::<AnonsurfState>().is_active.lock().unwrap() {
+        return Err(anyhow::anyhow!(
+            "The shield is off, the network is already open"
+        ));
+    }
+    let state = app.state::<PortalState>();
+    if state.session.lock().unwrap().is_some() {
+        return Err(anyhow::anyhow!(
+            "The captive portal exception is already open"
+        ));
+    }
+    let settings = app
+        .state::<ConfigState>()
+        .config
+        .lock()
+        .unwrap()
+        .portal
+        .clone();
+    settings.validate()?;
+
+    let uid = ensure_user()?;
+    write_resolver_files()?;
+    // The ruleset lets the portal user out while a session is recorded
+    *state.session.lock().unwrap() = Some(OpenPortal {
+        uid,
+        browser_pid: None,
+        opened: Instant::now(),
+    });
+    if let Err(e) = reapply(app) {
+        close(app)?;
+        return Err(e);
+    }
+    let _ = app.emit(
+        "app_log",
+        format!("Captive portal exception opened for {}", PORTAL_USER),
+    );
+
+    let probe = match settings.probe() {
+        Ok(p) if p.portal => p,
+        result => {
+            close(app)?;
+            return result;
+        }
+    };
+
+    let url = probe.location.clone().unwrap_or(settings.probe_url.clone());
+    match launch_browser(&settings.browser, &url) {
+        Ok(pid) => {
+            if let Some(session) = state.session.lock().unwrap().as_mut() {
+                session.browser_pid = Some(pid);
+            }
+        }
+        Err(e) => {
+            close(app)?;
+            return Err(e);
+        }
+    }
+
+    watch_bootstrap(app.clone());
+    Ok(probe)
+}
+
+/// Closes the exception and ends everything the portal user runs
+pub fn close(app: &AppHandle) -> Result<()> {
+    let session = app.state::<PortalState>().session.lock().unwrap().take();
+    if session.is_none() {
+        return Ok(());
+    }
+
+    // Shut the exception before ending what the portal user runs
+    let closed = reapply(app);
+    iptables::execute_batch(&[format!("(pkill -u {} || true)", PORTAL_USER)])?;
+    display_access(false);
+    closed?;
+
+    let _ = app.emit("captive_portal_closed", ());
+    let _ = app.emit("app_log", "Captive portal exception closed".to_string());
+    Ok(())
+}
+
+/// Rebuilds the live ruleset, which carries the exception while a session
+/// is recorded. Once the shield is off there is nothing to rebuild.
+fn reapply(app: &AppHandle) -> Result<()> {
+    let state = app.state::<AnonsurfState>();
+    let _transition = state.transition.lock().unwrap();
+    if !*state.is_active.lock().unwrap() {
+        return Ok(());
+    }
+    let config = app.state::<ConfigState>().config.lock().unwrap().clone();
+    IptablesManager::reapply_rules(&Anonsurf::ruleset(app, &config)?)
+}
+
+/// Closes the exception once the portal lets the probe through and Tor
+/// reports a complete bootstrap. Tor keeps reporting 100% from before the
+/// network changed, so the bootstrap alone doesn't prove anything.
+fn watch_bootstrap(app: AppHandle) {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(CHECK_INTERVAL));
+
+        let opened = match app.state::<PortalState>().session.lock().unwrap().as_ref() {
+            Some(p) => p.opened,
+            None => return, // closed by hand
+        };
+
+        let config = app.state::<ConfigState>().config.lock().unwrap().clone();
+        let accepted = config.portal.probe().map(|p| !p.portal).unwrap_or(false);
+        let bootstrapped = accepted
+            && bridges::bootstrap_status(&config.bridges)
+                .map(|s| s.progress >= 100)
+                .unwrap_or(false);
+
+        if bootstrapped || opened.elapsed().as_secs() > MAX_OPEN {
+            let reason = if bootstrapped {
+                "Tor bootstrapped"
+            } else {
+                "time limit reached"
+            };
+            let _ = app.emit(
+                "app_log",
+                format!("Closing captive portal exception: {}", reason),
+            );
+            if let Err(e) = close(&app) {
+                let _ = app.emit(
+                    "security_alert",
+                    format!("Failed to close captive portal exception: {}", e),
+                );
+            }
+            return;
+        }
+    });
+}
+
+/// Runs `program` as the portal user in a mount namespace of its own, where
+/// resolv.conf lists the network's resolvers. The system one points at
+/// Tor's DNSPort, which can't answer until the portal lets Tor out.
+fn as_portal_user(program: &str) -> Command {
+    let script = format!(
+        "mount --bind \"$1\" /etc/resolv.conf && mount --bind \"$2\" /etc/nsswitch.conf && shift 2 && exec runuser -u {} -- \"$@\"",
+        PORTAL_USER
+    );
+    let mut command = Command::new("unshare");
+    command
+        .args(["--mount", "--propagation", "private", "--", "sh", "-c"])
+        .arg(script)
+        .arg("sh")
+        .arg(config::data_path(PORTAL_RESOLV))
+        .arg(config::data_path(PORTAL_NSSWITCH))
+        .arg(program);
+    command
+}
+
+/// The resolv.conf and nsswitch.conf `as_portal_user` mounts. Names are
+/// looked up in resolv.conf only, not through systemd-resolved.
+fn write_resolver_files() -> Result<()> {
+    let servers = dns::upstream_servers();
+    if servers.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No resolver from the network to look up the portal with"
+        ));
+    }
+    let resolv: String = servers
+        .iter()
+        .map(|s| format!("nameserver {}\n", s))
+        .collect();
+    std::fs::write(config::ensure_data_path(PORTAL_RESOLV)?, resolv)
+        .context("Failed to write the portal resolv.conf")?;
+    std::fs::write(
+        config::ensure_data_path(PORTAL_NSSWITCH)?,
+        "passwd: files\ngroup: files\nshadow: files\nhosts: files dns\n",
+    )
+    .context("Failed to write the portal nsswitch.conf")
+}
+
+/// Creates the portal system user on first use and returns its uid
+fn ensure_user() -> Result<u32> {
+    if let Some(uid) = uid_of(PORTAL_USER) {
+        return Ok(uid);
+    }
+
+    let status = Command::new("useradd")
+        .args([
+            "--system",
+            "--no-create-home",
+            "--shell",
+            "/usr/sbin/nologin",
+        ])
+        .arg(PORTAL_USER)
+        .status()
+        .context("Failed to create the portal user")?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("Failed to create the portal user"));
+    }
+
+    uid_of(PORTAL_USER).ok_or_else(|| anyhow::anyhow!("Portal user has no uid"))
+}
+
+fn uid_of(user: &str) -> Option<u32> {
+    let output = Command::new("id").args(["-u", user]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// Starts the browser as the portal user with a fresh profile, so no
+/// cookies or history cross between it and the user's own browser
+fn launch_browser(browser: &str, url: &str) -> Result<u32> {
+    let home = config::ensure_data_path(PORTAL_HOME)?;
+    let _ = std::fs::remove_dir_all(&home);
+    std::fs::create_dir_all(&home).context("Failed to create portal browser home")?;
+    Command::new("chown")
+        .arg(PORTAL_USER)
+        .arg(&home)
+        .status()
+        .context("Failed to hand the portal home to the portal user")?;
+
+    display_access(true);
+    let child = as_portal_user("env")
+        .arg(format!("HOME={}", home.display()))
+        .args(browser.split_whitespace())
+        .arg(url)
+        .spawn()
+        .context("Failed to launch the portal browser")?;
+
+    Ok(child.id())
+}
+
+/// Lets the portal user draw on the desktop user's X display (and back)
+fn display_access(grant: bool) {
+    let user = match std::env::var("SUDO_USER") {
+        Ok(u) => u,
+        Err(_) => return,
+    };
+    let sign = if grant { "+" } else { "-" };
+    let _ = Command::new("runuser")
+        .args(["-u", &user, "--", "xhost"])
+        .arg(format!("{}SI:localuser:{}", sign, PORTAL_USER))
+        .output();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Local stand-in for the probe page, answering one request with `response`
+    fn probe_stand_in(response: &'static str) -> PortalSettings {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/success.txt", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        PortalSettings {
+            probe_url: url,
+            ..PortalSettings::default()
+        }
+    }
+
+    #[test]
+    fn expected_page_means_no_portal() {
+        let settings = probe_stand_in(
+            "HTTP/1.1 200 OK\r\nContent-Length: 8\r\nConnection: close\r\n\r\nsuccess\n",
+        );
+        let probe = settings.probe_with(Command::new("curl")).unwrap();
+
+        assert!(!probe.portal);
+        assert_eq!(probe.status, Some(200));
+    }
+
+    #[test]
+    fn redirect_is_a_portal() {
+        let settings = probe_stand_in(
+            "HTTP/1.1 302 Found\r\nLocation: http://192.0.2.1/login\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+        let probe = settings.probe_with(Command::new("curl")).unwrap();
+
+        assert!(probe.portal);
+        assert_eq!(probe.status, Some(302));
+        assert_eq!(probe.location.as_deref(), Some("http://192.0.2.1/login"));
+    }
+
+    #[test]
+    fn rewritten_page_is_a_portal() {
+        let settings = probe_stand_in(
+            "HTTP/1.1 200 OK\r\nContent-Length: 14\r\nConnection: close\r\n\r\nPlease log in\n",
+        );
+        let probe = settings.probe_with(Command::new("curl")).unwrap();
+
+        assert!(probe.portal);
+        assert_eq!(probe.location, None);
+    }
+
+    #[test]
+    fn no_answer_is_an_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let settings = PortalSettings {
+            probe_url: format!("http://{}/success.txt", listener.local_addr().unwrap()),
+            ..PortalSettings::default()
+        };
+        drop(listener);
+
+        assert!(settings.probe_with(Command::new("curl")).is_err());
+    }
+}
@@ -0,0 +1,445 @@
+use crate::anonsurf::{Anonsurf, AnonsurfState};
+use crate::config::ConfigState;
+use crate::netwatch::{self, Uplink};
+use crate::stealth;
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+
+// The gateway shows up in the ARP table once something talked to it
+const GATEWAY_MAC_ATTEMPTS: u32 = 5;
+const GATEWAY_MAC_DELAY: u64 = 1; // seconds
+
+// Spoofing the MAC takes the link down and up, which is no new network
+const SPOOF_GRACE: u64 = 30; // seconds
+
+/// What to do when joining a network
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ProfileActions {
+    pub spoof_mac: bool,
+    pub randomize_hostname: bool,
+    pub start_shield: bool,
+}
+
+/// A known network. Empty fields match anything, but at least one must
+/// be set. SSID and BSSID are known right after association, the
+/// gateway MAC only once DHCP handed out a lease.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct NetworkProfile {
+    pub name: String,
+    pub ssid: Option<String>,
+    pub bssid: Option<String>,
+    pub gateway_mac: Option<String>,
+    pub actions: ProfileActions,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct NetworkProfiles {
+    pub enabled: bool,
+    /// Checked in order, the first match wins
+    pub profiles: Vec<NetworkProfile>,
+    /// Applied on networks no profile matches
+    pub unknown: ProfileActions,
+}
+
+impl Default for NetworkProfiles {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            profiles: Vec::new(),
+            unknown: ProfileActions {
+                spoof_mac: true,
+                randomize_hostname: true,
+                start_shield: true,
+            },
+        }
+    }
+}
+
+impl NetworkProfiles {
+    /// Checks the profiles and lowercases MAC addresses
+    pub fn normalize(&mut self) -> Result<()> {
+        let mut names = Vec::new();
+        for profile in &mut self.profiles {
+            profile.name = profile.name.trim().to_string();
+            if profile.name.is_empty() {
+                return Err(anyhow::anyhow!("Every network profile needs a name"));
+            }
+            if names.contains(&profile.name) {
+                return Err(anyhow::anyhow!(
+                    "There are two profiles named '{}'",
+                    profile.name
+                ));
+            }
+            names.push(profile.name.clone());
+
+            profile.ssid = profile.ssid.take().filter(|s| !s.is_empty());
+            for mac in [&mut profile.bssid, &mut profile.gateway_mac] {
+                *mac = mac
+                    .take()
+                    .map(|m| m.trim().to_lowercase())
+                    .filter(|m| !m.is_empty());
+                if let Some(m) = mac {
+                    if !is_mac(m) {
+                        return Err(anyhow::anyhow!("Invalid MAC address: {}", m));
+                    }
+                }
+            }
+            if profile.ssid.is_none() && profile.bssid.is_none() && profile.gateway_mac.is_none() {
+                return Err(anyhow::anyhow!(
+                    "Profile '{}' needs an SSID, BSSID or gateway MAC",
+                    profile.name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What is known about the network an interface is on
+#[derive(Clone, Default, serde::Serialize)]
+pub struct NetworkIdentity {
+    pub interface: String,
+    pub ssid: Option<String>,
+    pub bssid: Option<String>,
+    pub gateway: Option<String>,
+    pub gateway_mac: Option<String>,
+}
+
+impl NetworkIdentity {
+    fn describe(&self) -> String {
+        match (&self.ssid, &self.gateway_mac) {
+            (Some(ssid), _) => format!("'{}' on {}", ssid, self.interface),
+            (None, Some(mac)) => format!("gateway {} on {}", mac, self.interface),
+            (None, None) => self.interface.clone(),
+        }
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct ProfileApplied {
+    pub network: NetworkIdentity,
+    /// None for an unknown network
+    pub profile: Option<String>,
+    pub actions: ProfileActions,
+}
+
+enum Decision {
+    Matched(NetworkProfile),
+    Unknown,
+    /// A profile may still match once more is known
+    Undecided,
+}
+
+pub struct ProfileState {
+    /// Decision applied last, so reconnects to the same network (our own
+    /// MAC spoofing included) don't apply it again
+    applied: Mutex<Option<String>>,
+    /// When a profile last spoofed the MAC
+    spoofed: Mutex<Option<Instant>>,
+}
+
+impl ProfileState {
+    pub fn new() -> Self {
+        Self {
+            applied: Mutex::new(None),
+            spoofed: Mutex::new(None),
+        }
+    }
+}
+
+/// A link or the default route went away, so whatever network comes next
+/// is decided afresh, even the same one again
+pub fn network_lost(app: &AppHandle) {
+    let state = app.state::<ProfileState>();
+    let own_doing = state
+        .spoofed
+        .lock()
+        .unwrap()
+        .is_some_and(|t| t.elapsed().as_secs() < SPOOF_GRACE);
+    if !own_doing {
+        *state.applied.lock().unwrap() = None;
+    }
+}
+
+/// A Wi-Fi link came up: decide on SSID/BSSID alone, before DHCP if we
+/// are fast enough
+pub fn link_up(app: &AppHandle, interface: &str) {
+    if !is_wireless(interface) {
+        return;
+    }
+    let (ssid, bssid) = wifi_link(interface);
+    if ssid.is_none() && bssid.is_none() {
+        return;
+    }
+    evaluate(
+        app,
+        NetworkIdentity {
+            interface: interface.to_string(),
+            ssid,
+            bssid,
+            ..Default::default()
+        },
+        false,
+    );
+}
+
+/// The default route changed: decide with the gateway known too
+pub fn uplink_changed(app: &AppHandle, uplink: &Uplink) {
+    let app = app.clone();
+    let uplink = uplink.clone();
+    thread::spawn(move || {
+        for attempt in 1..=GATEWAY_MAC_ATTEMPTS {
+            let network = identify(&uplink);
+            let last = attempt == GATEWAY_MAC_ATTEMPTS;
+            if network.gateway_mac.is_some() || last {
+                evaluate(&app, network, true);
+                return;
+            }
+            thread::sleep(Duration::from_secs(GATEWAY_MAC_DELAY));
+        }
+    });
+}
+
+/// The network the default route currently goes through
+pub fn current_network() -> Option<NetworkIdentity> {
+    netwatch::default_uplink().map(|u| identify(&u))
+}
+
+fn identify(uplink: &Uplink) -> NetworkIdentity {
+    let (ssid, bssid) = if is_wireless(&uplink.interface) {
+        wifi_link(&uplink.interface)
+    } else {
+        (None, None)
+    };
+    NetworkIdentity {
+        interface: uplink.interface.clone(),
+        ssid,
+        bssid,
+        gateway: Some(uplink.gateway.clone()),
+        gateway_mac: neighbour_mac(&uplink.gateway),
+    }
+}
+
+/// `complete`: nothing more will be learned about this network, so a
+/// profile that could still match counts as no match
+fn evaluate(app: &AppHandle, network: NetworkIdentity, complete: bool) {
+    let settings = app
+        .state::<ConfigState>()
+        .config
+        .lock()
+        .unwrap()
+        .network_profiles
+        .clone();
+    if !settings.enabled {
+        return;
+    }
+
+    let (key, profile, actions) = match decide(&settings.profiles, &network) {
+        Decision::Matched(p) => (format!("profile:{}", p.name), Some(p.name), p.actions),
+        Decision::Unknown => unknown(&network, settings.unknown),
+        Decision::Undecided if complete => unknown(&network, settings.unknown),
+        Decision::Undecided => return,
+    };
+
+    let state = app.state::<ProfileState>();
+    {
+        let mut applied = state.applied.lock().unwrap();
+        if applied.as_ref() == Some(&key) {
+            return;
+        }
+        *applied = Some(key);
+    }
+
+    let _ = app.emit(
+        "app_log",
+        format!(
+            "Network: {} is {}",
+            network.describe(),
+            match &profile {
+                Some(name) => format!("profile '{}'", name),
+                None => "unknown".to_string(),
+            }
+        ),
+    );
+    apply(
+        app,
+        ProfileApplied {
+            network,
+            profile,
+            actions,
+        },
+    );
+}
+
+fn unknown(
+    network: &NetworkIdentity,
+    actions: ProfileActions,
+) -> (String, Option<String>, ProfileActions) {
+    let id = network
+        .bssid
+        .as_ref()
+        .or(network.ssid.as_ref())
+        .or(network.gateway_mac.as_ref())
+        .or(network.gateway.as_ref())
+        .cloned()
+        .unwrap_or_default();
+    (
+        format!("unknown:{}:{}", network.interface, id),
+        None,
+        actions,
+    )
+}
+
+fn decide(profiles: &[NetworkProfile], network: &NetworkIdentity) -> Decision {
+    // Some(true): equal, Some(false): different, None: not known yet
+    let check = |wanted: &Option<String>, actual: &Option<String>| match (wanted, actual) {
+        (None, _) => Some(true),
+        (Some(_), None) => None,
+        (Some(w), Some(a)) => Some(w.eq_ignore_ascii_case(a)),
+    };
+
+    let mut undecided = false;
+    for profile in profiles {
+        let results = [
+            check(&profile.ssid, &network.ssid),
+            check(&profile.bssid, &network.bssid),
+            check(&profile.gateway_mac, &network.gateway_mac),
+        ];
+        if results.iter().all(|r| *r == Some(true)) {
+            return Decision::Matched(profile.clone());
+        }
+        if !results.contains(&Some(false)) {
+            undecided = true;
+        }
+    }
+
+    if undecided {
+        Decision::Undecided
+    } else {
+        Decision::Unknown
+    }
+}
+
+/// MAC first so DHCP sees the new one, then the hostname DHCP announces,
+/// then the shield. Emits `network_profile` once everything is done.
+fn apply(app: &AppHandle, applied: ProfileApplied) {
+    let network = &applied.network;
+    let actions = &applied.actions;
+    let log = |message: String| {
+        let _ = app.emit("app_log", format!("Network: {}", message));
+    };
+
+    if actions.spoof_mac {
+        *app.state::<ProfileState>().spoofed.lock().unwrap() = Some(Instant::now());
+        match stealth::spoof_mac(&network.interface) {
+            Ok(_) => log(format!(
+                "Spoofed MAC of {}: {}",
+                network.interface,
+                stealth::get_mac(&network.interface)
+            )),
+            Err(e) => log(format!("Failed to spoof MAC: {}", e)),
+        }
+    }
+
+    if actions.randomize_hostname {
+        match stealth::randomize_hostname() {
+            Ok(name) => log(format!("Hostname set to {}", name)),
+            Err(e) => log(format!("Failed to randomize hostname: {}", e)),
+        }
+    }
+
+    // Bootstrapping Tor takes a while, don't hold up network events
+    let app = app.clone();
+    thread::spawn(move || {
+        if applied.actions.start_shield {
+            start_shield(&app);
+        }
+        let _ = app.emit("network_profile", applied);
+    });
+}
+
+/// Starts the shield unless it is up already. The check happens under the
+/// transition lock, so a start from the UI in the meantime is seen.
+fn start_shield(app: &AppHandle) {
+    let state = app.state::<AnonsurfState>();
+    let _transition = state.transition.lock().unwrap();
+    if *state.is_active.lock().unwrap() {
+        return;
+    }
+
+    let _ = app.emit(
+        "app_log",
+        "Network: starting the shield for this network".to_string(),
+    );
+    match Anonsurf::start(app.clone()) {
+        Ok(_) => *state.is_active.lock().unwrap() = true,
+        Err(e) => {
+            let _ = app.emit(
+                "security_alert",
+                format!("Failed to start the shield on an untrusted network: {}", e),
+            );
+        }
+    }
+}
+
+fn is_wireless(interface: &str) -> bool {
+    Path::new("/sys/class/net")
+        .join(interface)
+        .join("wireless")
+        .exists()
+}
+
+/// SSID and BSSID from "iw dev <if> link":
+/// "Connected to aa:bb:cc:dd:ee:ff (on wlan0)\n\tSSID: Home ..."
+fn wifi_link(interface: &str) -> (Option<String>, Option<String>) {
+    let output = match Command::new("iw").args(["dev", interface, "link"]).output() {
+        Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout).to_string(),
+        _ => return (None, None),
+    };
+
+    let mut ssid = None;
+    let mut bssid = None;
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Connected to ") {
+            bssid = rest
+                .split_whitespace()
+                .next()
+                .map(|m| m.to_lowercase())
+                .filter(|m| is_mac(m));
+        } else if let Some(name) = line.strip_prefix("SSID: ") {
+            ssid = Some(name.to_string());
+        }
+    }
+    (ssid, bssid)
+}
+
+/// Hardware address of a neighbour from the ARP table
+fn neighbour_mac(ip: &str) -> Option<String> {
+    let table = fs::read_to_string("/proc/net/arp").ok()?;
+    table.lines().skip(1).find_map(|line| {
+        // "IP address HW type Flags HW address Mask Device"
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.first() != Some(&ip) {
+            return None;
+        }
+        let mac = fields.get(3)?.to_lowercase();
+        (is_mac(&mac) && mac != "00:00:00:00:00:00").then_some(mac)
+    })
+}
+
+fn is_mac(value: &str) -> bool {
+    let parts: Vec<&str> = value.split(':').collect();
+    parts.len() == 6
+        && parts
+            .iter()
+            .all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()))
+}
@@ -0,0 +1,262 @@
+use crate::bridges::{BridgeSettings, Transport};
+use crate::config;
+use anyhow::{Context, Result};
+use base64::Engine;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::os::unix::fs::PermissionsExt;
+use std::time::Duration;
+
+const CHECK_TIMEOUT: u64 = 5; // seconds
+const PASSWORD_FILE: &str = "proxy-password";
+
+// Destination used to test that an HTTP proxy allows CONNECT when there is
+// no bridge to test with. Asking for a Tor host would tell the proxy
+// operator what comes next.
+const CONNECT_CHECK_TARGET: &str = "example.com:443";
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyKind {
+    #[default]
+    Http,
+    Socks4,
+    Socks5,
+}
+
+/// Upstream proxy Tor has to go through to reach the network
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ProxySettings {
+    pub enabled: bool,
+    pub kind: ProxyKind,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    /// Kept out of config.json and never sent to the frontend, see
+    /// `load_password`
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    /// What `host` resolved to when the settings were saved. Pinned so
+    /// nothing has to resolve it later, in the clear or not at all.
+    pub address: Option<SocketAddr>,
+}
+
+impl ProxySettings {
+    pub fn validate(&self) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if self.host.trim().is_empty() || self.port == 0 {
+            return Err(anyhow::anyhow!("Proxy host and port are required"));
+        }
+
+        // Everything ends up in torrc, one option per line
+        let unsafe_in_torrc = |f: &String| f.chars().any(|c| c.is_whitespace() || c.is_control());
+        if [Some(&self.host), self.username.as_ref()]
+            .into_iter()
+            .flatten()
+            .any(unsafe_in_torrc)
+        {
+            return Err(anyhow::anyhow!(
+                "Proxy host and username must not contain spaces"
+            ));
+        }
+        if self.password.as_ref().is_some_and(unsafe_in_torrc) {
+            return Err(anyhow::anyhow!(
+                "Proxy password must not contain spaces or line breaks"
+            ));
+        }
+
+        match (self.kind, self.credentials()) {
+            (ProxyKind::Socks4, Some(_)) => Err(anyhow::anyhow!(
+                "SOCKS4 proxies don't support authentication"
+            )),
+            (ProxyKind::Http, Some((user, _))) if user.contains(':') => {
+                Err(anyhow::anyhow!("HTTP proxy username must not contain ':'"))
+            }
+            (ProxyKind::Socks5, Some((user, pass))) if user.len() > 255 || pass.len() > 255 => Err(
+                anyhow::anyhow!("SOCKS5 credentials are limited to 255 characters"),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    fn credentials(&self) -> Option<(&str, &str)> {
+        match (self.username.as_deref(), self.password.as_deref()) {
+            (Some(u), p) if !u.is_empty() => Some((u, p.unwrap_or(""))),
+            _ => None,
+        }
+    }
+
+    /// Resolves the proxy to the single address used in torrc and the
+    /// firewall and pins it
+    pub fn resolve(&mut self) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .context("Failed to resolve proxy host")?
+            .find(|a| a.is_ipv4())
+            .ok_or_else(|| anyhow::anyhow!("Proxy host has no IPv4 address"))?;
+        self.address = Some(addr);
+        Ok(())
+    }
+
+    pub fn endpoint(&self) -> Result<SocketAddr> {
+        self.address
+            .ok_or_else(|| anyhow::anyhow!("Proxy address unknown, save the proxy settings again"))
+    }
+
+    /// Lines for the Aegis torrc drop-in
+    pub fn torrc_lines(&self) -> Vec<String> {
+        if !self.enabled {
+            return Vec::new();
+        }
+
+        // With the killswitch up, the Tor user can't resolve names itself
+        let addr = match self.address {
+            Some(a) => a.to_string(),
+            None => format!("{}:{}", self.host, self.port),
+        };
+
+        let mut lines = Vec::new();
+        match self.kind {
+            ProxyKind::Http => {
+                lines.push(format!("HTTPSProxy {}", addr));
+                if let Some((user, pass)) = self.credentials() {
+                    lines.push(format!("HTTPSProxyAuthenticator {}:{}", user, pass));
+                }
+            }
+            ProxyKind::Socks4 => lines.push(format!("Socks4Proxy {}", addr)),
+            ProxyKind::Socks5 => {
+                lines.push(format!("Socks5Proxy {}", addr));
+                if let Some((user, pass)) = self.credentials() {
+                    lines.push(format!("Socks5ProxyUsername {}", user));
+                    lines.push(format!("Socks5ProxyPassword {}", pass));
+                }
+            }
+        }
+        lines
+    }
+
+    /// Connects to the proxy and performs its handshake, so a dead proxy
+    /// is caught before the killswitch cuts everything else off
+    pub fn check_connectivity(&self, bridges: &BridgeSettings) -> Result<()> {
+        let addr = self.endpoint()?;
+        let timeout = Duration::from_secs(CHECK_TIMEOUT);
+        let mut stream = TcpStream::connect_timeout(&addr, timeout)
+            .with_context(|| format!("Proxy {} is unreachable", addr))?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        match self.kind {
+            ProxyKind::Http => self.check_http(&mut stream, &check_target(bridges)),
+            ProxyKind::Socks5 => self.check_socks5(&mut stream),
+            // SOCKS4 has no handshake short of a CONNECT; reachability will do
+            ProxyKind::Socks4 => Ok(()),
+        }
+    }
+
+    fn check_http(&self, stream: &mut TcpStream, target: &str) -> Result<()> {
+        let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+        if let Some((user, pass)) = self.credentials() {
+            let token =
+                base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, pass));
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+
+        let mut buf = [0u8; 512];
+        let n = stream.read(&mut buf)?;
+        let status_line = String::from_utf8_lossy(&buf[..n])
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        match status_line.split_whitespace().nth(1) {
+            Some("200") => Ok(()),
+            Some("407") => Err(anyhow::anyhow!("Proxy rejected the credentials (407)")),
+            _ => Err(anyhow::anyhow!(
+                "Proxy refused CONNECT: {}",
+                status_line.trim()
+            )),
+        }
+    }
+
+    fn check_socks5(&self, stream: &mut TcpStream) -> Result<()> {
+        // Greeting: offer "no auth" and, with credentials, "username/password"
+        let greeting: &[u8] = if self.credentials().is_some() {
+            &[5, 2, 0, 2]
+        } else {
+            &[5, 1, 0]
+        };
+        stream.write_all(greeting)?;
+
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply)?;
+        if reply[0] != 5 {
+            return Err(anyhow::anyhow!("Not a SOCKS5 proxy"));
+        }
+
+        match (reply[1], self.credentials()) {
+            (0, _) => Ok(()),
+            (2, Some((user, pass))) => {
+                // RFC 1929 username/password sub-negotiation
+                let mut auth = vec![1, user.len() as u8];
+                auth.extend_from_slice(user.as_bytes());
+                auth.push(pass.len() as u8);
+                auth.extend_from_slice(pass.as_bytes());
+                stream.write_all(&auth)?;
+
+                stream.read_exact(&mut reply)?;
+                if reply[1] == 0 {
+                    Ok(())
+                } else {
+                    Err(anyhow::anyhow!("Proxy rejected the credentials"))
+                }
+            }
+            _ => Err(anyhow::anyhow!(
+                "Proxy requires an authentication method we didn't offer"
+            )),
+        }
+    }
+}
+
+/// A bridge Tor is going to connect to anyway, if there is one with a
+/// real address (snowflake and meek use placeholders)
+fn check_target(bridges: &BridgeSettings) -> String {
+    if !bridges.enabled {
+        return CONNECT_CHECK_TARGET.to_string();
+    }
+    bridges
+        .resolve()
+        .unwrap_or_default()
+        .into_iter()
+        .find(|b| matches!(b.transport, None | Some(Transport::Obfs4)))
+        .map(|b| b.address)
+        .unwrap_or_else(|| CONNECT_CHECK_TARGET.to_string())
+}
+
+/// Reads the proxy password from its own file, readable by root only
+pub fn load_password() -> Option<String> {
+    fs::read_to_string(config::data_path(PASSWORD_FILE)).ok()
+}
+
+pub fn store_password(password: Option<&str>) -> Result<()> {
+    let path = config::ensure_data_path(PASSWORD_FILE)?;
+    match password {
+        Some(p) if !p.is_empty() => {
+            // Restrict the file before the password goes in
+            fs::write(&path, "")?;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+            fs::write(&path, p).context("Failed to save proxy password")
+        }
+        _ if path.exists() => fs::remove_file(&path).context("Failed to remove proxy password"),
+        _ => Ok(()),
+    }
+}
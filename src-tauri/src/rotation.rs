@@ -0,0 +1,264 @@
+use crate::anonsurf::{self, AnonsurfState};
+use crate::config::ConfigState;
+use crate::torctl::TorControl;
+use anyhow::Result;
+use reqwest::blocking::Client;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+
+// Tor ignores NEWNYM requests sent less than 10 seconds apart
+const NEWNYM_MIN_INTERVAL: u64 = 10; // seconds
+const EVENT_POLL: u64 = 1; // seconds
+const RECONNECT_DELAY: u64 = 5; // seconds
+// Time given to Tor to build a fresh circuit before checking the exit IP
+const CIRCUIT_SETTLE: u64 = 5; // seconds
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RotationSettings {
+    pub enabled: bool,
+    /// Rotate every N minutes (0 disables the timer)
+    pub interval_minutes: u32,
+    /// Rotate after N new streams (0 disables the counter)
+    pub after_streams: u32,
+}
+
+impl Default for RotationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_minutes: 10,
+            after_streams: 0,
+        }
+    }
+}
+
+impl RotationSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.enabled && self.interval_minutes == 0 && self.after_streams == 0 {
+            return Err(anyhow::anyhow!(
+                "Set an interval or a stream count to enable automatic rotation"
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct IdentityRotated {
+    pub reason: String,
+    pub exit_ip: Option<String>,
+    pub at: u64, // Unix timestamp
+}
+
+/// Sends NEWNYM on a schedule while the shield is active
+pub fn start_identity_rotation(app: AppHandle) {
+    thread::spawn(move || loop {
+        if let Err(e) = run_scheduler(&app) {
+            println!("Identity rotation paused: {}", e);
+        }
+        thread::sleep(Duration::from_secs(RECONNECT_DELAY));
+    });
+}
+
+/// Runs until the control connection drops
+fn run_scheduler(app: &AppHandle) -> Result<()> {
+    // Wait for the shield before opening a control connection
+    if !is_enabled(app) {
+        return Ok(());
+    }
+
+    let mut ctl = TorControl::connect()?;
+    ctl.set_events(&["STREAM", "NOTICE"])?;
+
+    let mut last_rotation = Instant::now();
+    let mut not_before = last_rotation + Duration::from_secs(NEWNYM_MIN_INTERVAL);
+    let mut new_streams = 0u32;
+
+    loop {
+        if !is_enabled(app) {
+            return Ok(());
+        }
+
+        if let Some(event) = ctl.next_event(Duration::from_secs(EVENT_POLL))? {
+            // "STREAM <ID> NEW <CircuitID> <Target> ..."
+            if event.split_whitespace().nth(2) == Some("NEW") && event.starts_with("STREAM ") {
+                new_streams += 1;
+            }
+        }
+
+        if Instant::now() < not_before {
+            continue;
+        }
+
+        let settings = app.state::<ConfigState>().config.lock().unwrap().rotation.clone();
+        let elapsed = last_rotation.elapsed().as_secs();
+
+        let reason = if settings.interval_minutes > 0
+            && elapsed >= u64::from(settings.interval_minutes) * 60
+        {
+            format!("{} minute interval", settings.interval_minutes)
+        } else if settings.after_streams > 0 && new_streams >= settings.after_streams {
+            format!("{} new streams", new_streams)
+        } else {
+            continue;
+        };
+
+        match request_newnym(&mut ctl)? {
+            Some(delay) => {
+                // Tor deferred the request; try again once the limit expires
+                println!("NEWNYM rate limited by Tor, retrying in {}s", delay);
+                not_before = Instant::now() + Duration::from_secs(delay);
+            }
+            None => {
+                last_rotation = Instant::now();
+                not_before = last_rotation + Duration::from_secs(NEWNYM_MIN_INTERVAL);
+                new_streams = 0;
+                let _ = app.emit("app_log", format!("Identity rotated ({})", reason));
+
+                thread::sleep(Duration::from_secs(CIRCUIT_SETTLE));
+                let echo_url = app.state::<ConfigState>().config.lock().unwrap().echo_url.clone();
+                let rotated = identity_rotated(reason, &echo_url, anonsurf::TOR_SOCKS_PROXY);
+                let _ = app.emit("identity_rotated", rotated);
+            }
+        }
+    }
+}
+
+fn is_enabled(app: &AppHandle) -> bool {
+    *app.state::<AnonsurfState>().is_active.lock().unwrap()
+        && app.state::<ConfigState>().config.lock().unwrap().rotation.enabled
+}
+
+/// Sends NEWNYM and watches the NOTICE log for Tor's rate-limit warning.
+/// Returns the delay in seconds if Tor postponed the request.
+fn request_newnym(ctl: &mut TorControl) -> Result<Option<u64>> {
+    ctl.signal("NEWNYM")?;
+
+    // Tor answers 250 OK either way; the limit only shows up as
+    // "Rate limiting NEWNYM request: delaying by N second(s)"
+    let deadline = Instant::now() + Duration::from_secs(EVENT_POLL);
+    while Instant::now() < deadline {
+        let event = match ctl.next_event(Duration::from_millis(200))? {
+            Some(e) => e,
+            None => continue,
+        };
+
+        if let Some(rest) = event.split("Rate limiting NEWNYM request: delaying by ").nth(1) {
+            let delay = rest
+                .split_whitespace()
+                .next()
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(NEWNYM_MIN_INTERVAL);
+            return Ok(Some(delay));
+        }
+    }
+
+    Ok(None)
+}
+
+fn identity_rotated(reason: String, echo_url: &str, socks: &str) -> IdentityRotated {
+    IdentityRotated {
+        reason,
+        exit_ip: fetch_exit_ip(echo_url, Some(socks)).ok(),
+        at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    }
+}
+
+/// Asks the configured echo service for our apparent IP, through `socks`
+/// (normally Tor's SocksPort). Without one the request goes out directly.
+pub fn fetch_exit_ip(echo_url: &str, socks: Option<&str>) -> Result<String> {
+    let mut builder = Client::builder().timeout(Duration::from_secs(15));
+    if let Some(socks) = socks {
+        builder = builder.proxy(reqwest::Proxy::all(socks)?);
+    }
+    let client = builder.build()?;
+    let ip = client.get(echo_url).send()?.error_for_status()?.text()?;
+    Ok(ip.trim().to_string())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::torctl::tests::{expect_command, fake_control_port};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Never resolved: the SOCKS stand-in takes the name as it is
+    pub(crate) const ECHO_URL: &str = "http://echo.invalid/";
+
+    /// Local stand-in for Tor's SocksPort with the echo service behind it,
+    /// answering one request with `ip`. Returns the proxy URL.
+    pub(crate) fn echo_stand_in(ip: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = format!("socks5h://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            // Greeting: version, method count, methods; pick "no auth"
+            let mut greeting = [0u8; 2];
+            stream.read_exact(&mut greeting).unwrap();
+            let mut methods = vec![0u8; greeting[1] as usize];
+            stream.read_exact(&mut methods).unwrap();
+            stream.write_all(&[5, 0]).unwrap();
+
+            // CONNECT by name (socks5h): 5 1 0 3 <len> <name> <port>
+            let mut head = [0u8; 5];
+            stream.read_exact(&mut head).unwrap();
+            assert_eq!(head[3], 3, "name resolved locally instead of by Tor");
+            let mut rest = vec![0u8; head[4] as usize + 2];
+            stream.read_exact(&mut rest).unwrap();
+            stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}\n",
+                ip.len() + 1,
+                ip
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        proxy
+    }
+
+    #[test]
+    fn newnym_rate_limit_returns_the_delay() {
+        let addr = fake_control_port(|reader, stream| {
+            expect_command(reader, "SIGNAL NEWNYM");
+            stream
+                .write_all(
+                    b"250 OK\r\n650 NOTICE Rate limiting NEWNYM request: delaying by 7 second(s)\r\n",
+                )
+                .unwrap();
+        });
+        let mut ctl = TorControl::connect_to(&addr).unwrap();
+
+        assert_eq!(request_newnym(&mut ctl).unwrap(), Some(7));
+    }
+
+    #[test]
+    fn newnym_without_rate_limit() {
+        let addr = fake_control_port(|reader, stream| {
+            expect_command(reader, "SIGNAL NEWNYM");
+            stream.write_all(b"250 OK\r\n").unwrap();
+        });
+        let mut ctl = TorControl::connect_to(&addr).unwrap();
+
+        assert_eq!(request_newnym(&mut ctl).unwrap(), None);
+    }
+
+    #[test]
+    fn rotation_event_carries_the_new_exit_ip() {
+        let socks = echo_stand_in("203.0.113.7");
+        let event = identity_rotated("10 minute interval".to_string(), ECHO_URL, &socks);
+
+        assert_eq!(event.reason, "10 minute interval");
+        assert_eq!(event.exit_ip.as_deref(), Some("203.0.113.7"));
+        assert!(event.at > 0);
+    }
+}
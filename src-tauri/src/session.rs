@@ -0,0 +1,111 @@
+use crate::anonsurf::Anonsurf;
+use crate::bootguard;
+use crate::config;
+use crate::dns::DnsBackup;
+use crate::iptables::{FirewallSnapshot, IptablesManager};
+use anyhow::{Context, Result};
+use std::fs;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SESSION_FILE: &str = "session.json";
+
+/// Persisted record of an active shield, written before the killswitch
+/// goes up and removed once it is torn down.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct ShieldSession {
+    pub started_at: u64, // Unix timestamp
+    pub pid: u32,
+    pub tor_uid: String,
+    pub trans_port: String,
+    pub dns_port: String,
+    #[serde(default)]
+    pub firewall_snapshot: Option<FirewallSnapshot>,
+    #[serde(default)]
+    pub tor_was_running: bool,
+    /// Resolver configuration to put back on teardown
+    #[serde(default)]
+    pub dns: Option<DnsBackup>,
+    /// NTP was on and got turned off for the shield
+    #[serde(default)]
+    pub ntp_disabled: bool,
+}
+
+impl ShieldSession {
+    pub fn new(tor_uid: &str, trans_port: &str, dns_port: &str) -> Self {
+        Self {
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            pid: std::process::id(),
+            tor_uid: tor_uid.to_string(),
+            trans_port: trans_port.to_string(),
+            dns_port: dns_port.to_string(),
+            firewall_snapshot: None,
+            tor_was_running: false,
+            dns: None,
+            ntp_disabled: false,
+        }
+    }
+
+    pub fn load() -> Option<Self> {
+        let content = fs::read_to_string(config::data_path(SESSION_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = config::ensure_data_path(SESSION_FILE)?;
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .context("Failed to write shield session record")
+    }
+
+    pub fn clear() -> Result<()> {
+        match fs::remove_file(config::data_path(SESSION_FILE)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).context("Failed to remove shield session record")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Shield state left behind by a previous Aegis process
+#[derive(Clone, serde::Serialize)]
+pub struct OrphanedSession {
+    pub session: Option<ShieldSession>,
+    pub firewall_active: bool,
+    pub tor_running: bool,
+    /// The killswitch was put up at boot, not by a previous session
+    pub from_boot: bool,
+}
+
+pub struct SessionState {
+    pub orphaned: Mutex<Option<OrphanedSession>>,
+}
+
+impl SessionState {
+    pub fn new(orphaned: Option<OrphanedSession>) -> Self {
+        Self {
+            orphaned: Mutex::new(orphaned),
+        }
+    }
+}
+
+/// Inspects the session record and the live ruleset on startup.
+/// Returns `None` when the system is cleanly unprotected.
+pub fn detect_orphaned() -> Option<OrphanedSession> {
+    let session = ShieldSession::load();
+    let firewall_active = IptablesManager::is_killswitch_installed();
+
+    if session.is_none() && !firewall_active {
+        return None;
+    }
+
+    Some(OrphanedSession {
+        from_boot: session.is_none() && firewall_active && bootguard::is_installed(),
+        session,
+        firewall_active,
+        tor_running: Anonsurf::is_tor_running(),
+    })
+}
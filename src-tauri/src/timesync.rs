@@ -0,0 +1,422 @@
+use crate::anonsurf::{self, AnonsurfState};
+use crate::config::{AegisConfig, ConfigState};
+use crate::torctl::{self, TorControl};
+use anyhow::{Context, Result};
+use reqwest::blocking::Client;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+
+const EVENT_POLL: u64 = 1; // seconds
+const RECONNECT_DELAY: u64 = 5; // seconds
+const CONSENSUS_CHECK: u64 = 60; // seconds
+const REPORT_COOLDOWN: u64 = 300; // seconds
+const FETCH_TIMEOUT: u64 = 15; // seconds
+
+// Tor keeps using a consensus for a day after it expires, so an old one
+// alone doesn't mean the clock is ahead
+const CONSENSUS_GRACE: i64 = 24 * 3600; // seconds
+
+// Date headers have a one second resolution, and the round trip through
+// Tor adds its own error on top
+const MIN_CORRECTION: i64 = 5; // seconds
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TimeSyncSettings {
+    /// Set the clock from the Date headers of HTTPS servers, fetched
+    /// through Tor, when Tor reports a skew
+    pub sync_over_tor: bool,
+    /// Turn off NTP while the shield is active. It would leak in the
+    /// clear or be blocked and leave the clock drifting.
+    pub disable_ntp: bool,
+    /// A majority of these has to answer for the clock to be set
+    pub sources: Vec<String>,
+}
+
+impl Default for TimeSyncSettings {
+    fn default() -> Self {
+        Self {
+            sync_over_tor: false,
+            disable_ntp: true,
+            sources: vec![
+                "https://www.torproject.org".to_string(),
+                "https://www.eff.org".to_string(),
+                "https://www.wikipedia.org".to_string(),
+            ],
+        }
+    }
+}
+
+impl TimeSyncSettings {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(source) = self.sources.iter().find(|s| !s.starts_with("https://")) {
+            return Err(anyhow::anyhow!("Time sources must use HTTPS: {}", source));
+        }
+        if self.sync_over_tor && self.sources.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Add at least one time source to sync over Tor"
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct ClockSkew {
+    /// Seconds our clock is ahead, negative if it is behind
+    pub skew: i64,
+    /// The skew is only a lower bound (from consensus times)
+    pub at_least: bool,
+    pub source: String,
+}
+
+/// Watches Tor for signs of a wrong clock while the shield is active,
+/// emitting `clock_skew` and optionally correcting it
+pub fn start_time_watch(app: AppHandle) {
+    thread::spawn(move || loop {
+        if let Err(e) = run_watch(&app) {
+            println!("Clock skew watch paused: {}", e);
+        }
+        thread::sleep(Duration::from_secs(RECONNECT_DELAY));
+    });
+}
+
+/// Runs until the control connection drops
+fn run_watch(app: &AppHandle) -> Result<()> {
+    if !is_active(app) {
+        return Ok(());
+    }
+
+    let mut ctl = TorControl::connect()?;
+    ctl.set_events(&["STATUS_GENERAL"])?;
+
+    let mut next_check = Instant::now();
+    let mut last_report: Option<Instant> = None;
+
+    loop {
+        if !is_active(app) {
+            return Ok(());
+        }
+
+        let mut skew = ctl
+            .next_event(Duration::from_secs(EVENT_POLL))?
+            .and_then(|event| parse_skew_event(&event));
+        if skew.is_none() && Instant::now() >= next_check {
+            next_check = Instant::now() + Duration::from_secs(CONSENSUS_CHECK);
+            skew = consensus_skew(&mut ctl);
+        }
+
+        let skew = match skew {
+            Some(s) => s,
+            None => continue,
+        };
+        if last_report.is_some_and(|t| t.elapsed().as_secs() < REPORT_COOLDOWN) {
+            continue;
+        }
+        last_report = Some(Instant::now());
+
+        let _ = app.emit("clock_skew", skew);
+
+        let config = app.state::<ConfigState>().config.lock().unwrap().clone();
+        if !config.time_sync.sync_over_tor {
+            continue;
+        }
+        let message = match sync(&config) {
+            Ok(message) => message,
+            Err(e) => format!("Failed to sync the clock over Tor: {}", e),
+        };
+        let _ = app.emit("app_log", format!("Time: {}", message));
+    }
+}
+
+/// Sets the clock from the median Date header of the configured sources,
+/// fetched through Tor's SocksPort
+pub fn sync(config: &AegisConfig) -> Result<String> {
+    let sources = &config.time_sync.sources;
+    let client = Client::builder()
+        .timeout(Duration::from_secs(FETCH_TIMEOUT))
+        .proxy(reqwest::Proxy::all(anonsurf::TOR_SOCKS_PROXY)?)
+        .build()?;
+    let mut skews: Vec<i64> = thread::scope(|s| {
+        let fetches: Vec<_> = sources
+            .iter()
+            .map(|url| s.spawn(|| fetch_skew(&client, url)))
+            .collect();
+        fetches
+            .into_iter()
+            .filter_map(|f| f.join().ok()?.ok())
+            .collect()
+    });
+
+    // One server can't move the clock on its own
+    if skews.len() * 2 <= sources.len() {
+        return Err(anyhow::anyhow!(
+            "Only {} of {} time sources answered",
+            skews.len(),
+            sources.len()
+        ));
+    }
+    skews.sort_unstable();
+    let skew = skews[skews.len() / 2];
+
+    if skew.abs() < MIN_CORRECTION {
+        return Ok(format!("Clock is correct ({})", describe(skew)));
+    }
+    set_clock(now() - skew)?;
+    Ok(format!("Clock was {}, corrected", describe(skew)))
+}
+
+/// Turns NTP off if it is on. Returns whether it was, so teardown can
+/// turn it back on.
+pub fn disable_ntp() -> Result<bool> {
+    let output = Command::new("timedatectl")
+        .args(["show", "--property=NTP", "--value"])
+        .output()
+        .context("Failed to run timedatectl")?;
+    if String::from_utf8_lossy(&output.stdout).trim() != "yes" {
+        return Ok(false);
+    }
+    set_ntp(false)?;
+    Ok(true)
+}
+
+pub fn set_ntp(enabled: bool) -> Result<()> {
+    let status = Command::new("timedatectl")
+        .args(["set-ntp", if enabled { "true" } else { "false" }])
+        .status()
+        .context("Failed to run timedatectl")?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("timedatectl set-ntp failed"));
+    }
+    Ok(())
+}
+
+fn is_active(app: &AppHandle) -> bool {
+    *app.state::<AnonsurfState>().is_active.lock().unwrap()
+}
+
+/// "STATUS_GENERAL WARN CLOCK_SKEW SKEW=-7200 SOURCE=OR:1.2.3.4:9001", or
+/// MIN_SKEW instead of SKEW when Tor only knows a lower bound
+fn parse_skew_event(event: &str) -> Option<ClockSkew> {
+    let args = torctl::split_args(event);
+    if args.first().map(String::as_str) != Some("STATUS_GENERAL")
+        || args.get(2).map(String::as_str) != Some("CLOCK_SKEW")
+    {
+        return None;
+    }
+
+    let keywords = torctl::keyword_args(event);
+    let (skew, at_least) = match (keywords.get("SKEW"), keywords.get("MIN_SKEW")) {
+        (Some(s), _) => (s.parse().ok()?, false),
+        (None, Some(s)) => (s.parse().ok()?, true),
+        (None, None) => return None,
+    };
+    Some(ClockSkew {
+        skew,
+        at_least,
+        source: keywords
+            .get("SOURCE")
+            .cloned()
+            .unwrap_or_else(|| "unknown".to_string()),
+    })
+}
+
+/// A consensus from the future, or one Tor should have given up on,
+/// means the clock is off by at least the difference
+fn consensus_skew(ctl: &mut TorControl) -> Option<ClockSkew> {
+    let mut time = |key: &str| parse_tor_time(&ctl.getinfo(key).ok()?);
+    let valid_after = time("consensus/valid-after")?;
+    let valid_until = time("consensus/valid-until")?;
+
+    let now = now();
+    let skew = if now < valid_after {
+        now - valid_after
+    } else if now > valid_until + CONSENSUS_GRACE {
+        now - valid_until
+    } else {
+        return None;
+    };
+    Some(ClockSkew {
+        skew,
+        at_least: true,
+        source: "CONSENSUS".to_string(),
+    })
+}
+
+/// How far our clock is ahead of the server's, going by its Date header
+fn fetch_skew(client: &Client, url: &str) -> Result<i64> {
+    let sent = now();
+    let response = client.head(url).send()?;
+    let date = response
+        .headers()
+        .get(reqwest::header::DATE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_http_date)
+        .ok_or_else(|| anyhow::anyhow!("{} sent no usable Date header", url))?;
+    // The server stamped the response somewhere during the round trip
+    Ok((sent + now()) / 2 - date)
+}
+
+fn set_clock(unix: i64) -> Result<()> {
+    let status = Command::new("date")
+        .args(["-u", "-s", &format!("@{}", unix)])
+        .status()
+        .context("Failed to run date")?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("Failed to set the clock"));
+    }
+    Ok(())
+}
+
+fn describe(skew: i64) -> String {
+    let direction = if skew < 0 { "behind" } else { "ahead" };
+    let secs = skew.unsigned_abs();
+    if secs >= 3600 {
+        format!("{}h {}m {}", secs / 3600, secs % 3600 / 60, direction)
+    } else if secs >= 60 {
+        format!("{}m {}s {}", secs / 60, secs % 60, direction)
+    } else {
+        format!("{}s {}", secs, direction)
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// "2024-05-01 12:00:00" (UTC) as used in GETINFO replies
+fn parse_tor_time(value: &str) -> Option<i64> {
+    let (date, time) = value.trim_matches('"').split_once(' ')?;
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    match date.as_slice() {
+        [year, month, day] => unix_time(*year, *month, *day, time),
+        _ => None,
+    }
+}
+
+/// "Sun, 06 Nov 1994 08:49:37 GMT", the only format servers may send
+fn parse_http_date(value: &str) -> Option<i64> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    match fields.as_slice() {
+        [_, day, month, year, time, "GMT"] => {
+            let month = MONTHS.iter().position(|m| m == month)? as i64 + 1;
+            unix_time(year.parse().ok()?, month, day.parse().ok()?, time)
+        }
+        _ => None,
+    }
+}
+
+/// Seconds since the epoch for a UTC date and "HH:MM:SS"
+fn unix_time(year: i64, month: i64, day: i64, time: &str) -> Option<i64> {
+    let hms: Vec<i64> = time
+        .split(':')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    let [hour, minute, second] = hms.as_slice() else {
+        return None;
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days from civil, with March as the first month of the year
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skew_event_from_a_relay() {
+        let skew =
+            parse_skew_event("STATUS_GENERAL WARN CLOCK_SKEW SKEW=-7200 SOURCE=OR:1.2.3.4:9001")
+                .unwrap();
+        assert_eq!(skew.skew, -7200);
+        assert!(!skew.at_least);
+        assert_eq!(skew.source, "OR:1.2.3.4:9001");
+    }
+
+    #[test]
+    fn min_skew_event_is_a_lower_bound() {
+        let skew =
+            parse_skew_event("STATUS_GENERAL WARN CLOCK_SKEW MIN_SKEW=3600 SOURCE=CONSENSUS")
+                .unwrap();
+        assert_eq!(skew.skew, 3600);
+        assert!(skew.at_least);
+        assert_eq!(skew.source, "CONSENSUS");
+    }
+
+    #[test]
+    fn other_status_events_are_not_skew() {
+        assert!(parse_skew_event("STATUS_GENERAL WARN DANGEROUS_VERSION CURRENT=0.4.8").is_none());
+        assert!(parse_skew_event("STATUS_CLIENT NOTICE CLOCK_SKEW SKEW=10").is_none());
+        assert!(
+            parse_skew_event("STATUS_GENERAL WARN CLOCK_SKEW SOURCE=DIRSERV:1.2.3.4:80").is_none()
+        );
+    }
+
+    #[test]
+    fn tor_time_with_and_without_quotes() {
+        assert_eq!(parse_tor_time("2024-05-01 12:00:00"), Some(1714564800));
+        assert_eq!(parse_tor_time("\"2024-05-01 12:00:00\""), Some(1714564800));
+        assert_eq!(parse_tor_time("2024-05-01T12:00:00"), None);
+        assert_eq!(parse_tor_time("2024-05 12:00:00"), None);
+    }
+
+    #[test]
+    fn http_date() {
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(784111777)
+        );
+        // Obsolete formats and other zones aren't accepted
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 CET"), None);
+        assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+    }
+
+    #[test]
+    fn unix_time_in_january_and_february() {
+        assert_eq!(unix_time(1970, 1, 1, "00:00:00"), Some(0));
+        assert_eq!(unix_time(2024, 1, 1, "00:00:00"), Some(1704067200));
+        assert_eq!(unix_time(2023, 12, 31, "23:59:59"), Some(1704067199));
+        assert_eq!(unix_time(1969, 12, 31, "23:59:59"), Some(-1));
+    }
+
+    #[test]
+    fn unix_time_across_leap_days() {
+        assert_eq!(unix_time(2024, 2, 29, "00:00:00"), Some(1709164800));
+        // Divisible by 400, so 2000 is a leap year
+        assert_eq!(unix_time(2000, 2, 29, "12:00:00"), Some(951825600));
+        // 2100 isn't: March follows February 28th
+        assert_eq!(unix_time(2100, 2, 28, "00:00:00"), Some(4107456000));
+        assert_eq!(unix_time(2100, 3, 1, "00:00:00"), Some(4107542400));
+    }
+
+    #[test]
+    fn unix_time_rejects_bad_fields() {
+        assert_eq!(unix_time(2024, 13, 1, "00:00:00"), None);
+        assert_eq!(unix_time(2024, 1, 0, "00:00:00"), None);
+        assert_eq!(unix_time(2024, 1, 1, "00:00"), None);
+    }
+}
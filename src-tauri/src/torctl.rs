@@ -0,0 +1,294 @@
+use anyhow::{Context, Result};
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+pub const CONTROL_ADDR: &str = "127.0.0.1:9051";
+const IO_TIMEOUT: u64 = 5; // seconds
+
+/// Minimal client for Tor's control protocol (control-spec.txt)
+pub struct TorControl {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    // Asynchronous "650" events received while waiting for a reply
+    events: VecDeque<String>,
+    // Start of a line cut off by a read timeout, completed by the next read
+    partial: Vec<u8>,
+}
+
+impl TorControl {
+    /// Connects to the default Control Port and authenticates
+    pub fn connect() -> Result<Self> {
+        Self::connect_to(CONTROL_ADDR)
+    }
+
+    pub fn connect_to(addr: &str) -> Result<Self> {
+        let stream = TcpStream::connect(addr).context(
+            "Could not connect to Tor Control Port. Is Tor running and ControlPort 9051 enabled?",
+        )?;
+        stream.set_read_timeout(Some(Duration::from_secs(IO_TIMEOUT)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(IO_TIMEOUT)))?;
+
+        let mut ctl = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            events: VecDeque::new(),
+            partial: Vec::new(),
+        };
+
+        // We configure CookieAuthentication 0, so an empty password is enough
+        ctl.command("AUTHENTICATE \"\"")
+            .context("Tor Control Port rejected authentication")?;
+        Ok(ctl)
+    }
+
+    /// Sends a command and returns the reply lines (without status codes)
+    pub fn command(&mut self, cmd: &str) -> Result<Vec<String>> {
+        self.writer.write_all(format!("{}\r\n", cmd).as_bytes())?;
+        self.read_reply()
+    }
+
+    /// Reads a single reply, following "250-" continuations and "250+" data blocks
+    fn read_reply(&mut self) -> Result<Vec<String>> {
+        let mut lines = Vec::new();
+        let mut error = None;
+
+        loop {
+            let line = self.read_line()?;
+            let (code, sep, body) = match (line.get(..3), line.get(3..4), line.get(4..)) {
+                (Some(code), Some(sep), Some(body)) => (code, sep, body),
+                _ => return Err(anyhow::anyhow!("Malformed control reply: {}", line)),
+            };
+
+            if code == "650" {
+                let event = self.read_event_body(sep, body)?;
+                self.events.push_back(event);
+                continue;
+            }
+            // A failed reply is still read to its end, so none of it is
+            // mistaken for the reply to the next command
+            if !code.starts_with('2') && error.is_none() {
+                error = Some(line.clone());
+            }
+
+            match sep {
+                "-" => lines.push(body.to_string()),
+                "+" => {
+                    // Data block terminated by a single "."
+                    let mut block = body.to_string();
+                    loop {
+                        let data = self.read_line()?;
+                        if data == "." {
+                            break;
+                        }
+                        block.push('\n');
+                        block.push_str(data.strip_prefix('.').unwrap_or(&data));
+                    }
+                    lines.push(block);
+                }
+                _ => {
+                    lines.push(body.to_string());
+                    break;
+                }
+            }
+        }
+
+        match error {
+            Some(line) => Err(anyhow::anyhow!("Tor returned {}", line)),
+            None => Ok(lines),
+        }
+    }
+
+    /// Collects the remaining lines of an asynchronous event
+    fn read_event_body(&mut self, sep: &str, body: &str) -> Result<String> {
+        let mut event = body.to_string();
+        let mut sep = sep.to_string();
+
+        while sep != " " {
+            let line = self.read_line()?;
+            if sep == "+" {
+                // Data block terminated by a single "."
+                if line == "." {
+                    sep = "-".to_string();
+                } else {
+                    event.push('\n');
+                    event.push_str(&line);
+                }
+                continue;
+            }
+            match (line.get(3..4), line.get(4..)) {
+                (Some(next), Some(body)) => {
+                    event.push('\n');
+                    event.push_str(body);
+                    sep = next.to_string();
+                }
+                _ => return Err(anyhow::anyhow!("Malformed control event: {}", line)),
+            }
+        }
+
+        Ok(event)
+    }
+
+    /// Subscribes this connection to asynchronous events (e.g. "STREAM", "NOTICE")
+    pub fn set_events(&mut self, events: &[&str]) -> Result<()> {
+        self.command(&format!("SETEVENTS {}", events.join(" ")))?;
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for the next event, returning its body
+    /// without the "650 " prefix
+    pub fn next_event(&mut self, timeout: Duration) -> Result<Option<String>> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(Some(event));
+        }
+
+        self.writer.set_read_timeout(Some(timeout))?;
+        let line = self.read_line();
+        self.writer
+            .set_read_timeout(Some(Duration::from_secs(IO_TIMEOUT)))?;
+
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                let timed_out = e.downcast_ref::<std::io::Error>().is_some_and(|io| {
+                    matches!(io.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+                });
+                return if timed_out { Ok(None) } else { Err(e) };
+            }
+        };
+
+        match line.get(..4) {
+            Some(prefix) if prefix.starts_with("650") => {
+                let event = self.read_event_body(&prefix[3..], &line[4..])?;
+                Ok(Some(event))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected control reply: {}", line)),
+        }
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        // On a timeout, whatever was read so far stays in `partial`
+        self.reader.read_until(b'\n', &mut self.partial)?;
+        if !self.partial.ends_with(b"\n") {
+            return Err(anyhow::anyhow!("Tor closed the control connection"));
+        }
+        let line = std::mem::take(&mut self.partial);
+        Ok(String::from_utf8_lossy(&line)
+            .trim_end_matches(['\r', '\n'])
+            .to_string())
+    }
+
+    /// Returns the value of a single GETINFO key
+    pub fn getinfo(&mut self, key: &str) -> Result<String> {
+        let prefix = format!("{}=", key);
+        self.command(&format!("GETINFO {}", key))?
+            .into_iter()
+            .find_map(|l| l.strip_prefix(&prefix).map(|v| v.trim_start().to_string()))
+            .ok_or_else(|| anyhow::anyhow!("GETINFO {} returned no value", key))
+    }
+
+    pub fn signal(&mut self, signal: &str) -> Result<()> {
+        self.command(&format!("SIGNAL {}", signal))?;
+        Ok(())
+    }
+}
+
+/// Splits a reply line into arguments, keeping quoted values together
+pub fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for c in line.chars() {
+        match c {
+            _ if escaped => {
+                current.push(c);
+                escaped = false;
+            }
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ' ' if !quoted => {
+                if !current.is_empty() {
+                    args.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        args.push(current);
+    }
+
+    args
+}
+
+/// Collects the KEY=VALUE arguments of a reply line (quotes removed)
+pub fn keyword_args(line: &str) -> HashMap<String, String> {
+    split_args(line)
+        .into_iter()
+        .filter_map(|arg| {
+            let (k, v) = arg.split_once('=')?;
+            Some((k.to_string(), v.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serves one control connection on a local port: accepts the
+    /// AUTHENTICATE handshake, then hands the socket to `script`
+    pub(crate) fn fake_control_port<F>(script: F) -> String
+    where
+        F: FnOnce(&mut BufReader<TcpStream>, &mut TcpStream) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            expect_command(&mut reader, "AUTHENTICATE");
+            stream.write_all(b"250 OK\r\n").unwrap();
+            script(&mut reader, &mut stream);
+            // Hold the connection open until the client is done
+            let _ = reader.read_to_end(&mut Vec::new());
+        });
+        addr
+    }
+
+    pub(crate) fn expect_command(reader: &mut BufReader<TcpStream>, prefix: &str) {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(
+            line.starts_with(prefix),
+            "expected {}, got {}",
+            prefix,
+            line
+        );
+    }
+
+    #[test]
+    fn event_split_by_a_timeout_is_not_lost() {
+        let addr = fake_control_port(|_, stream| {
+            stream.write_all(b"650 NOTICE Rate limiting ").unwrap();
+            thread::sleep(Duration::from_millis(300));
+            stream.write_all(b"NEWNYM request\r\n").unwrap();
+        });
+        let mut ctl = TorControl::connect_to(&addr).unwrap();
+
+        assert_eq!(ctl.next_event(Duration::from_millis(100)).unwrap(), None);
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(
+            ctl.next_event(Duration::from_millis(100))
+                .unwrap()
+                .as_deref(),
+            Some("NOTICE Rate limiting NEWNYM request")
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use crate::anonsurf::TOR_UID;
+use crate::config::AegisConfig;
+use anyhow::{Context, Result};
+use std::fs;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::process::Command;
+
+const TORRC_PATH: &str = "/etc/tor/torrc";
+const DROPIN_PATH: &str = "/etc/tor/aegis.torrc";
+const INCLUDE_LINE: &str = "%include /etc/tor/aegis.torrc";
+
+// Markers of the block older Aegis versions appended to torrc directly
+const LEGACY_BEGIN: &str = "# --- Added by Aegis Privacy Shield ---";
+const LEGACY_END: &str = "# -----------------------------------";
+
+/// Renders the Aegis-managed Tor configuration
+pub fn render(trans_port: &str, dns_port: &str, config: &AegisConfig) -> Result<String> {
+    // The password may come from a file nobody validated; a line break in
+    // it would add torrc options of its own
+    config.proxy.validate()?;
+
+    let mut lines = vec![
+        "# Managed by Aegis Privacy Shield. Changes are overwritten.".to_string(),
+        "VirtualAddrNetworkIPv4 10.192.0.0/10".to_string(),
+        "AutomapHostsOnResolve 1".to_string(),
+        format!(
+            "TransPort {} IsolateClientAddr IsolateClientProtocol IsolateDestAddr IsolateDestPort",
+            trans_port
+        ),
+        format!("DNSPort {}", dns_port),
+        "ControlPort 9051".to_string(),
+        "CookieAuthentication 0".to_string(),
+    ];
+    lines.extend(config.node_policy.torrc_lines());
+    lines.extend(config.bridges.torrc_lines());
+    lines.extend(config.proxy.torrc_lines());
+    lines.extend(config.isolation.torrc_lines());
+    lines.extend(crate::netns::torrc_lines());
+
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Writes the drop-in and makes torrc include it.
+/// Returns true if anything on disk changed.
+pub fn install(content: &str) -> Result<bool> {
+    let torrc = fs::read_to_string(TORRC_PATH).context("Failed to read torrc")?;
+    let mut changed = false;
+
+    let mut new_torrc = strip_legacy_block(&torrc);
+    if !new_torrc.lines().any(|l| l.trim() == INCLUDE_LINE) {
+        if !new_torrc.is_empty() && !new_torrc.ends_with('\n') {
+            new_torrc.push('\n');
+        }
+        new_torrc.push_str(INCLUDE_LINE);
+        new_torrc.push('\n');
+    }
+
+    if new_torrc != torrc {
+        fs::write(TORRC_PATH, &new_torrc).context("Failed to update torrc")?;
+        changed = true;
+    }
+
+    secure_dropin()?;
+    if fs::read_to_string(DROPIN_PATH).ok().as_deref() != Some(content) {
+        fs::write(DROPIN_PATH, content).context("Failed to write Aegis torrc drop-in")?;
+        changed = true;
+    }
+
+    Ok(changed)
+}
+
+/// Creates the drop-in, or tightens an existing one, before anything is
+/// written to it: it can hold proxy credentials. Tor rereads it as its own
+/// user on reload, hence root:debian-tor 0640.
+fn secure_dropin() -> Result<()> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o640)
+        .open(DROPIN_PATH)
+        .context("Failed to create Aegis torrc drop-in")?;
+    file.set_permissions(fs::Permissions::from_mode(0o640))
+        .context("Failed to restrict Aegis torrc drop-in")?;
+
+    let status = Command::new("chown")
+        .arg(format!("root:{}", TOR_UID))
+        .arg(DROPIN_PATH)
+        .status()
+        .context("Failed to run chown")?;
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to hand the Aegis torrc drop-in to the {} group",
+            TOR_UID
+        ));
+    }
+    Ok(())
+}
+
+/// Removes the include line and the drop-in, leaving torrc as we found it
+pub fn revert() -> Result<()> {
+    let torrc = fs::read_to_string(TORRC_PATH).context("Failed to read torrc")?;
+    let cleaned: String = strip_legacy_block(&torrc)
+        .lines()
+        .filter(|l| l.trim() != INCLUDE_LINE)
+        .map(|l| format!("{}\n", l))
+        .collect();
+
+    if cleaned != torrc {
+        fs::write(TORRC_PATH, cleaned).context("Failed to update torrc")?;
+    }
+
+    match fs::remove_file(DROPIN_PATH) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).context("Failed to remove Aegis torrc drop-in")
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn is_installed() -> bool {
+    fs::read_to_string(TORRC_PATH)
+        .map(|c| c.lines().any(|l| l.trim() == INCLUDE_LINE))
+        .unwrap_or(false)
+}
+
+fn strip_legacy_block(torrc: &str) -> String {
+    let mut out = String::new();
+    let mut in_block = false;
+
+    for line in torrc.lines() {
+        match line.trim() {
+            LEGACY_BEGIN => in_block = true,
+            LEGACY_END if in_block => in_block = false,
+            _ if !in_block => {
+                out.push_str(line);
+                out.push('\n');
+            }
+            _ => {}
+        }
+    }
+
+    out
+}
@@ -0,0 +1,201 @@
+use crate::anonsurf::TOR_UID;
+use anyhow::Result;
+use std::fs;
+
+const PASSWD_FILE: &str = "/etc/passwd";
+const GROUP_FILE: &str = "/etc/group";
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OwnerKind {
+    #[default]
+    User,
+    Group,
+}
+
+/// A local user or group, by name
+#[derive(Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Owner {
+    pub kind: OwnerKind,
+    pub name: String,
+}
+
+/// Which local accounts are routed through Tor
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct UserRules {
+    /// Reach the network directly, e.g. a backup service account
+    pub exempt: Vec<Owner>,
+    /// Always go through Tor, even to allowlisted destinations
+    pub forced: Vec<Owner>,
+    /// Only the forced accounts are shielded; everyone else is exempt
+    pub only_forced: bool,
+}
+
+/// `-m owner` arguments for the firewall, resolved from the account names
+#[derive(Clone, Default)]
+pub struct OwnerMatches {
+    pub exempt: Vec<String>,
+    pub forced: Vec<String>,
+    pub only_forced: bool,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct Account {
+    pub kind: OwnerKind,
+    pub name: String,
+    pub id: u32,
+}
+
+impl Owner {
+    fn matcher(&self) -> Result<String> {
+        let (file, option) = match self.kind {
+            OwnerKind::User => (PASSWD_FILE, "--uid-owner"),
+            OwnerKind::Group => (GROUP_FILE, "--gid-owner"),
+        };
+        let id = lookup(file, &self.name).ok_or_else(|| {
+            anyhow::anyhow!("Unknown local {} \"{}\"", self.kind_name(), self.name)
+        })?;
+        match self.kind {
+            OwnerKind::User => Ok(format!("-m owner {} {}", option, id)),
+            // Without --suppl-groups only the primary group matches, and
+            // members through a supplementary group would slip past
+            OwnerKind::Group => Ok(format!("-m owner {} {} --suppl-groups", option, id)),
+        }
+    }
+
+    /// Whether Tor's own traffic would match: rerouting or exempting it
+    /// breaks the shield
+    fn includes_tor(&self) -> bool {
+        match self.kind {
+            OwnerKind::User => self.name == TOR_UID,
+            OwnerKind::Group => {
+                let tor_gid = passwd_field(TOR_UID, 3).and_then(|gid| gid.parse().ok());
+                let primary = tor_gid.is_some() && lookup(GROUP_FILE, &self.name) == tor_gid;
+                primary || group_members(&self.name).iter().any(|m| m == TOR_UID)
+            }
+        }
+    }
+
+    fn kind_name(&self) -> &'static str {
+        match self.kind {
+            OwnerKind::User => "user",
+            OwnerKind::Group => "group",
+        }
+    }
+}
+
+impl UserRules {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(owner) = self.exempt.iter().find(|o| self.forced.contains(o)) {
+            return Err(anyhow::anyhow!(
+                "{} can't be both exempt and forced through Tor",
+                owner.name
+            ));
+        }
+        if let Some(owner) = self
+            .exempt
+            .iter()
+            .chain(&self.forced)
+            .find(|o| o.includes_tor())
+        {
+            return Err(anyhow::anyhow!(
+                "{} includes Tor's own user ({}), which can't be exempt or forced",
+                owner.name,
+                TOR_UID
+            ));
+        }
+        if self.only_forced && self.forced.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Add at least one forced user or group, or nobody would be shielded"
+            ));
+        }
+        self.resolve().map(|_| ())
+    }
+
+    pub fn resolve(&self) -> Result<OwnerMatches> {
+        Ok(OwnerMatches {
+            exempt: self
+                .exempt
+                .iter()
+                .map(Owner::matcher)
+                .collect::<Result<_>>()?,
+            forced: self
+                .forced
+                .iter()
+                .map(Owner::matcher)
+                .collect::<Result<_>>()?,
+            only_forced: self.only_forced,
+        })
+    }
+}
+
+/// Users and groups from /etc/passwd and /etc/group
+pub fn list_accounts() -> Vec<Account> {
+    let mut accounts = Vec::new();
+    for (file, kind) in [
+        (PASSWD_FILE, OwnerKind::User),
+        (GROUP_FILE, OwnerKind::Group),
+    ] {
+        accounts.extend(
+            entries(file)
+                .into_iter()
+                .map(|(name, id)| Account { kind, name, id }),
+        );
+    }
+    accounts
+}
+
+pub fn uid_of(user: &str) -> Option<u32> {
+    lookup(PASSWD_FILE, user)
+}
+
+/// A field of the user's /etc/passwd entry, counting from 0
+fn passwd_field(user: &str, index: usize) -> Option<String> {
+    fs::read_to_string(PASSWD_FILE)
+        .ok()?
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.first() == Some(&user))?
+        .get(index)
+        .map(|f| f.to_string())
+}
+
+/// Supplementary members of a group ("name:x:gid:user1,user2")
+fn group_members(group: &str) -> Vec<String> {
+    fs::read_to_string(GROUP_FILE)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.first() == Some(&group))
+        .and_then(|fields| fields.get(3).map(|m| m.to_string()))
+        .map(|m| {
+            m.split(',')
+                .filter(|u| !u.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn lookup(file: &str, name: &str) -> Option<u32> {
+    entries(file)
+        .into_iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, id)| id)
+}
+
+/// "name:x:id:..." entries of a passwd-style file
+fn entries(file: &str) -> Vec<(String, u32)> {
+    fs::read_to_string(file)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((name.to_string(), id))
+        })
+        .collect()
+}
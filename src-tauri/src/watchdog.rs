@@ -0,0 +1,179 @@
+use crate::anonsurf::{Anonsurf, AnonsurfState};
+use crate::bridges;
+use crate::config::ConfigState;
+use crate::torctl::{self, TorControl};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+
+const CHECK_INTERVAL: u64 = 10; // seconds
+const MIN_BACKOFF: u64 = 5; // seconds
+const MAX_BACKOFF: u64 = 300; // seconds
+
+// Bootstrapping over bridges or a proxy can take minutes, so a missing
+// circuit only counts as degraded once bootstrap progress stops moving
+const BOOTSTRAP_STALL: u64 = 120; // seconds
+
+#[derive(Clone, Default, serde::Serialize)]
+pub struct ShieldHealth {
+    pub tor_process: bool,
+    pub control_port: bool,
+    pub circuit_established: bool,
+    pub degraded: bool,
+    pub restarts: u32,
+}
+
+pub struct WatchdogState {
+    pub health: Mutex<ShieldHealth>,
+}
+
+impl WatchdogState {
+    pub fn new() -> Self {
+        Self {
+            health: Mutex::new(ShieldHealth::default()),
+        }
+    }
+}
+
+/// Monitors Tor while the shield is active and restarts it with backoff.
+/// The firewall is never touched here: the killswitch stays in place
+/// while Tor is down, so a crash means "no traffic", not "clear traffic".
+pub fn start_tor_watchdog(app: AppHandle) {
+    thread::spawn(move || {
+        let mut backoff = MIN_BACKOFF;
+        let mut next_restart = Instant::now();
+        let mut progress = 0u32;
+        let mut progress_at = Instant::now();
+
+        loop {
+            thread::sleep(Duration::from_secs(CHECK_INTERVAL));
+
+            let active = *app.state::<AnonsurfState>().is_active.lock().unwrap();
+            let watchdog = app.state::<WatchdogState>();
+
+            if !active {
+                *watchdog.health.lock().unwrap() = ShieldHealth::default();
+                backoff = MIN_BACKOFF;
+                progress = 0;
+                progress_at = Instant::now();
+                continue;
+            }
+
+            let (tor_process, control_port, circuit_established, bootstrap) = probe_tor();
+            if circuit_established || bootstrap > progress {
+                progress = bootstrap;
+                progress_at = Instant::now();
+            }
+
+            let problem = if !tor_process {
+                Some("Tor process is not running".to_string())
+            } else if !control_port {
+                Some("Tor Control Port is not responding".to_string())
+            } else if !circuit_established && progress_at.elapsed().as_secs() >= BOOTSTRAP_STALL {
+                Some(circuit_problem(&app))
+            } else {
+                None
+            };
+
+            let mut health = watchdog.health.lock().unwrap();
+            health.tor_process = tor_process;
+            health.control_port = control_port;
+            health.circuit_established = circuit_established;
+
+            match problem {
+                None => {
+                    if health.degraded {
+                        health.degraded = false;
+                        let _ = app.emit("shield_recovered", "Tor connectivity restored");
+                        let _ = app.emit("app_log", "Watchdog: Tor recovered, shield fully protected.");
+                    }
+                    backoff = MIN_BACKOFF;
+                }
+                Some(reason) => {
+                    if !health.degraded {
+                        health.degraded = true;
+                        let _ = app.emit("shield_degraded", &reason);
+                        let _ = app.emit(
+                            "app_log",
+                            format!("Watchdog: {}. Killswitch remains active.", reason),
+                        );
+                    }
+
+                    if Instant::now() >= next_restart {
+                        drop(health);
+
+                        // A start or stop in progress owns Tor, look again
+                        // on the next round
+                        let state = app.state::<AnonsurfState>();
+                        let _transition = match state.transition.try_lock() {
+                            Ok(guard) => guard,
+                            Err(_) => continue,
+                        };
+                        if !*state.is_active.lock().unwrap() {
+                            continue;
+                        }
+                        watchdog.health.lock().unwrap().restarts += 1;
+
+                        match Anonsurf::start_tor_service(&app) {
+                            Ok(_) => Anonsurf::restore_onions(&app),
+                            Err(e) => {
+                                let _ =
+                                    app.emit("app_log", format!("Watchdog: restart failed: {}", e));
+                            }
+                        }
+                        progress = 0;
+                        progress_at = Instant::now();
+                        next_restart = Instant::now() + Duration::from_secs(backoff);
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Explains a failed bootstrap, with transport-specific hints when bridges are used
+fn circuit_problem(app: &AppHandle) -> String {
+    let settings = app
+        .state::<ConfigState>()
+        .config
+        .lock()
+        .unwrap()
+        .bridges
+        .clone();
+
+    match bridges::bootstrap_status(&settings) {
+        Ok(status) if status.warning.is_some() => format!(
+            "Tor is stuck bootstrapping at {}% ({}). {}",
+            status.progress,
+            status.warning.unwrap_or_default(),
+            status.hint.unwrap_or_default()
+        ),
+        _ => "Tor has no established circuits".to_string(),
+    }
+}
+
+/// Returns (process running, control port alive, circuit established,
+/// bootstrap progress in percent)
+fn probe_tor() -> (bool, bool, bool, u32) {
+    let tor_process = Anonsurf::is_tor_running();
+
+    let mut ctl = match TorControl::connect() {
+        Ok(ctl) => ctl,
+        Err(_) => return (tor_process, false, false, 0),
+    };
+
+    let circuit_established = ctl
+        .getinfo("status/circuit-established")
+        .map(|v| v == "1")
+        .unwrap_or(false);
+    // "NOTICE BOOTSTRAP PROGRESS=75 TAG=enough_dirinfo SUMMARY=..."
+    let bootstrap = ctl
+        .getinfo("status/bootstrap-phase")
+        .ok()
+        .and_then(|phase| torctl::keyword_args(&phase).get("PROGRESS")?.parse().ok())
+        .unwrap_or(0);
+
+    (tor_process, true, circuit_established, bootstrap)
+}
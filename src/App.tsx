@@ -5,22 +5,67 @@ import { StatusCard } from "./components/StatusCard";
 import { ControlPanel } from "./components/ControlPanel";
 import { TrafficGraph } from "./components/TrafficGraph";
 import { StealthPanel } from "./components/StealthPanel";
+import { RotationPanel } from "./components/RotationPanel";
+import { BridgesPanel } from "./components/BridgesPanel";
+import { AllowlistPanel } from "./components/AllowlistPanel";
+import { AppsPanel } from "./components/AppsPanel";
+import { UsersPanel } from "./components/UsersPanel";
+import { IsolationPanel } from "./components/IsolationPanel";
+import { NamespacePanel } from "./components/NamespacePanel";
+import { PortalPanel } from "./components/PortalPanel";
+import { ShieldModePanel } from "./components/ShieldModePanel";
+import { OnionPanel } from "./components/OnionPanel";
+import { LeakTestPanel } from "./components/LeakTestPanel";
+import { AppFirewallPanel } from "./components/AppFirewallPanel";
+import { ProfilesPanel } from "./components/ProfilesPanel";
+import { BootPanel } from "./components/BootPanel";
+import { TimePanel } from "./components/TimePanel";
 import { ActivityLog } from "./components/ActivityLog";
 import { DonationModal } from "./components/DonationModal";
 import { Shield, ChevronUp, ChevronDown, Heart } from "lucide-react";
 import "./index.css";
 
+interface OrphanedSession {
+  session: { started_at: number } | null;
+  firewall_active: boolean;
+  tor_running: boolean;
+  from_boot: boolean;
+}
+
+interface TeardownReport {
+  undone: string[];
+  failed: string[];
+  killswitch_active: boolean;
+}
+
+interface IdentityRotated {
+  reason: string;
+  exit_ip: string | null;
+  at: number;
+}
+
+interface BlockedConnection {
+  app: string;
+  pid: number | null;
+  destination: string;
+  port: number | null;
+  protocol: string;
+  count: number;
+}
+
 interface SystemIdentity {
   mac: string;
   hostname: string;
+  resolver: string;
 }
 
 function App() {
   const [isActive, setIsActive] = useState(false);
+  const [degraded, setDegraded] = useState(false);
   const [ip, setIp] = useState<string | undefined>(undefined);
   const [country, setCountry] = useState<string | undefined>(undefined);
   const [ipv6, setIpv6] = useState<string | undefined>(undefined);
-  const [identity, setIdentity] = useState<SystemIdentity>({ mac: "Loading...", hostname: "Loading..." });
+  const [identity, setIdentity] = useState<SystemIdentity>({ mac: "Loading...", hostname: "Loading...", resolver: "Loading..." });
 
   const [loading, setLoading] = useState(false);
   const [dataLoading, setDataLoading] = useState(false);
@@ -43,12 +88,49 @@ function App() {
       addLog(`[SECURITY ALERT] ${event.payload}`);
     });
 
-    checkStatus();
+    const unlistenDegraded = listen<string>('shield_degraded', (event) => {
+      setDegraded(true);
+      addLog(`[CRITICAL] Shield degraded: ${event.payload}`);
+    });
+
+    const unlistenRecovered = listen<string>('shield_recovered', (event) => {
+      setDegraded(false);
+      addLog(`[OK] ${event.payload}`);
+    });
+
+    const unlistenRotated = listen<IdentityRotated>('identity_rotated', (event) => {
+      addLog(`[OK] New identity (${event.payload.reason}) - exit IP ${event.payload.exit_ip ?? "unknown"}`);
+      if (event.payload.exit_ip) setIp(event.payload.exit_ip);
+    });
+
+    const unlistenBlocked = listen<BlockedConnection>('blocked_connection', (event) => {
+      const b = event.payload;
+      const target = b.port ? `${b.destination}:${b.port}` : b.destination;
+      addLog(`[BLOCKED] ${b.app}${b.pid ? ` (${b.pid})` : ""} -> ${target} ${b.protocol}${b.count > 1 ? ` x${b.count}` : ""}`);
+    });
+
+    const unlistenProfile = listen<{ profile: string | null }>('network_profile', (event) => {
+      addLog(`[OK] Network profile applied: ${event.payload.profile ?? "unknown network"}`);
+      checkStatus();
+    });
+
+    const unlistenSkew = listen<{ skew: number; at_least: boolean; source: string }>('clock_skew', (event) => {
+      const s = event.payload;
+      addLog(`[WARN] Clock ${s.skew < 0 ? "behind" : "ahead"} by ${s.at_least ? "at least " : ""}${Math.abs(s.skew)}s (${s.source})`);
+    });
+
+    checkOrphanedSession().then(checkStatus);
     fetchIpData();
 
     return () => {
       unlisten.then(f => f());
       unlistenAlert.then(f => f());
+      unlistenDegraded.then(f => f());
+      unlistenRecovered.then(f => f());
+      unlistenRotated.then(f => f());
+      unlistenBlocked.then(f => f());
+      unlistenProfile.then(f => f());
+      unlistenSkew.then(f => f());
     };
   }, []);
 
@@ -69,6 +151,37 @@ function App() {
     }
   }
 
+  async function checkOrphanedSession() {
+    try {
+      const orphan = await invoke<OrphanedSession | null>("get_orphaned_session");
+      if (!orphan) return;
+
+      if (orphan.from_boot) {
+        addLog("[WARN] Boot killswitch is active, only Tor can reach the network");
+      } else {
+        addLog(`[WARN] Previous shield session found (firewall: ${orphan.firewall_active ? "active" : "missing"}, Tor: ${orphan.tor_running ? "running" : "stopped"})`);
+      }
+      const resume = window.confirm(
+        (orphan.from_boot
+          ? "The boot killswitch is blocking all traffic except Tor.\n\n"
+          : "Aegis was not shut down cleanly and the shield may still be active.\n\n") +
+        "OK: resume the shield\nCancel: tear it down and restore normal networking"
+      );
+      if (resume) {
+        addLog(`[OK] ${await invoke<string>("resume_session")}`);
+      } else {
+        logTeardown(await invoke<TeardownReport>("teardown_session"));
+      }
+    } catch (e) {
+      addLog(`[ERR] Session recovery: ${e}`);
+    }
+  }
+
+  function logTeardown(report: TeardownReport) {
+    report.undone.forEach(step => addLog(`[OK] Undone: ${step}`));
+    report.failed.forEach(step => addLog(`[ERR] Teardown: ${step}`));
+  }
+
   async function fetchIpData() {
     setDataLoading(true);
 
@@ -121,8 +234,9 @@ function App() {
     try {
       if (isActive) {
         addLog("Stopping Services...");
-        await invoke("stop_anonsurf");
+        logTeardown(await invoke<TeardownReport>("stop_anonsurf"));
         setIsActive(false);
+        setDegraded(false);
         addLog("Shield Deactivated");
       } else {
         addLog("Initializing Shield...");
@@ -195,11 +309,13 @@ function App() {
         <div className="grid grid-cols-1 gap-6">
           <StatusCard
             active={isActive}
+            degraded={degraded}
             ip={ip}
             country={country}
             ipv6={ipv6}
             mac={identity.mac}
             hostname={identity.hostname}
+            resolver={identity.resolver}
             loading={dataLoading}
           />
 
@@ -214,6 +330,21 @@ function App() {
         {/* Info & Metrics */}
         <div className="grid grid-cols-1 gap-6">
           <TrafficGraph />
+          <ShieldModePanel isActive={isActive} onLog={addLog} />
+          <PortalPanel isActive={isActive} onLog={addLog} />
+          <LeakTestPanel isActive={isActive} onLog={addLog} />
+          <RotationPanel onLog={addLog} />
+          <BridgesPanel onLog={addLog} />
+          <AllowlistPanel onLog={addLog} />
+          <AppsPanel onLog={addLog} />
+          <AppFirewallPanel onLog={addLog} />
+          <UsersPanel onLog={addLog} />
+          <IsolationPanel onLog={addLog} />
+          <NamespacePanel onLog={addLog} />
+          <OnionPanel onLog={addLog} />
+          <ProfilesPanel onLog={addLog} />
+          <BootPanel onLog={addLog} />
+          <TimePanel isActive={isActive} onLog={addLog} />
           <StealthPanel onLog={addLog} />
         </div>
 
@@ -0,0 +1,166 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Network, Trash2 } from "lucide-react";
+
+type Protocol = "any" | "tcp" | "udp";
+
+interface AllowRule {
+    cidr: string;
+    protocol: Protocol;
+    ports: string;
+    description: string;
+}
+
+interface Allowlist {
+    rules: AllowRule[];
+}
+
+interface AllowlistPanelProps {
+    onLog: (msg: string) => void;
+}
+
+const emptyRule: AllowRule = { cidr: "", protocol: "any", ports: "", description: "" };
+
+export function AllowlistPanel({ onLog }: AllowlistPanelProps) {
+    const [rules, setRules] = useState<AllowRule[]>([]);
+    const [draft, setDraft] = useState<AllowRule>(emptyRule);
+    const [preview, setPreview] = useState<string[] | null>(null);
+
+    useEffect(() => {
+        invoke<Allowlist>("get_allowlist")
+            .then(a => setRules(a.rules))
+            .catch(e => onLog(`[ERR] Allowlist: ${e}`));
+    }, []);
+
+    const save = async (next: AllowRule[]): Promise<boolean> => {
+        const allowlist: Allowlist = { rules: next };
+        try {
+            const warnings = await invoke<string[]>("check_allowlist", { allowlist });
+            if (warnings.length > 0 && !window.confirm(`${warnings.join("\n")}\n\nAllowlist these ranges anyway?`)) {
+                return false;
+            }
+            const msg = await invoke<string>("set_allowlist", { allowlist, acceptPublic: warnings.length > 0 });
+            setRules(next);
+            setPreview(null);
+            onLog(`[OK] ${msg}`);
+            return true;
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+            return false;
+        }
+    };
+
+    const addPreset = async () => {
+        const preset = await invoke<AllowRule[]>("get_allowlist_preset");
+        save([...rules, ...preset.filter(p => !rules.some(r => r.cidr === p.cidr))]);
+    };
+
+    const togglePreview = async () => {
+        if (preview) {
+            setPreview(null);
+            return;
+        }
+        try {
+            setPreview(await invoke<string[]>("preview_ruleset"));
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center justify-between mb-4">
+                <div className="flex items-center space-x-3">
+                    <div className="p-2 bg-emerald-500/10 rounded-lg">
+                        <Network className="w-5 h-5 text-emerald-400" />
+                    </div>
+                    <div>
+                        <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">Direct Access Allowlist</h3>
+                        <p className="text-xs text-slate-500">Destinations reached without Tor (printers, NAS, SSH)</p>
+                    </div>
+                </div>
+                <div className="flex gap-2">
+                    <button
+                        onClick={addPreset}
+                        className="px-3 py-2 bg-slate-700 hover:bg-slate-600 text-slate-200 text-xs font-medium rounded-lg transition-colors"
+                    >
+                        + Local networks
+                    </button>
+                    <button
+                        onClick={togglePreview}
+                        className="px-3 py-2 bg-slate-700 hover:bg-slate-600 text-slate-200 text-xs font-medium rounded-lg transition-colors"
+                    >
+                        {preview ? "Hide rules" : "Preview rules"}
+                    </button>
+                </div>
+            </div>
+
+            <ul className="space-y-1 mb-3 text-xs">
+                {rules.length === 0 && <li className="text-slate-500">Everything goes through Tor</li>}
+                {rules.map((rule, i) => (
+                    <li key={i} className="flex items-center justify-between bg-slate-900/50 rounded-lg px-3 py-2">
+                        <span className="font-mono text-slate-200">
+                            {rule.cidr} {rule.protocol !== "any" && rule.protocol}{rule.ports && `:${rule.ports}`}
+                        </span>
+                        <span className="flex-1 ml-3 text-slate-500 truncate">{rule.description}</span>
+                        <button
+                            onClick={() => save(rules.filter((_, j) => j !== i))}
+                            className="text-slate-500 hover:text-red-400"
+                        >
+                            <Trash2 className="w-4 h-4" />
+                        </button>
+                    </li>
+                ))}
+            </ul>
+
+            <form
+                onSubmit={(e) => {
+                    e.preventDefault();
+                    if (!draft.cidr.trim()) return;
+                    save([...rules, draft]).then(ok => ok && setDraft(emptyRule));
+                }}
+                className="flex gap-2 items-center"
+            >
+                <input
+                    placeholder="192.168.1.0/24"
+                    value={draft.cidr}
+                    onChange={e => setDraft({ ...draft, cidr: e.target.value })}
+                    className="w-36 bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-emerald-500"
+                />
+                <select
+                    value={draft.protocol}
+                    onChange={e => setDraft({ ...draft, protocol: e.target.value as Protocol })}
+                    className="bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-emerald-500"
+                >
+                    <option value="any">any</option>
+                    <option value="tcp">tcp</option>
+                    <option value="udp">udp</option>
+                </select>
+                <input
+                    placeholder="ports"
+                    value={draft.ports}
+                    onChange={e => setDraft({ ...draft, ports: e.target.value })}
+                    className="w-24 bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-emerald-500"
+                />
+                <input
+                    placeholder="Description"
+                    value={draft.description}
+                    onChange={e => setDraft({ ...draft, description: e.target.value })}
+                    className="flex-1 bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-emerald-500"
+                />
+                <button
+                    type="submit"
+                    className="px-4 py-2 bg-slate-700 hover:bg-emerald-600 text-white text-sm font-medium rounded-lg transition-colors"
+                >
+                    Add
+                </button>
+            </form>
+
+            {preview && (
+                <pre className="mt-4 max-h-64 overflow-auto bg-slate-900/70 rounded-lg p-3 text-[11px] leading-relaxed text-slate-300 font-mono">
+                    {preview.join("\n")}
+                </pre>
+            )}
+        </div>
+    );
+}
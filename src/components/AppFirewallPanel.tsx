@@ -0,0 +1,177 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { ShieldQuestion, Trash2 } from "lucide-react";
+import { clsx } from 'clsx';
+
+type AppAction = "direct" | "tor" | "deny";
+
+interface FirewallPrompt {
+    id: number;
+    exe: string;
+    pid: number;
+    destination: string;
+    port: number;
+    protocol: string;
+    changed: boolean;
+    killswitch: boolean;
+}
+
+interface AppFirewallRule {
+    exe: string;
+    sha256: string;
+    action: AppAction;
+}
+
+interface AppFirewallPanelProps {
+    onLog: (msg: string) => void;
+}
+
+const actionLabel: Record<AppAction, string> = {
+    direct: "Direct",
+    tor: "Via Tor",
+    deny: "Deny",
+};
+
+export function AppFirewallPanel({ onLog }: AppFirewallPanelProps) {
+    const [enabled, setEnabled] = useState(false);
+    const [rules, setRules] = useState<AppFirewallRule[]>([]);
+    const [prompts, setPrompts] = useState<FirewallPrompt[]>([]);
+
+    const refreshRules = () => invoke<AppFirewallRule[]>("list_firewall_rules").then(setRules).catch(() => {});
+
+    useEffect(() => {
+        invoke<{ enabled: boolean }>("get_app_firewall_settings")
+            .then(s => setEnabled(s.enabled))
+            .catch(e => onLog(`[ERR] Application firewall: ${e}`));
+        invoke<FirewallPrompt[]>("get_firewall_prompts").then(setPrompts).catch(() => {});
+        refreshRules();
+
+        const unlistenPrompt = listen<FirewallPrompt>('firewall_prompt', (event) => {
+            setPrompts(prev => [...prev, event.payload]);
+        });
+        const unlistenExpired = listen<number>('firewall_prompt_expired', (event) => {
+            setPrompts(prev => prev.filter(p => p.id !== event.payload));
+        });
+        return () => {
+            unlistenPrompt.then(f => f());
+            unlistenExpired.then(f => f());
+        };
+    }, []);
+
+    const toggle = async () => {
+        try {
+            onLog(`[OK] ${await invoke<string>("set_app_firewall_settings", { settings: { enabled: !enabled } })}`);
+            setEnabled(!enabled);
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    const answer = async (prompt: FirewallPrompt, action: AppAction) => {
+        setPrompts(prev => prev.filter(p => p.id !== prompt.id));
+        try {
+            await invoke<string>("answer_firewall_prompt", { id: prompt.id, action });
+            onLog(`[OK] ${prompt.exe}: ${actionLabel[action]}`);
+            refreshRules();
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    const remove = async (exe: string) => {
+        try {
+            onLog(`[OK] ${await invoke<string>("delete_firewall_rule", { exe })}`);
+            refreshRules();
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    const prompt = prompts[0];
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center justify-between mb-4">
+                <div className="flex items-center space-x-3">
+                    <div className="p-2 bg-rose-500/10 rounded-lg">
+                        <ShieldQuestion className="w-5 h-5 text-rose-400" />
+                    </div>
+                    <div>
+                        <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">Application Firewall</h3>
+                        <p className="text-xs text-slate-500">Ask before unknown programs connect</p>
+                    </div>
+                </div>
+                <button
+                    onClick={toggle}
+                    className={clsx(
+                        "px-4 py-2 text-sm font-medium rounded-lg transition-colors",
+                        enabled ? "bg-rose-600 hover:bg-rose-500 text-white" : "bg-slate-700 hover:bg-slate-600 text-slate-200"
+                    )}
+                >
+                    {enabled ? "On" : "Off"}
+                </button>
+            </div>
+
+            <ul className="space-y-1 text-xs">
+                {rules.length === 0 && <li className="text-slate-500">No decisions yet</li>}
+                {rules.map(r => (
+                    <li key={r.exe} className="flex items-center bg-slate-900/50 rounded-lg px-3 py-2 gap-3">
+                        <span className="flex-1 font-mono text-slate-200 truncate" title={r.sha256}>{r.exe}</span>
+                        <span className={clsx(
+                            r.action === "deny" ? "text-red-400" : r.action === "direct" ? "text-amber-400" : "text-emerald-400"
+                        )}>
+                            {actionLabel[r.action]}
+                        </span>
+                        <button onClick={() => remove(r.exe)} className="text-slate-500 hover:text-red-400">
+                            <Trash2 className="w-4 h-4" />
+                        </button>
+                    </li>
+                ))}
+            </ul>
+
+            {prompt && (
+                <div className="fixed inset-0 z-50 flex items-center justify-center bg-black/60 backdrop-blur-sm">
+                    <div className="glass-card w-full max-w-md p-6 border border-white/10">
+                        <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider mb-2">Outgoing Connection</h3>
+                        <p className="text-xs font-mono text-slate-200 break-all">{prompt.exe} ({prompt.pid})</p>
+                        <p className="text-xs text-slate-400 mt-1">
+                            wants to reach <span className="font-mono text-slate-200">{prompt.destination}:{prompt.port}</span> over {prompt.protocol}
+                        </p>
+                        {prompt.changed && (
+                            <p className="text-xs text-amber-400 mt-2">This program has changed since your last decision.</p>
+                        )}
+                        {prompt.killswitch && (
+                            <p className="text-xs text-slate-400 mt-2">
+                                Killswitch mode: "Via Tor" blocks the connection unless the program uses Tor's SocksPort.
+                            </p>
+                        )}
+                        {prompts.length > 1 && (
+                            <p className="text-[10px] text-slate-500 mt-2">{prompts.length - 1} more waiting</p>
+                        )}
+                        <div className="flex gap-2 mt-4">
+                            <button
+                                onClick={() => answer(prompt, "tor")}
+                                className="flex-1 px-4 py-2 bg-emerald-600 hover:bg-emerald-500 text-white text-sm font-medium rounded-lg transition-colors"
+                            >
+                                Via Tor
+                            </button>
+                            <button
+                                onClick={() => answer(prompt, "direct")}
+                                className="flex-1 px-4 py-2 bg-amber-600 hover:bg-amber-500 text-white text-sm font-medium rounded-lg transition-colors"
+                            >
+                                Direct
+                            </button>
+                            <button
+                                onClick={() => answer(prompt, "deny")}
+                                className="flex-1 px-4 py-2 bg-red-600 hover:bg-red-500 text-white text-sm font-medium rounded-lg transition-colors"
+                            >
+                                Deny
+                            </button>
+                        </div>
+                    </div>
+                </div>
+            )}
+        </div>
+    );
+}
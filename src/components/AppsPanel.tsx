@@ -0,0 +1,142 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { AppWindow, Trash2 } from "lucide-react";
+import { clsx } from 'clsx';
+
+type AppGroup = "bypass" | "forced";
+
+interface AppRules {
+    bypass: string[];
+    forced: string[];
+}
+
+interface AppProcess {
+    pid: number;
+    name: string;
+    group: AppGroup;
+}
+
+interface AppsPanelProps {
+    onLog: (msg: string) => void;
+}
+
+export function AppsPanel({ onLog }: AppsPanelProps) {
+    const [rules, setRules] = useState<AppRules>({ bypass: [], forced: [] });
+    const [running, setRunning] = useState<AppProcess[]>([]);
+    const [program, setProgram] = useState("");
+    const [group, setGroup] = useState<AppGroup>("bypass");
+
+    useEffect(() => {
+        invoke<AppRules>("get_app_rules")
+            .then(setRules)
+            .catch(e => onLog(`[ERR] App rules: ${e}`));
+
+        const refresh = () => invoke<AppProcess[]>("list_routed_apps").then(setRunning).catch(() => {});
+        refresh();
+        const interval = setInterval(refresh, 5000);
+        return () => clearInterval(interval);
+    }, []);
+
+    const save = async (next: AppRules) => {
+        try {
+            onLog(`[OK] ${await invoke<string>("set_app_rules", { rules: next })}`);
+            setRules(next);
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    const launch = async () => {
+        if (!program.trim()) return;
+        try {
+            onLog(`[OK] ${await invoke<string>("launch_bypassed", { app: program })}`);
+            setProgram("");
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    const addRule = () => {
+        const name = program.trim();
+        if (!name || rules[group].includes(name)) return;
+        save({ ...rules, [group]: [...rules[group], name] }).then(() => setProgram(""));
+    };
+
+    const removeRule = (g: AppGroup, name: string) =>
+        save({ ...rules, [g]: rules[g].filter(n => n !== name) });
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center space-x-3 mb-4">
+                <div className="p-2 bg-amber-500/10 rounded-lg">
+                    <AppWindow className="w-5 h-5 text-amber-400" />
+                </div>
+                <div>
+                    <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">Per-App Routing</h3>
+                    <p className="text-xs text-slate-500">Programs that bypass Tor, or are always forced through it</p>
+                </div>
+            </div>
+
+            <div className="grid grid-cols-2 gap-3 mb-3 text-xs">
+                {(["bypass", "forced"] as AppGroup[]).map(g => (
+                    <div key={g}>
+                        <p className="text-slate-400 mb-1">{g === "bypass" ? "Bypassed" : "Forced through Tor"}</p>
+                        <ul className="space-y-1">
+                            {rules[g].length === 0 && <li className="text-slate-600">None</li>}
+                            {rules[g].map(name => (
+                                <li key={name} className="flex items-center justify-between bg-slate-900/50 rounded-lg px-3 py-1.5">
+                                    <span className="font-mono text-slate-200">{name}</span>
+                                    <span className="flex-1 ml-2 text-slate-500">
+                                        {running.filter(p => p.group === g && p.name === name).length || ""}
+                                    </span>
+                                    <button onClick={() => removeRule(g, name)} className="text-slate-500 hover:text-red-400">
+                                        <Trash2 className="w-3.5 h-3.5" />
+                                    </button>
+                                </li>
+                            ))}
+                        </ul>
+                    </div>
+                ))}
+            </div>
+
+            <div className="flex gap-2 items-center">
+                <input
+                    placeholder="Program, e.g. steam"
+                    value={program}
+                    onChange={e => setProgram(e.target.value)}
+                    className="flex-1 bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-amber-500"
+                />
+                <select
+                    value={group}
+                    onChange={e => setGroup(e.target.value as AppGroup)}
+                    className="bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-amber-500"
+                >
+                    <option value="bypass">bypass</option>
+                    <option value="forced">forced</option>
+                </select>
+                <button
+                    onClick={addRule}
+                    className="px-4 py-2 bg-slate-700 hover:bg-slate-600 text-white text-sm font-medium rounded-lg transition-colors"
+                >
+                    Add
+                </button>
+                <button
+                    onClick={launch}
+                    disabled={group !== "bypass"}
+                    className={clsx(
+                        "px-4 py-2 text-sm font-medium rounded-lg transition-colors",
+                        group === "bypass" ? "bg-amber-600 hover:bg-amber-500 text-white" : "bg-slate-800 text-slate-500"
+                    )}
+                >
+                    Launch bypassed
+                </button>
+            </div>
+
+            {running.length > 0 && (
+                <p className="mt-3 text-xs text-slate-500">
+                    Running: {running.map(p => `${p.name} (${p.pid}, ${p.group})`).join(", ")}
+                </p>
+            )}
+        </div>
+    );
+}
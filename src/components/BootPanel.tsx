@@ -0,0 +1,72 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Power } from "lucide-react";
+import { clsx } from 'clsx';
+
+interface BootKillswitchStatus {
+    installed: boolean;
+    init: "systemd" | "openrc" | null;
+}
+
+interface BootPanelProps {
+    onLog: (msg: string) => void;
+}
+
+export function BootPanel({ onLog }: BootPanelProps) {
+    const [status, setStatus] = useState<BootKillswitchStatus | null>(null);
+    const [busy, setBusy] = useState(false);
+
+    const refresh = () => invoke<BootKillswitchStatus>("get_boot_killswitch")
+        .then(setStatus)
+        .catch(e => onLog(`[ERR] Boot killswitch: ${e}`));
+
+    useEffect(() => {
+        refresh();
+    }, []);
+
+    const toggle = async () => {
+        if (!status) return;
+        setBusy(true);
+        try {
+            const command = status.installed ? "uninstall_boot_killswitch" : "install_boot_killswitch";
+            onLog(`[OK] ${await invoke<string>(command)}`);
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+        await refresh();
+        setBusy(false);
+    };
+
+    const unsupported = status !== null && !status.installed && status.init === null;
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center justify-between">
+                <div className="flex items-center space-x-3">
+                    <div className="p-2 bg-red-500/10 rounded-lg">
+                        <Power className="w-5 h-5 text-red-400" />
+                    </div>
+                    <div>
+                        <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">Boot Killswitch</h3>
+                        <p className="text-xs text-slate-500">
+                            {unsupported
+                                ? "Needs systemd or OpenRC"
+                                : "Block everything but Tor from boot until the shield starts"}
+                        </p>
+                    </div>
+                </div>
+                <button
+                    onClick={toggle}
+                    disabled={busy || !status || unsupported}
+                    className={clsx(
+                        "px-4 py-2 text-sm font-medium rounded-lg transition-colors",
+                        status?.installed ? "bg-red-600 hover:bg-red-500 text-white" : "bg-slate-700 hover:bg-slate-600 text-slate-200",
+                        (busy || !status || unsupported) && "opacity-50 cursor-not-allowed"
+                    )}
+                >
+                    {status?.installed ? "Installed" : "Off"}
+                </button>
+            </div>
+        </div>
+    );
+}
@@ -0,0 +1,131 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Network } from "lucide-react";
+import { clsx } from 'clsx';
+
+type Transport = "obfs4" | "snowflake" | "meek-azure" | "webtunnel";
+
+interface BridgeSettings {
+    enabled: boolean;
+    builtin: Transport | null;
+    bridges: string[];
+}
+
+interface TransportStatus {
+    transport: Transport;
+    binary: string | null;
+    builtin_bridges: number;
+}
+
+interface BootstrapStatus {
+    progress: number;
+    summary: string;
+    warning: string | null;
+    hint: string | null;
+}
+
+interface BridgesPanelProps {
+    onLog: (msg: string) => void;
+}
+
+export function BridgesPanel({ onLog }: BridgesPanelProps) {
+    const [settings, setSettings] = useState<BridgeSettings | null>(null);
+    const [transports, setTransports] = useState<TransportStatus[]>([]);
+    const [lines, setLines] = useState("");
+    const [bootstrap, setBootstrap] = useState<BootstrapStatus | null>(null);
+
+    useEffect(() => {
+        invoke<BridgeSettings>("get_bridge_settings")
+            .then(s => {
+                setSettings(s);
+                setLines(s.bridges.join("\n"));
+            })
+            .catch(e => onLog(`[ERR] Bridge settings: ${e}`));
+        invoke<TransportStatus[]>("detect_transports").then(setTransports).catch(() => {});
+    }, []);
+
+    const save = async (next: BridgeSettings) => {
+        try {
+            onLog(`[OK] ${await invoke<string>("set_bridge_settings", { settings: next })}`);
+            setSettings(next);
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    const checkBootstrap = () => invoke<BootstrapStatus>("get_bootstrap_status")
+        .then(setBootstrap)
+        .catch(e => onLog(`[ERR] ${e}`));
+
+    if (!settings) return null;
+
+    const pasted = () => lines.split("\n").map(l => l.trim()).filter(Boolean);
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center justify-between mb-4">
+                <div className="flex items-center space-x-3">
+                    <div className="p-2 bg-emerald-500/10 rounded-lg">
+                        <Network className="w-5 h-5 text-emerald-400" />
+                    </div>
+                    <div>
+                        <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">Bridges</h3>
+                        <p className="text-xs text-slate-500">Reach Tor where it is blocked or watched</p>
+                    </div>
+                </div>
+                <button
+                    onClick={() => save({ ...settings, bridges: pasted(), enabled: !settings.enabled })}
+                    className={clsx(
+                        "px-4 py-2 text-sm font-medium rounded-lg transition-colors",
+                        settings.enabled ? "bg-emerald-600 hover:bg-emerald-500 text-white" : "bg-slate-700 hover:bg-slate-600 text-slate-200"
+                    )}
+                >
+                    {settings.enabled ? "On" : "Off"}
+                </button>
+            </div>
+
+            <div className="flex items-center gap-2 mb-3 text-xs text-slate-400">
+                <span>Built-in</span>
+                <select
+                    value={settings.builtin ?? ""}
+                    onChange={e => save({ ...settings, bridges: pasted(), builtin: (e.target.value || null) as Transport | null })}
+                    className="bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-1 text-slate-200"
+                >
+                    <option value="">None</option>
+                    {transports.filter(t => t.builtin_bridges > 0).map(t => (
+                        <option key={t.transport} value={t.transport} disabled={!t.binary}>
+                            {t.transport}{t.binary ? "" : " (not installed)"}
+                        </option>
+                    ))}
+                </select>
+            </div>
+
+            <textarea
+                placeholder="Paste bridge lines, one per line, e.g. obfs4 192.0.2.1:443 FINGERPRINT cert=... iat-mode=0"
+                value={lines}
+                onChange={e => setLines(e.target.value)}
+                rows={4}
+                className="w-full mb-2 bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-xs font-mono text-slate-200 focus:outline-none focus:border-emerald-500"
+            />
+            <div className="flex items-center gap-2">
+                <button
+                    onClick={() => save({ ...settings, bridges: pasted() })}
+                    className="px-3 py-2 bg-slate-700 hover:bg-slate-600 text-slate-200 text-xs font-medium rounded-lg transition-colors"
+                >
+                    Save bridges
+                </button>
+                <button
+                    onClick={checkBootstrap}
+                    className="px-3 py-2 bg-slate-700 hover:bg-slate-600 text-slate-200 text-xs font-medium rounded-lg transition-colors"
+                >
+                    Bootstrap status
+                </button>
+                {bootstrap && (
+                    <span className={clsx("text-xs truncate", bootstrap.warning ? "text-amber-400" : "text-slate-400")}>
+                        {bootstrap.progress}% {bootstrap.summary}{bootstrap.hint ? ` - ${bootstrap.hint}` : ""}
+                    </span>
+                )}
+            </div>
+        </div>
+    );
+}
@@ -0,0 +1,125 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Layers, Play, Trash2 } from "lucide-react";
+
+interface IsolatedApp {
+    name: string;
+    command: string;
+    port: number;
+}
+
+interface IsolationSettings {
+    apps: IsolatedApp[];
+}
+
+interface AppCircuits {
+    app: string;
+    circuits: string[];
+}
+
+interface IsolationPanelProps {
+    onLog: (msg: string) => void;
+}
+
+export function IsolationPanel({ onLog }: IsolationPanelProps) {
+    const [apps, setApps] = useState<IsolatedApp[]>([]);
+    const [held, setHeld] = useState<AppCircuits[]>([]);
+    const [draft, setDraft] = useState<IsolatedApp>({ name: "", command: "", port: 0 });
+
+    useEffect(() => {
+        invoke<IsolationSettings>("get_isolation_settings")
+            .then(s => setApps(s.apps))
+            .catch(e => onLog(`[ERR] Isolation settings: ${e}`));
+
+        const refresh = () => invoke<AppCircuits[]>("get_isolated_circuits").then(setHeld).catch(() => setHeld([]));
+        refresh();
+        const interval = setInterval(refresh, 5000);
+        return () => clearInterval(interval);
+    }, []);
+
+    const save = async (next: IsolatedApp[]): Promise<boolean> => {
+        try {
+            const saved = await invoke<IsolationSettings>("set_isolation_settings", { settings: { apps: next } });
+            setApps(saved.apps);
+            onLog(`[OK] Isolation settings saved`);
+            return true;
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+            return false;
+        }
+    };
+
+    const launch = async (name: string) => {
+        try {
+            onLog(`[OK] ${await invoke<string>("launch_isolated", { name })}`);
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center space-x-3 mb-4">
+                <div className="p-2 bg-violet-500/10 rounded-lg">
+                    <Layers className="w-5 h-5 text-violet-400" />
+                </div>
+                <div>
+                    <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">Stream Isolation</h3>
+                    <p className="text-xs text-slate-500">Apps launched on their own SocksPort never share circuits</p>
+                </div>
+            </div>
+
+            <ul className="space-y-1 mb-3 text-xs">
+                {apps.length === 0 && <li className="text-slate-500">No isolated apps</li>}
+                {apps.map(app => {
+                    const circuits = held.find(h => h.app === app.name)?.circuits ?? [];
+                    return (
+                        <li key={app.name} className="flex items-center bg-slate-900/50 rounded-lg px-3 py-2 gap-3">
+                            <span className="font-mono text-slate-200">{app.name}</span>
+                            <span className="text-slate-500">:{app.port}</span>
+                            <span className="flex-1 text-slate-500 truncate">
+                                {circuits.length > 0 ? `circuits ${circuits.join(", ")}` : "no circuits"}
+                            </span>
+                            <button onClick={() => launch(app.name)} className="text-slate-400 hover:text-violet-400">
+                                <Play className="w-4 h-4" />
+                            </button>
+                            <button
+                                onClick={() => save(apps.filter(a => a.name !== app.name))}
+                                className="text-slate-500 hover:text-red-400"
+                            >
+                                <Trash2 className="w-4 h-4" />
+                            </button>
+                        </li>
+                    );
+                })}
+            </ul>
+
+            <form
+                onSubmit={(e) => {
+                    e.preventDefault();
+                    save([...apps, draft]).then(ok => ok && setDraft({ name: "", command: "", port: 0 }));
+                }}
+                className="flex gap-2 items-center"
+            >
+                <input
+                    placeholder="Name"
+                    value={draft.name}
+                    onChange={e => setDraft({ ...draft, name: e.target.value })}
+                    className="w-28 bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-violet-500"
+                />
+                <input
+                    placeholder="Command, e.g. hexchat"
+                    value={draft.command}
+                    onChange={e => setDraft({ ...draft, command: e.target.value })}
+                    className="flex-1 bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-violet-500"
+                />
+                <button
+                    type="submit"
+                    className="px-4 py-2 bg-slate-700 hover:bg-violet-600 text-white text-sm font-medium rounded-lg transition-colors"
+                >
+                    Add
+                </button>
+            </form>
+        </div>
+    );
+}
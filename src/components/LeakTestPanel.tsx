@@ -0,0 +1,164 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { ShieldCheck, CheckCircle2, XCircle, MinusCircle } from "lucide-react";
+import { clsx } from 'clsx';
+
+interface LeakCheck {
+    name: string;
+    status: "pass" | "fail" | "skipped";
+    detail: string;
+}
+
+interface LeakReport {
+    checks: LeakCheck[];
+    passed: boolean;
+}
+
+interface LeakMonitorSettings {
+    verify_rules: boolean;
+    interval_secs: number;
+    packet_watch: boolean;
+}
+
+interface LeakTestSettings {
+    dns_server: string;
+    dns_probe_name: string;
+    tcp_target: string;
+    udp_target: string;
+    ipv6_target: string;
+    record_real_ip: boolean;
+}
+
+interface LeakTestPanelProps {
+    isActive: boolean;
+    onLog: (msg: string) => void;
+}
+
+const statusIcon = {
+    pass: <CheckCircle2 className="w-4 h-4 text-emerald-400" />,
+    fail: <XCircle className="w-4 h-4 text-red-400" />,
+    skipped: <MinusCircle className="w-4 h-4 text-slate-500" />,
+};
+
+export function LeakTestPanel({ isActive, onLog }: LeakTestPanelProps) {
+    const [report, setReport] = useState<LeakReport | null>(null);
+    const [running, setRunning] = useState(false);
+    const [monitor, setMonitor] = useState<LeakMonitorSettings | null>(null);
+    const [settings, setSettings] = useState<LeakTestSettings | null>(null);
+
+    useEffect(() => {
+        invoke<LeakMonitorSettings>("get_leak_monitor_settings")
+            .then(setMonitor)
+            .catch(e => onLog(`[ERR] Leak monitor: ${e}`));
+        invoke<LeakTestSettings>("get_leak_test_settings")
+            .then(setSettings)
+            .catch(e => onLog(`[ERR] Leak test settings: ${e}`));
+    }, []);
+
+    const saveMonitor = async (settings: LeakMonitorSettings) => {
+        try {
+            onLog(`[OK] ${await invoke<string>("set_leak_monitor_settings", { settings })}`);
+            setMonitor(settings);
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    const saveSettings = async (next: LeakTestSettings) => {
+        try {
+            onLog(`[OK] ${await invoke<string>("set_leak_test_settings", { settings: next })}`);
+            setSettings(next);
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    const run = async () => {
+        setRunning(true);
+        try {
+            const result = await invoke<LeakReport>("run_leak_test");
+            setReport(result);
+            result.checks
+                .filter(c => c.status === "fail")
+                .forEach(c => onLog(`[ERR] Leak test: ${c.name}: ${c.detail}`));
+            if (result.passed) {
+                onLog("[OK] Leak test passed");
+            }
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+        setRunning(false);
+    };
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center justify-between mb-4">
+                <div className="flex items-center space-x-3">
+                    <div className="p-2 bg-emerald-500/10 rounded-lg">
+                        <ShieldCheck className="w-5 h-5 text-emerald-400" />
+                    </div>
+                    <div>
+                        <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">Leak Test</h3>
+                        <p className="text-xs text-slate-500">Checks DNS, TCP, UDP, IPv6 and the exit IP</p>
+                    </div>
+                </div>
+                <button
+                    onClick={run}
+                    disabled={running || !isActive}
+                    className={clsx(
+                        "px-4 py-2 bg-slate-700 hover:bg-emerald-600 text-white text-sm font-medium rounded-lg transition-colors",
+                        (running || !isActive) && "opacity-50 cursor-not-allowed"
+                    )}
+                >
+                    {running ? "Testing..." : "Run"}
+                </button>
+            </div>
+
+            {monitor && (
+                <div className="flex gap-4 mb-4 text-xs text-slate-400">
+                    <label className="flex items-center gap-2">
+                        <input
+                            type="checkbox"
+                            checked={monitor.verify_rules}
+                            onChange={e => saveMonitor({ ...monitor, verify_rules: e.target.checked })}
+                        />
+                        Verify firewall rules
+                    </label>
+                    <label className="flex items-center gap-2">
+                        <input
+                            type="checkbox"
+                            checked={monitor.packet_watch}
+                            onChange={e => saveMonitor({ ...monitor, packet_watch: e.target.checked })}
+                        />
+                        Watch egress packets
+                    </label>
+                    {settings && (
+                        <label
+                            className="flex items-center gap-2"
+                            title="Asks the echo service in the clear each time the shield starts"
+                        >
+                            <input
+                                type="checkbox"
+                                checked={settings.record_real_ip}
+                                onChange={e => saveSettings({ ...settings, record_real_ip: e.target.checked })}
+                            />
+                            Record real IP at start
+                        </label>
+                    )}
+                </div>
+            )}
+
+            {report && (
+                <ul className="space-y-1 text-xs">
+                    {report.checks.map(c => (
+                        <li key={c.name} className="flex items-center bg-slate-900/50 rounded-lg px-3 py-2 gap-3">
+                            {statusIcon[c.status]}
+                            <span className="w-32 text-slate-200">{c.name}</span>
+                            <span className="flex-1 text-slate-500">{c.detail}</span>
+                        </li>
+                    ))}
+                </ul>
+            )}
+        </div>
+    );
+}
@@ -0,0 +1,90 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Box } from "lucide-react";
+import { clsx } from 'clsx';
+
+interface NamespaceStatus {
+    up: boolean;
+    name: string;
+    host_addr: string;
+    ns_addr: string;
+}
+
+interface NamespacePanelProps {
+    onLog: (msg: string) => void;
+}
+
+export function NamespacePanel({ onLog }: NamespacePanelProps) {
+    const [status, setStatus] = useState<NamespaceStatus | null>(null);
+    const [cmd, setCmd] = useState("");
+    const [busy, setBusy] = useState(false);
+
+    const refresh = () => invoke<NamespaceStatus>("get_tor_namespace").then(setStatus).catch(() => {});
+
+    useEffect(() => {
+        refresh();
+    }, []);
+
+    const run = async (command: string, args: Record<string, unknown> = {}) => {
+        setBusy(true);
+        try {
+            onLog(`[OK] ${await invoke<string>(command, args)}`);
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+        setBusy(false);
+        refresh();
+    };
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center justify-between mb-4">
+                <div className="flex items-center space-x-3">
+                    <div className="p-2 bg-teal-500/10 rounded-lg">
+                        <Box className="w-5 h-5 text-teal-400" />
+                    </div>
+                    <div>
+                        <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">Tor Namespace</h3>
+                        <p className="text-xs text-slate-500">
+                            {status?.up
+                                ? `Namespace "${status.name}" up (${status.ns_addr} → ${status.host_addr})`
+                                : "Run programs through Tor without touching the host network"}
+                        </p>
+                    </div>
+                </div>
+                <button
+                    disabled={busy}
+                    onClick={() => run(status?.up ? "stop_tor_namespace" : "start_tor_namespace")}
+                    className={clsx(
+                        "px-4 py-2 text-sm font-medium rounded-lg transition-colors",
+                        status?.up ? "bg-teal-600 hover:bg-teal-500 text-white" : "bg-slate-700 hover:bg-slate-600 text-slate-200"
+                    )}
+                >
+                    {status?.up ? "Up" : "Down"}
+                </button>
+            </div>
+
+            <form
+                onSubmit={(e) => {
+                    e.preventDefault();
+                    if (cmd.trim()) run("launch_in_tor_namespace", { cmd });
+                }}
+                className="flex gap-2"
+            >
+                <input
+                    placeholder="Command, e.g. firefox --no-remote"
+                    value={cmd}
+                    onChange={e => setCmd(e.target.value)}
+                    className="flex-1 bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-teal-500"
+                />
+                <button
+                    type="submit"
+                    disabled={busy}
+                    className="px-4 py-2 bg-slate-700 hover:bg-teal-600 text-white text-sm font-medium rounded-lg transition-colors"
+                >
+                    Launch
+                </button>
+            </form>
+        </div>
+    );
+}
@@ -0,0 +1,129 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Globe, Trash2 } from "lucide-react";
+
+interface OnionPort {
+    virtual_port: number;
+    target_port: number;
+}
+
+interface OnionService {
+    service_id: string;
+    ports: OnionPort[];
+    persistent: boolean;
+    clients: { name: string; public_key: string }[];
+}
+
+interface CreatedOnion {
+    service: OnionService;
+    address: string;
+    client_keys: { name: string; auth_line: string }[];
+}
+
+interface OnionPanelProps {
+    onLog: (msg: string) => void;
+}
+
+export function OnionPanel({ onLog }: OnionPanelProps) {
+    const [services, setServices] = useState<OnionService[]>([]);
+    const [virtualPort, setVirtualPort] = useState(80);
+    const [targetPort, setTargetPort] = useState(8080);
+    const [persistent, setPersistent] = useState(false);
+    const [clients, setClients] = useState("");
+    const [created, setCreated] = useState<CreatedOnion | null>(null);
+
+    const refresh = () => invoke<OnionService[]>("list_onions").then(setServices).catch(() => setServices([]));
+
+    useEffect(() => {
+        refresh();
+    }, []);
+
+    const create = async () => {
+        try {
+            const result = await invoke<CreatedOnion>("create_onion", {
+                request: {
+                    ports: [{ virtual_port: virtualPort, target_port: targetPort }],
+                    persistent,
+                    clients: clients.split(",").map(c => c.trim()).filter(c => c),
+                },
+            });
+            setCreated(result);
+            onLog(`[OK] Onion service ${result.address} published`);
+            refresh();
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    const remove = async (id: string) => {
+        try {
+            onLog(`[OK] ${await invoke<string>("delete_onion", { serviceId: id })}`);
+            refresh();
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    const inputClass = "bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-fuchsia-500";
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center space-x-3 mb-4">
+                <div className="p-2 bg-fuchsia-500/10 rounded-lg">
+                    <Globe className="w-5 h-5 text-fuchsia-400" />
+                </div>
+                <div>
+                    <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">Onion Services</h3>
+                    <p className="text-xs text-slate-500">Publish a local service on a .onion address</p>
+                </div>
+            </div>
+
+            <ul className="space-y-1 mb-3 text-xs">
+                {services.length === 0 && <li className="text-slate-500">No onion services running</li>}
+                {services.map(s => (
+                    <li key={s.service_id} className="flex items-center bg-slate-900/50 rounded-lg px-3 py-2 gap-3">
+                        <span className="font-mono text-slate-200 truncate">{s.service_id}.onion</span>
+                        <span className="flex-1 text-slate-500">
+                            {s.ports.map(p => `${p.virtual_port}→${p.target_port}`).join(", ")}
+                            {s.persistent && " · persistent"}
+                            {s.clients.length > 0 && ` · ${s.clients.length} client(s)`}
+                        </span>
+                        <button onClick={() => remove(s.service_id)} className="text-slate-500 hover:text-red-400">
+                            <Trash2 className="w-4 h-4" />
+                        </button>
+                    </li>
+                ))}
+            </ul>
+
+            <div className="flex gap-2 items-center text-xs text-slate-400">
+                <span>Port</span>
+                <input type="number" min={1} max={65535} value={virtualPort}
+                    onChange={e => setVirtualPort(Number(e.target.value))} className={`w-20 ${inputClass}`} />
+                <span>→ 127.0.0.1:</span>
+                <input type="number" min={1} max={65535} value={targetPort}
+                    onChange={e => setTargetPort(Number(e.target.value))} className={`w-20 ${inputClass}`} />
+                <input placeholder="Clients (comma separated)" value={clients}
+                    onChange={e => setClients(e.target.value)} className={`flex-1 ${inputClass}`} />
+                <label className="flex items-center gap-1">
+                    <input type="checkbox" checked={persistent} onChange={e => setPersistent(e.target.checked)} />
+                    Keep key
+                </label>
+                <button
+                    onClick={create}
+                    className="px-4 py-2 bg-slate-700 hover:bg-fuchsia-600 text-white text-sm font-medium rounded-lg transition-colors"
+                >
+                    Publish
+                </button>
+            </div>
+
+            {created && created.client_keys.length > 0 && (
+                <div className="mt-3 text-xs">
+                    <p className="text-amber-400 mb-1">Client keys are shown only once:</p>
+                    <pre className="bg-slate-900/70 rounded-lg p-3 text-[11px] text-slate-300 font-mono overflow-auto">
+                        {created.client_keys.map(k => `# ${k.name}\n${k.auth_line}`).join("\n")}
+                    </pre>
+                </div>
+            )}
+        </div>
+    );
+}
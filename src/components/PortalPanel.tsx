@@ -0,0 +1,86 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { Wifi } from "lucide-react";
+import { clsx } from 'clsx';
+
+interface PortalProbe {
+    portal: boolean;
+    status: number | null;
+    location: string | null;
+}
+
+interface PortalSession {
+    uid: number;
+    browser_pid: number | null;
+    opened_secs_ago: number;
+}
+
+interface PortalPanelProps {
+    isActive: boolean;
+    onLog: (msg: string) => void;
+}
+
+export function PortalPanel({ isActive, onLog }: PortalPanelProps) {
+    const [session, setSession] = useState<PortalSession | null>(null);
+    const [busy, setBusy] = useState(false);
+
+    const refresh = () => invoke<PortalSession | null>("get_captive_portal").then(setSession).catch(() => {});
+
+    useEffect(() => {
+        refresh();
+        const unlisten = listen('captive_portal_closed', () => setSession(null));
+        return () => {
+            unlisten.then(f => f());
+        };
+    }, []);
+
+    const toggle = async () => {
+        setBusy(true);
+        try {
+            if (session) {
+                onLog(`[OK] ${await invoke<string>("close_captive_portal")}`);
+            } else {
+                const probe = await invoke<PortalProbe>("open_captive_portal");
+                onLog(probe.portal
+                    ? `[WARN] Captive portal detected (HTTP ${probe.status}), opening portal browser`
+                    : "[OK] No captive portal detected, exception closed");
+            }
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+        setBusy(false);
+        refresh();
+    };
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center justify-between">
+                <div className="flex items-center space-x-3">
+                    <div className="p-2 bg-orange-500/10 rounded-lg">
+                        <Wifi className="w-5 h-5 text-orange-400" />
+                    </div>
+                    <div>
+                        <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">Captive Portal</h3>
+                        <p className="text-xs text-slate-500">
+                            {session
+                                ? "Portal browser has direct access until Tor bootstraps"
+                                : "Log in to hotel or airport Wi-Fi without dropping the shield"}
+                        </p>
+                    </div>
+                </div>
+                <button
+                    onClick={toggle}
+                    disabled={busy || (!isActive && !session)}
+                    className={clsx(
+                        "px-4 py-2 text-sm font-medium rounded-lg transition-colors",
+                        session ? "bg-orange-600 hover:bg-orange-500 text-white" : "bg-slate-700 hover:bg-slate-600 text-slate-200",
+                        !isActive && !session && "opacity-50 cursor-not-allowed"
+                    )}
+                >
+                    {session ? "Close" : "Open"}
+                </button>
+            </div>
+        </div>
+    );
+}
@@ -0,0 +1,174 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Wifi, Trash2 } from "lucide-react";
+import { clsx } from 'clsx';
+
+interface ProfileActions {
+    spoof_mac: boolean;
+    randomize_hostname: boolean;
+    start_shield: boolean;
+}
+
+interface NetworkProfile {
+    name: string;
+    ssid: string | null;
+    bssid: string | null;
+    gateway_mac: string | null;
+    actions: ProfileActions;
+}
+
+interface NetworkProfiles {
+    enabled: boolean;
+    profiles: NetworkProfile[];
+    unknown: ProfileActions;
+}
+
+interface NetworkIdentity {
+    interface: string;
+    ssid: string | null;
+    bssid: string | null;
+    gateway: string | null;
+    gateway_mac: string | null;
+}
+
+interface ProfilesPanelProps {
+    onLog: (msg: string) => void;
+}
+
+const noActions: ProfileActions = { spoof_mac: false, randomize_hostname: false, start_shield: false };
+
+const actionLabels: [keyof ProfileActions, string][] = [
+    ["spoof_mac", "Spoof MAC"],
+    ["randomize_hostname", "Random hostname"],
+    ["start_shield", "Start shield"],
+];
+
+function describeActions(actions: ProfileActions): string {
+    const enabled = actionLabels.filter(([key]) => actions[key]).map(([, label]) => label);
+    return enabled.length > 0 ? enabled.join(", ") : "Trusted, do nothing";
+}
+
+export function ProfilesPanel({ onLog }: ProfilesPanelProps) {
+    const [settings, setSettings] = useState<NetworkProfiles | null>(null);
+    const [current, setCurrent] = useState<NetworkIdentity | null>(null);
+    const [name, setName] = useState("");
+
+    useEffect(() => {
+        invoke<NetworkProfiles>("get_network_profiles")
+            .then(setSettings)
+            .catch(e => onLog(`[ERR] Network profiles: ${e}`));
+        invoke<NetworkIdentity | null>("get_current_network").then(setCurrent).catch(() => {});
+    }, []);
+
+    const save = async (next: NetworkProfiles): Promise<boolean> => {
+        try {
+            onLog(`[OK] ${await invoke<string>("set_network_profiles", { profiles: next })}`);
+            setSettings(next);
+            return true;
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+            return false;
+        }
+    };
+
+    if (!settings) return null;
+
+    const trustCurrent = () => {
+        if (!current || !name.trim()) return;
+        const profile: NetworkProfile = {
+            name: name.trim(),
+            ssid: current.ssid,
+            bssid: null,
+            // Wired networks have no SSID to go by
+            gateway_mac: current.ssid ? null : current.gateway_mac,
+            actions: noActions,
+        };
+        save({ ...settings, profiles: [...settings.profiles, profile] }).then(ok => ok && setName(""));
+    };
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center justify-between mb-4">
+                <div className="flex items-center space-x-3">
+                    <div className="p-2 bg-sky-500/10 rounded-lg">
+                        <Wifi className="w-5 h-5 text-sky-400" />
+                    </div>
+                    <div>
+                        <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">Network Profiles</h3>
+                        <p className="text-xs text-slate-500">Protect automatically on networks you don't trust</p>
+                    </div>
+                </div>
+                <button
+                    onClick={() => save({ ...settings, enabled: !settings.enabled })}
+                    className={clsx(
+                        "px-4 py-2 text-sm font-medium rounded-lg transition-colors",
+                        settings.enabled ? "bg-sky-600 hover:bg-sky-500 text-white" : "bg-slate-700 hover:bg-slate-600 text-slate-200"
+                    )}
+                >
+                    {settings.enabled ? "On" : "Off"}
+                </button>
+            </div>
+
+            <div className="mb-3 text-xs">
+                <p className="text-slate-400 mb-1">On unknown networks</p>
+                <div className="flex gap-4 text-slate-400">
+                    {actionLabels.map(([key, label]) => (
+                        <label key={key} className="flex items-center gap-2">
+                            <input
+                                type="checkbox"
+                                checked={settings.unknown[key]}
+                                onChange={e => save({ ...settings, unknown: { ...settings.unknown, [key]: e.target.checked } })}
+                            />
+                            {label}
+                        </label>
+                    ))}
+                </div>
+            </div>
+
+            <ul className="space-y-1 mb-3 text-xs">
+                {settings.profiles.length === 0 && <li className="text-slate-500">No known networks</li>}
+                {settings.profiles.map((p, i) => (
+                    <li key={p.name} className="flex items-center bg-slate-900/50 rounded-lg px-3 py-2 gap-3">
+                        <span className="text-slate-200">{p.name}</span>
+                        <span className="font-mono text-slate-500 truncate">
+                            {[p.ssid, p.bssid, p.gateway_mac].filter(Boolean).join(" / ")}
+                        </span>
+                        <span className="flex-1 text-right text-slate-400">{describeActions(p.actions)}</span>
+                        <button
+                            onClick={() => save({ ...settings, profiles: settings.profiles.filter((_, j) => j !== i) })}
+                            className="text-slate-500 hover:text-red-400"
+                        >
+                            <Trash2 className="w-4 h-4" />
+                        </button>
+                    </li>
+                ))}
+            </ul>
+
+            {current && (
+                <form
+                    onSubmit={(e) => {
+                        e.preventDefault();
+                        trustCurrent();
+                    }}
+                    className="flex gap-2 items-center"
+                >
+                    <span className="text-xs text-slate-500 truncate">
+                        {current.ssid ?? current.gateway_mac ?? current.interface}
+                    </span>
+                    <input
+                        placeholder="Home"
+                        value={name}
+                        onChange={e => setName(e.target.value)}
+                        className="flex-1 bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-sky-500"
+                    />
+                    <button
+                        type="submit"
+                        className="px-3 py-2 bg-slate-700 hover:bg-slate-600 text-slate-200 text-xs font-medium rounded-lg transition-colors"
+                    >
+                        Trust this network
+                    </button>
+                </form>
+            )}
+        </div>
+    );
+}
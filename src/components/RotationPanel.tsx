@@ -0,0 +1,91 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Timer } from "lucide-react";
+import { clsx } from 'clsx';
+
+interface RotationSettings {
+    enabled: boolean;
+    interval_minutes: number;
+    after_streams: number;
+}
+
+interface RotationPanelProps {
+    onLog: (msg: string) => void;
+}
+
+export function RotationPanel({ onLog }: RotationPanelProps) {
+    const [settings, setSettings] = useState<RotationSettings>({ enabled: false, interval_minutes: 10, after_streams: 0 });
+
+    useEffect(() => {
+        invoke<RotationSettings>("get_rotation_settings")
+            .then(setSettings)
+            .catch(e => onLog(`[ERR] Rotation settings: ${e}`));
+    }, []);
+
+    const save = async (next: RotationSettings) => {
+        try {
+            await invoke<string>("set_rotation_settings", { settings: next });
+            setSettings(next);
+            onLog(`[OK] Auto rotation ${next.enabled ? "enabled" : "disabled"}`);
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center justify-between mb-4">
+                <div className="flex items-center space-x-3">
+                    <div className="p-2 bg-indigo-500/10 rounded-lg">
+                        <Timer className="w-5 h-5 text-indigo-400" />
+                    </div>
+                    <div>
+                        <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">Automatic Identity Rotation</h3>
+                        <p className="text-xs text-slate-500">Request a new Tor circuit on a schedule</p>
+                    </div>
+                </div>
+                <button
+                    onClick={() => save({ ...settings, enabled: !settings.enabled })}
+                    className={clsx(
+                        "px-4 py-2 text-sm font-medium rounded-lg transition-colors",
+                        settings.enabled ? "bg-indigo-600 hover:bg-indigo-500 text-white" : "bg-slate-700 hover:bg-slate-600 text-slate-200"
+                    )}
+                >
+                    {settings.enabled ? "On" : "Off"}
+                </button>
+            </div>
+
+            <form
+                onSubmit={(e) => {
+                    e.preventDefault();
+                    save(settings);
+                }}
+                className="flex gap-2 items-center text-xs text-slate-400"
+            >
+                <span>Every</span>
+                <input
+                    type="number"
+                    min={0}
+                    value={settings.interval_minutes}
+                    onChange={e => setSettings({ ...settings, interval_minutes: Number(e.target.value) })}
+                    className="w-16 bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-indigo-500"
+                />
+                <span>min or after</span>
+                <input
+                    type="number"
+                    min={0}
+                    value={settings.after_streams}
+                    onChange={e => setSettings({ ...settings, after_streams: Number(e.target.value) })}
+                    className="w-16 bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-indigo-500"
+                />
+                <span className="flex-1">streams (0 = off)</span>
+                <button
+                    type="submit"
+                    className="px-4 py-2 bg-slate-700 hover:bg-indigo-600 text-white text-sm font-medium rounded-lg transition-colors"
+                >
+                    Save
+                </button>
+            </form>
+        </div>
+    );
+}
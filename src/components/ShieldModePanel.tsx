@@ -0,0 +1,111 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Lock } from "lucide-react";
+import { clsx } from 'clsx';
+
+type ShieldMode = "transparent" | "killswitch";
+
+interface ShieldSettings {
+    mode: ShieldMode;
+    socks_ports: string[];
+}
+
+interface ShieldModePanelProps {
+    isActive: boolean;
+    onLog: (msg: string) => void;
+}
+
+const modes: [ShieldMode, string][] = [
+    ["transparent", "Transparent"],
+    ["killswitch", "Killswitch only"],
+];
+
+export function ShieldModePanel({ isActive, onLog }: ShieldModePanelProps) {
+    const [settings, setSettings] = useState<ShieldSettings | null>(null);
+    const [socksInput, setSocksInput] = useState("");
+
+    useEffect(() => {
+        invoke<ShieldSettings>("get_shield_settings")
+            .then(s => {
+                setSettings(s);
+                setSocksInput(s.socks_ports.join(", "));
+            })
+            .catch(e => onLog(`[ERR] Shield settings: ${e}`));
+    }, []);
+
+    const save = async (next: ShieldSettings) => {
+        try {
+            onLog(`[OK] ${await invoke<string>("set_shield_settings", { settings: next })}`);
+            setSettings(next);
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    if (!settings) return null;
+
+    const saveSocks = () => {
+        const socks_ports = socksInput.split(",").map(s => s.trim()).filter(Boolean);
+        save({ ...settings, socks_ports });
+    };
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center justify-between mb-4">
+                <div className="flex items-center space-x-3">
+                    <div className="p-2 bg-indigo-500/10 rounded-lg">
+                        <Lock className="w-5 h-5 text-indigo-400" />
+                    </div>
+                    <div>
+                        <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">Shield Mode</h3>
+                        <p className="text-xs text-slate-500">
+                            {settings.mode === "transparent"
+                                ? "Redirect all TCP and DNS into Tor"
+                                : "Block everything; only SOCKS-aware programs reach Tor"}
+                        </p>
+                    </div>
+                </div>
+                <div className="flex gap-1">
+                    {modes.map(([mode, label]) => (
+                        <button
+                            key={mode}
+                            disabled={isActive}
+                            title={isActive ? "Stop the shield to change its mode" : undefined}
+                            onClick={() => save({ ...settings, mode })}
+                            className={clsx(
+                                "px-3 py-2 text-xs font-medium rounded-lg transition-colors",
+                                settings.mode === mode ? "bg-indigo-600 text-white" : "bg-slate-700 hover:bg-slate-600 text-slate-200",
+                                isActive && "opacity-50 cursor-not-allowed"
+                            )}
+                        >
+                            {label}
+                        </button>
+                    ))}
+                </div>
+            </div>
+
+            {settings.mode === "killswitch" && (
+                <form
+                    onSubmit={(e) => {
+                        e.preventDefault();
+                        saveSocks();
+                    }}
+                    className="flex gap-2"
+                >
+                    <input
+                        placeholder="Extra SocksPorts, e.g. 192.168.1.1:9050"
+                        value={socksInput}
+                        onChange={e => setSocksInput(e.target.value)}
+                        className="flex-1 bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-indigo-500"
+                    />
+                    <button
+                        type="submit"
+                        className="px-3 py-2 bg-slate-700 hover:bg-slate-600 text-slate-200 text-xs font-medium rounded-lg transition-colors"
+                    >
+                        Save
+                    </button>
+                </form>
+            )}
+        </div>
+    );
+}
@@ -1,18 +1,20 @@
-import { Shield, ShieldAlert, Globe, Activity } from "lucide-react";
+import { Shield, ShieldAlert, Globe, Activity, Server } from "lucide-react";
 import { clsx } from 'clsx';
 
 
 interface StatusCardProps {
     active: boolean;
+    degraded?: boolean;
     ip?: string;
     country?: string;
     ipv6?: string;
     mac?: string;
     hostname?: string;
+    resolver?: string;
     loading: boolean;
 }
 
-export function StatusCard({ active, ip, country, ipv6, mac, hostname, loading }: StatusCardProps) {
+export function StatusCard({ active, degraded = false, ip, country, ipv6, mac, hostname, resolver, loading }: StatusCardProps) {
     return (
         <div className="relative w-full">
             {/* Background Glow */}
@@ -37,10 +39,12 @@ export function StatusCard({ active, ip, country, ipv6, mac, hostname, loading }
                                     "text-2xl font-bold tracking-tight block",
                                     active ? "text-white" : "text-slate-300"
                                 )}>
-                                    {active ? "Protected" : "Exposed"}
+                                    {active ? (degraded ? "Degraded" : "Protected") : "Exposed"}
                                 </span>
                                 <span className="text-xs text-slate-500">
-                                    {active ? "Traffic is encrypted via Tor" : "Your real IP is visible"}
+                                    {active
+                                        ? (degraded ? "Tor is down - killswitch is blocking traffic" : "Traffic is encrypted via Tor")
+                                        : "Your real IP is visible"}
                                 </span>
                             </div>
                         </div>
@@ -134,6 +138,18 @@ export function StatusCard({ active, ip, country, ipv6, mac, hostname, loading }
                         </div>
                     </div>
 
+                    <div className="flex items-center space-x-3">
+                        <div className="p-2 bg-slate-800/50 rounded-lg text-slate-400">
+                            <Server className="w-4 h-4" />
+                        </div>
+                        <div>
+                            <div className="text-[10px] uppercase text-slate-500 font-semibold">DNS Resolver</div>
+                            <div className="text-xs font-mono text-slate-300 truncate max-w-[120px]" title={resolver}>
+                                {loading ? "..." : resolver || "Unknown"}
+                            </div>
+                        </div>
+                    </div>
+
                 </div>
             </div>
         </div>
@@ -1,4 +1,4 @@
-import { useState } from 'react';
+import { useEffect, useState } from 'react';
 import { invoke } from '@tauri-apps/api/core';
 import { Fingerprint, Eraser, Ghost, Clock, FileWarning, Check, RotateCcw } from "lucide-react";
 import { clsx } from 'clsx';
@@ -10,6 +10,22 @@ interface StealthPanelProps {
 export function StealthPanel({ onLog }: StealthPanelProps) {
     const [loading, setLoading] = useState(false);
     const [activeModules, setActiveModules] = useState<Record<string, boolean>>({});
+    const [spoofOnLinkUp, setSpoofOnLinkUp] = useState(false);
+
+    useEffect(() => {
+        invoke<{ spoof_mac: boolean }>("get_network_watch_settings")
+            .then(s => setSpoofOnLinkUp(s.spoof_mac))
+            .catch(() => {});
+    }, []);
+
+    const toggleSpoofOnLinkUp = async (enabled: boolean) => {
+        try {
+            onLog(`[OK] ${await invoke<string>("set_network_watch_settings", { settings: { spoof_mac: enabled } })}`);
+            setSpoofOnLinkUp(enabled);
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
 
     const handleAction = async (label: string, command: string, id: string, args: Record<string, unknown> = {}) => {
         setLoading(true);
@@ -63,6 +79,10 @@ export function StealthPanel({ onLog }: StealthPanelProps) {
                 <StealthButton icon={Eraser} label="Wipe RAM" id="ram" command="wipe_ram" color="red" />
                 <StealthButton icon={FileWarning} label="Clean Logs" id="logs" command="clean_logs" color="orange" />
             </div>
+            <label className="flex items-center gap-2 px-1 text-xs text-slate-400">
+                <input type="checkbox" checked={spoofOnLinkUp} onChange={e => toggleSpoofOnLinkUp(e.target.checked)} />
+                Spoof MAC whenever an interface comes up
+            </label>
         </div>
     );
 }
@@ -0,0 +1,120 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Clock } from "lucide-react";
+import { clsx } from 'clsx';
+
+interface TimeSyncSettings {
+    sync_over_tor: boolean;
+    disable_ntp: boolean;
+    sources: string[];
+}
+
+interface TimePanelProps {
+    isActive: boolean;
+    onLog: (msg: string) => void;
+}
+
+export function TimePanel({ isActive, onLog }: TimePanelProps) {
+    const [settings, setSettings] = useState<TimeSyncSettings | null>(null);
+    const [sourcesInput, setSourcesInput] = useState("");
+    const [syncing, setSyncing] = useState(false);
+
+    useEffect(() => {
+        invoke<TimeSyncSettings>("get_time_sync_settings")
+            .then(s => {
+                setSettings(s);
+                setSourcesInput(s.sources.join(", "));
+            })
+            .catch(e => onLog(`[ERR] Time sync settings: ${e}`));
+    }, []);
+
+    const save = async (next: TimeSyncSettings) => {
+        try {
+            onLog(`[OK] ${await invoke<string>("set_time_sync_settings", { settings: next })}`);
+            setSettings(next);
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    const syncNow = async () => {
+        setSyncing(true);
+        onLog("[REQ] Syncing clock over Tor...");
+        try {
+            onLog(`[OK] ${await invoke<string>("sync_time")}`);
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+        setSyncing(false);
+    };
+
+    if (!settings) return null;
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center justify-between mb-4">
+                <div className="flex items-center space-x-3">
+                    <div className="p-2 bg-yellow-500/10 rounded-lg">
+                        <Clock className="w-5 h-5 text-yellow-400" />
+                    </div>
+                    <div>
+                        <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">System Time</h3>
+                        <p className="text-xs text-slate-500">Keep the clock right without NTP leaking outside Tor</p>
+                    </div>
+                </div>
+                <button
+                    onClick={syncNow}
+                    disabled={syncing || !isActive}
+                    title={isActive ? undefined : "Start the shield first"}
+                    className={clsx(
+                        "px-4 py-2 text-sm font-medium rounded-lg transition-colors bg-slate-700 hover:bg-slate-600 text-slate-200",
+                        (syncing || !isActive) && "opacity-50 cursor-not-allowed"
+                    )}
+                >
+                    {syncing ? "Syncing..." : "Sync now"}
+                </button>
+            </div>
+
+            <div className="flex gap-4 mb-3 text-xs text-slate-400">
+                <label className="flex items-center gap-2">
+                    <input
+                        type="checkbox"
+                        checked={settings.disable_ntp}
+                        onChange={e => save({ ...settings, disable_ntp: e.target.checked })}
+                    />
+                    Disable NTP while shielded
+                </label>
+                <label className="flex items-center gap-2">
+                    <input
+                        type="checkbox"
+                        checked={settings.sync_over_tor}
+                        onChange={e => save({ ...settings, sync_over_tor: e.target.checked })}
+                    />
+                    Sync over Tor when the clock is off
+                </label>
+            </div>
+
+            <form
+                onSubmit={(e) => {
+                    e.preventDefault();
+                    const sources = sourcesInput.split(",").map(s => s.trim()).filter(Boolean);
+                    save({ ...settings, sources });
+                }}
+                className="flex gap-2"
+            >
+                <input
+                    placeholder="HTTPS time sources, comma separated"
+                    value={sourcesInput}
+                    onChange={e => setSourcesInput(e.target.value)}
+                    className="flex-1 bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-yellow-500"
+                />
+                <button
+                    type="submit"
+                    className="px-3 py-2 bg-slate-700 hover:bg-slate-600 text-slate-200 text-xs font-medium rounded-lg transition-colors"
+                >
+                    Save
+                </button>
+            </form>
+        </div>
+    );
+}
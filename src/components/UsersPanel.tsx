@@ -0,0 +1,138 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Users, Trash2 } from "lucide-react";
+import { clsx } from 'clsx';
+
+type OwnerKind = "user" | "group";
+
+interface Owner {
+    kind: OwnerKind;
+    name: string;
+}
+
+interface UserRules {
+    exempt: Owner[];
+    forced: Owner[];
+    only_forced: boolean;
+}
+
+interface Account {
+    kind: OwnerKind;
+    name: string;
+    id: number;
+}
+
+interface UsersPanelProps {
+    onLog: (msg: string) => void;
+}
+
+type ListName = "exempt" | "forced";
+
+export function UsersPanel({ onLog }: UsersPanelProps) {
+    const [rules, setRules] = useState<UserRules>({ exempt: [], forced: [], only_forced: false });
+    const [accounts, setAccounts] = useState<Account[]>([]);
+    const [selected, setSelected] = useState("");
+    const [list, setList] = useState<ListName>("exempt");
+
+    useEffect(() => {
+        invoke<UserRules>("get_user_rules")
+            .then(setRules)
+            .catch(e => onLog(`[ERR] User rules: ${e}`));
+        invoke<Account[]>("list_local_accounts").then(setAccounts).catch(() => {});
+    }, []);
+
+    const save = async (next: UserRules) => {
+        try {
+            onLog(`[OK] ${await invoke<string>("set_user_rules", { rules: next })}`);
+            setRules(next);
+        } catch (e) {
+            onLog(`[ERR] ${e}`);
+        }
+    };
+
+    const add = () => {
+        const account = accounts.find(a => `${a.kind}:${a.name}` === selected);
+        if (!account) return;
+        const owner: Owner = { kind: account.kind, name: account.name };
+        if (rules[list].some(o => o.kind === owner.kind && o.name === owner.name)) return;
+        save({ ...rules, [list]: [...rules[list], owner] });
+    };
+
+    const remove = (l: ListName, owner: Owner) =>
+        save({ ...rules, [l]: rules[l].filter(o => o !== owner) });
+
+    return (
+        <div className="glass-card p-6 border-t border-white/5">
+            <div className="flex items-center justify-between mb-4">
+                <div className="flex items-center space-x-3">
+                    <div className="p-2 bg-sky-500/10 rounded-lg">
+                        <Users className="w-5 h-5 text-sky-400" />
+                    </div>
+                    <div>
+                        <h3 className="text-sm font-semibold text-slate-200 uppercase tracking-wider">Per-User Routing</h3>
+                        <p className="text-xs text-slate-500">Local accounts exempted from, or forced through, Tor</p>
+                    </div>
+                </div>
+                <button
+                    onClick={() => save({ ...rules, only_forced: !rules.only_forced })}
+                    className={clsx(
+                        "px-4 py-2 text-xs font-medium rounded-lg transition-colors",
+                        rules.only_forced ? "bg-sky-600 hover:bg-sky-500 text-white" : "bg-slate-700 hover:bg-slate-600 text-slate-200"
+                    )}
+                >
+                    {rules.only_forced ? "Shield forced only" : "Shield everyone"}
+                </button>
+            </div>
+
+            <div className="grid grid-cols-2 gap-3 mb-3 text-xs">
+                {(["exempt", "forced"] as ListName[]).map(l => (
+                    <div key={l}>
+                        <p className="text-slate-400 mb-1">{l === "exempt" ? "Exempt" : "Forced through Tor"}</p>
+                        <ul className="space-y-1">
+                            {rules[l].length === 0 && <li className="text-slate-600">None</li>}
+                            {rules[l].map(owner => (
+                                <li key={`${owner.kind}:${owner.name}`} className="flex items-center justify-between bg-slate-900/50 rounded-lg px-3 py-1.5">
+                                    <span className="font-mono text-slate-200">
+                                        {owner.kind === "group" ? `@${owner.name}` : owner.name}
+                                    </span>
+                                    <button onClick={() => remove(l, owner)} className="text-slate-500 hover:text-red-400">
+                                        <Trash2 className="w-3.5 h-3.5" />
+                                    </button>
+                                </li>
+                            ))}
+                        </ul>
+                    </div>
+                ))}
+            </div>
+
+            <div className="flex gap-2 items-center">
+                <select
+                    value={selected}
+                    onChange={e => setSelected(e.target.value)}
+                    className="flex-1 bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-sky-500"
+                >
+                    <option value="">Select a user or group</option>
+                    {accounts.map(a => (
+                        <option key={`${a.kind}:${a.name}`} value={`${a.kind}:${a.name}`}>
+                            {a.kind === "group" ? `@${a.name}` : a.name} ({a.kind === "group" ? "gid" : "uid"} {a.id})
+                        </option>
+                    ))}
+                </select>
+                <select
+                    value={list}
+                    onChange={e => setList(e.target.value as ListName)}
+                    className="bg-slate-900/50 border border-slate-700 rounded-lg px-2 py-2 text-sm text-slate-200 focus:outline-none focus:border-sky-500"
+                >
+                    <option value="exempt">exempt</option>
+                    <option value="forced">forced</option>
+                </select>
+                <button
+                    onClick={add}
+                    className="px-4 py-2 bg-slate-700 hover:bg-sky-600 text-white text-sm font-medium rounded-lg transition-colors"
+                >
+                    Add
+                </button>
+            </div>
+        </div>
+    );
+}